@@ -1,22 +1,340 @@
+mod xml;
+
 pub mod cfghelper {
 
-    use quick_xml::{de::from_str, events::Event, Reader};
+    use crate::xml::from_str;
+    use quick_xml::{events::Event, Reader};
     use regex::Regex;
-    use serde::{Deserialize, Serialize};
-    use std::{fs, path::Path};
+    use serde::de::{MapAccess, Visitor};
+    use serde::ser::SerializeMap;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::{collections::HashMap, fmt, fs, path::Path};
 
     #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
     pub struct AnnoCfg {
         #[serde(rename = "RenderPropertyFlags", default)]
         render_property_flags: String,
 
+        #[serde(rename = "Center.x", default)]
+        pub center_x: f32,
+        #[serde(rename = "Center.y", default)]
+        pub center_y: f32,
+        #[serde(rename = "Center.z", default)]
+        pub center_z: f32,
+        #[serde(rename = "Extent.x", default)]
+        pub extent_x: f32,
+        #[serde(rename = "Extent.y", default)]
+        pub extent_y: f32,
+        #[serde(rename = "Extent.z", default)]
+        pub extent_z: f32,
+        #[serde(rename = "Radius", default)]
+        pub radius: f32,
+        #[serde(rename = "Mass", default)]
+        pub mass: f32,
+        #[serde(rename = "Drag", default)]
+        pub drag: f32,
+        #[serde(rename = "MeshCenter.x", default)]
+        pub mesh_center_x: f32,
+        #[serde(rename = "MeshCenter.y", default)]
+        pub mesh_center_y: f32,
+        #[serde(rename = "MeshCenter.z", default)]
+        pub mesh_center_z: f32,
+        #[serde(rename = "MeshExtent.x", default)]
+        pub mesh_extent_x: f32,
+        #[serde(rename = "MeshExtent.y", default)]
+        pub mesh_extent_y: f32,
+        #[serde(rename = "MeshExtent.z", default)]
+        pub mesh_extent_z: f32,
+        #[serde(rename = "MeshRadius", default)]
+        pub mesh_radius: f32,
+
         #[serde(rename = "Models", default)]
         pub models: Models,
+
+        #[serde(rename = "Files", skip_serializing_if = "Option::is_none")]
+        pub files: Option<Files>,
+        #[serde(rename = "Dummies", skip_serializing_if = "Option::is_none")]
+        pub dummies: Option<Dummies>,
+        #[serde(rename = "Particles", skip_serializing_if = "Option::is_none")]
+        pub particles: Option<Particles>,
+        #[serde(rename = "Lights", skip_serializing_if = "Option::is_none")]
+        pub lights: Option<Lights>,
+        #[serde(rename = "PropContainers", skip_serializing_if = "Option::is_none")]
+        pub prop_containers: Option<PropContainers>,
+        #[serde(rename = "Decals", skip_serializing_if = "Option::is_none")]
+        pub decals: Option<Decals>,
+
+        /// e.g. `Sequences`
+        #[serde(
+            rename = "$unknown",
+            default,
+            skip_serializing_if = "Unknown::is_empty"
+        )]
+        pub unknown: Unknown,
+    }
+
+    impl AnnoCfg {
+        pub fn center(&self) -> [f32; 3] {
+            [self.center_x, self.center_y, self.center_z]
+        }
+
+        pub fn extent(&self) -> [f32; 3] {
+            [self.extent_x, self.extent_y, self.extent_z]
+        }
+
+        pub fn mesh_center(&self) -> [f32; 3] {
+            [self.mesh_center_x, self.mesh_center_y, self.mesh_center_z]
+        }
+
+        pub fn mesh_extent(&self) -> [f32; 3] {
+            [self.mesh_extent_x, self.mesh_extent_y, self.mesh_extent_z]
+        }
+    }
+
+    /// Children of a cfg element that have no typed field, in document order. Tags can repeat.
+    #[derive(Debug, Clone, PartialEq, Default)]
+    pub struct Unknown(pub Vec<(String, UnknownValue)>);
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum UnknownValue {
+        Text(String),
+        Element(Unknown),
+    }
+
+    impl Unknown {
+        pub fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        /// First child with the tag `name`.
+        pub fn get(&self, name: &str) -> Option<&UnknownValue> {
+            self.0.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+        }
+    }
+
+    impl UnknownValue {
+        pub fn as_text(&self) -> Option<&str> {
+            match self {
+                UnknownValue::Text(text) => Some(text),
+                UnknownValue::Element(_) => None,
+            }
+        }
+
+        pub fn as_element(&self) -> Option<&Unknown> {
+            match self {
+                UnknownValue::Text(_) => None,
+                UnknownValue::Element(element) => Some(element),
+            }
+        }
+    }
+
+    struct UnknownVisitor;
+
+    impl<'de> Visitor<'de> for UnknownVisitor {
+        type Value = UnknownValue;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("text or elements")
+        }
+
+        fn visit_str<E>(self, text: &str) -> Result<UnknownValue, E> {
+            Ok(UnknownValue::Text(text.to_owned()))
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<UnknownValue, A::Error> {
+            let mut children = Vec::new();
+            while let Some(child) = map.next_entry()? {
+                children.push(child);
+            }
+            Ok(UnknownValue::Element(Unknown(children)))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for UnknownValue {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(UnknownVisitor)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Unknown {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            match deserializer.deserialize_map(UnknownVisitor)? {
+                UnknownValue::Element(unknown) => Ok(unknown),
+                UnknownValue::Text(text) => Err(serde::de::Error::invalid_type(
+                    serde::de::Unexpected::Str(&text),
+                    &UnknownVisitor,
+                )),
+            }
+        }
+    }
+
+    impl Serialize for UnknownValue {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self {
+                UnknownValue::Text(text) => serializer.serialize_str(text),
+                UnknownValue::Element(element) => element.serialize(serializer),
+            }
+        }
+    }
+
+    impl Serialize for Unknown {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(self.0.len()))?;
+            for (name, value) in &self.0 {
+                map.serialize_entry(name, value)?;
+            }
+            map.end()
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Files {
+        #[serde(rename = "$value", default)]
+        pub files_vec: Vec<File>,
+    }
+
+    /// A sub-cfg (or other file) placed by the cfg, e.g. a flag on a ship.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct File {
+        #[serde(rename = "Transformer", skip_serializing_if = "Option::is_none")]
+        pub transformer: Option<Transformer>,
+
+        #[serde(rename = "Name", default)]
+        pub name: String,
+        #[serde(rename = "FileName", default)]
+        pub file_name: String,
+        #[serde(rename = "AdaptTerrainHeight", skip_serializing_if = "Option::is_none")]
+        pub adapt_terrain_height: Option<bool>,
+
+        #[serde(
+            rename = "$unknown",
+            default,
+            skip_serializing_if = "Unknown::is_empty"
+        )]
+        pub unknown: Unknown,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Transformer {
+        #[serde(rename = "$value", default)]
+        pub transforms: Vec<Transform>,
+    }
+
+    /// Entry of a `Transformer` chain, the fields used depend on the `ConfigType`:
+    /// `ORIENTATION_TRANSFORM`s have a position, rotation and scale, `VISIBILITY_TRANSFORM`s
+    /// an animation and `COLOR_TRANSFORM`s color `State`s.
+    #[allow(non_snake_case)]
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Transform {
+        #[serde(rename = "ConfigType", default)]
+        pub config_type: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub Conditions: Option<u32>,
+        #[serde(rename = "Position.x", skip_serializing_if = "Option::is_none")]
+        pub Position_x: Option<f32>,
+        #[serde(rename = "Position.y", skip_serializing_if = "Option::is_none")]
+        pub Position_y: Option<f32>,
+        #[serde(rename = "Position.z", skip_serializing_if = "Option::is_none")]
+        pub Position_z: Option<f32>,
+        #[serde(rename = "Rotation.x", skip_serializing_if = "Option::is_none")]
+        pub Rotation_x: Option<f32>,
+        #[serde(rename = "Rotation.y", skip_serializing_if = "Option::is_none")]
+        pub Rotation_y: Option<f32>,
+        #[serde(rename = "Rotation.z", skip_serializing_if = "Option::is_none")]
+        pub Rotation_z: Option<f32>,
+        #[serde(rename = "Rotation.w", skip_serializing_if = "Option::is_none")]
+        pub Rotation_w: Option<f32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub Scale: Option<f32>,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub AnimationTime: Option<f32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub AnimationMode: Option<u32>,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ApplyAlways: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub StartInvisible: Option<bool>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub State: Vec<ColorState>,
+
+        #[serde(
+            rename = "$unknown",
+            default,
+            skip_serializing_if = "Unknown::is_empty"
+        )]
+        pub unknown: Unknown,
+    }
+
+    /// Key of a `COLOR_TRANSFORM`.
+    #[allow(non_snake_case)]
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct ColorState {
+        #[serde(rename = "Color.r", default)]
+        pub Color_r: f32,
+        #[serde(rename = "Color.g", default)]
+        pub Color_g: f32,
+        #[serde(rename = "Color.b", default)]
+        pub Color_b: f32,
+        #[serde(rename = "Color.a", default)]
+        pub Color_a: f32,
+        #[serde(default)]
+        pub Time: f32,
+        #[serde(default)]
+        pub Condition: u32,
+        #[serde(default)]
+        pub Multiply: bool,
+
+        #[serde(
+            rename = "$unknown",
+            default,
+            skip_serializing_if = "Unknown::is_empty"
+        )]
+        pub unknown: Unknown,
+    }
+
+    impl Transformer {
+        /// The `ORIENTATION_TRANSFORM`s of the chain, in order.
+        pub fn orientations(&self) -> impl Iterator<Item = &Transform> {
+            self.transforms.iter().filter(|t| t.is_orientation())
+        }
+    }
+
+    impl Transform {
+        /// Transforms that were not parsed from a cfg have no `ConfigType`, for them any
+        /// position, rotation or scale counts.
+        pub fn is_orientation(&self) -> bool {
+            match self.config_type.as_str() {
+                "" => {
+                    self.Position_x.is_some() || self.Rotation_w.is_some() || self.Scale.is_some()
+                }
+                config_type => config_type == "ORIENTATION_TRANSFORM",
+            }
+        }
+
+        pub fn position(&self) -> [f32; 3] {
+            [self.Position_x, self.Position_y, self.Position_z].map(|v| v.unwrap_or(0.0))
+        }
+
+        /// Quaternion x, y, z, w.
+        pub fn rotation(&self) -> [f32; 4] {
+            [
+                self.Rotation_x.unwrap_or(0.0),
+                self.Rotation_y.unwrap_or(0.0),
+                self.Rotation_z.unwrap_or(0.0),
+                self.Rotation_w.unwrap_or(1.0),
+            ]
+        }
+
+        pub fn scale(&self) -> f32 {
+            self.Scale.unwrap_or(1.0)
+        }
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
     pub struct Models {
-        #[serde(rename = "$value")]
+        #[serde(rename = "$value", default)]
         pub models_vec: Vec<Model>,
     }
 
@@ -28,42 +346,68 @@ pub mod cfghelper {
         #[serde(rename = "Animations")]
         animations: Option<Animations>,
 
+        #[serde(rename = "Name", default)]
+        pub name: String,
         #[serde(rename = "FileName", default)]
         pub file_name: String,
         #[serde(rename = "IgnoreRuinState", default)]
         ignore_ruin_state: Option<bool>,
+
+        #[serde(rename = "Transformer", skip_serializing_if = "Option::is_none")]
+        pub transformer: Option<Transformer>,
+
+        #[serde(
+            rename = "$unknown",
+            default,
+            skip_serializing_if = "Unknown::is_empty"
+        )]
+        pub unknown: Unknown,
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
     pub struct Materials {
-        #[serde(rename = "$value")]
+        #[serde(rename = "$value", default)]
         pub materials_vec: Vec<Material>,
     }
 
+    impl Model {
+        /// Material name -> material index of this model. Unnamed materials are skipped, for repeated
+        /// names the first index is kept.
+        pub fn material_map(&self) -> HashMap<String, u32> {
+            let mut map = HashMap::new();
+            for (idx, material) in self.materials.materials_vec.iter().enumerate() {
+                if !material.Name.is_empty() {
+                    map.entry(material.Name.clone()).or_insert(idx as u32);
+                }
+            }
+            map
+        }
+    }
+
     #[allow(non_snake_case)]
     #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
     pub struct Material {
-        Name: String,
+        pub Name: String,
         ShaderID: u32,
         pub VertexFormat: String,
         NumBonesPerVertex: u32,
-        METALLIC_TEX_ENABLED: Option<bool>,
-        cModelMetallicTex: Option<String>,
+        pub METALLIC_TEX_ENABLED: Option<bool>,
+        pub cModelMetallicTex: Option<String>,
         cUseTerrainTinting: Option<String>,
         SEPARATE_AO_TEXTURE: Option<String>,
         cSeparateAOTex: Option<String>,
         Common: Option<String>,
-        DIFFUSE_ENABLED: Option<bool>,
+        pub DIFFUSE_ENABLED: Option<bool>,
         pub cModelDiffTex: String,
-        NORMAL_ENABLED: Option<bool>,
-        cModelNormalTex: Option<String>,
+        pub NORMAL_ENABLED: Option<bool>,
+        pub cModelNormalTex: Option<String>,
         #[serde(rename = "cDiffuseColor.r")]
-        cDiffuseColor_r: f32,
+        pub cDiffuseColor_r: f32,
         #[serde(rename = "cDiffuseColor.g")]
-        cDiffuseColor_g: f32,
+        pub cDiffuseColor_g: f32,
         #[serde(rename = "cDiffuseColor.b")]
-        cDiffuseColor_b: f32,
-        ALPHA_BLEND_ENABLED: Option<bool>,
+        pub cDiffuseColor_b: f32,
+        pub ALPHA_BLEND_ENABLED: Option<bool>,
         cTexScrollSpeed: String,
         DYE_MASK_ENABLED: Option<bool>,
         WATER_CUTOUT_ENABLED: Option<bool>,
@@ -82,14 +426,22 @@ pub mod cfghelper {
         #[serde(rename = "cEnvironmentBoundingBox.w")]
         cEnvironmentBoundingBox_w: f32,
         Glow: String,
-        GLOW_ENABLED: Option<bool>,
+        pub GLOW_ENABLED: Option<bool>,
         #[serde(rename = "cEmissiveColor.r")]
-        cEmissiveColor_r: f32,
+        pub cEmissiveColor_r: f32,
         #[serde(rename = "cEmissiveColor.g")]
-        cEmissiveColor_g: f32,
+        pub cEmissiveColor_g: f32,
         #[serde(rename = "cEmissiveColor.b")]
-        cEmissiveColor_b: f32,
+        pub cEmissiveColor_b: f32,
         NIGHT_GLOW_ENABLED: Option<bool>,
+
+        /// e.g. `cDyeMask`
+        #[serde(
+            rename = "$unknown",
+            default,
+            skip_serializing_if = "Unknown::is_empty"
+        )]
+        pub unknown: Unknown,
     }
 
     #[allow(non_snake_case)]
@@ -105,6 +457,212 @@ pub mod cfghelper {
         FileName: String,
         LoopCount: u32,
         Scale: f32,
+
+        #[serde(
+            rename = "$unknown",
+            default,
+            skip_serializing_if = "Unknown::is_empty"
+        )]
+        pub unknown: Unknown,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Dummies {
+        #[serde(rename = "$value", default)]
+        pub dummies_vec: Vec<Dummy>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Dummy {
+        #[serde(rename = "Name", default)]
+        pub name: String,
+        #[serde(rename = "Transformer", skip_serializing_if = "Option::is_none")]
+        pub transformer: Option<Transformer>,
+
+        #[serde(
+            rename = "$unknown",
+            default,
+            skip_serializing_if = "Unknown::is_empty"
+        )]
+        pub unknown: Unknown,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Particles {
+        #[serde(rename = "$value", default)]
+        pub particles_vec: Vec<Particle>,
+    }
+
+    /// A particle effect (`.rdp`) placed by its `Transformer`.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Particle {
+        #[serde(rename = "Transformer", skip_serializing_if = "Option::is_none")]
+        pub transformer: Option<Transformer>,
+
+        #[serde(rename = "Name", default)]
+        pub name: String,
+        #[serde(rename = "FileName", default)]
+        pub file_name: String,
+        #[serde(rename = "TimeScale", skip_serializing_if = "Option::is_none")]
+        pub time_scale: Option<f32>,
+
+        #[serde(
+            rename = "$unknown",
+            default,
+            skip_serializing_if = "Unknown::is_empty"
+        )]
+        pub unknown: Unknown,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Lights {
+        #[serde(rename = "$value", default)]
+        pub lights_vec: Vec<Light>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Light {
+        #[serde(rename = "ConfigType", default)]
+        pub config_type: String,
+        #[serde(rename = "Transformer", skip_serializing_if = "Option::is_none")]
+        pub transformer: Option<Transformer>,
+
+        #[serde(rename = "Name", default)]
+        pub name: String,
+
+        #[serde(
+            rename = "$unknown",
+            default,
+            skip_serializing_if = "Unknown::is_empty"
+        )]
+        pub unknown: Unknown,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct PropContainers {
+        #[serde(rename = "$value", default)]
+        pub prop_containers_vec: Vec<PropContainer>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct PropContainer {
+        #[serde(rename = "Transformer", skip_serializing_if = "Option::is_none")]
+        pub transformer: Option<Transformer>,
+
+        #[serde(rename = "Name", default)]
+        pub name: String,
+        #[serde(rename = "VariationEnabled", skip_serializing_if = "Option::is_none")]
+        pub variation_enabled: Option<bool>,
+        #[serde(
+            rename = "VariationProbability",
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub variation_probability: Option<u32>,
+
+        #[serde(rename = "Props", default)]
+        pub props: Props,
+
+        #[serde(
+            rename = "$unknown",
+            default,
+            skip_serializing_if = "Unknown::is_empty"
+        )]
+        pub unknown: Unknown,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Props {
+        #[serde(rename = "$value", default)]
+        pub props_vec: Vec<Prop>,
+    }
+
+    /// A prop (`.prp`) placed relative to its container.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Prop {
+        #[serde(rename = "FileName", default)]
+        pub file_name: String,
+        #[serde(rename = "Position.x", default)]
+        pub position_x: f32,
+        #[serde(rename = "Position.y", default)]
+        pub position_y: f32,
+        #[serde(rename = "Position.z", default)]
+        pub position_z: f32,
+        #[serde(rename = "Rotation.x", default)]
+        pub rotation_x: f32,
+        #[serde(rename = "Rotation.y", default)]
+        pub rotation_y: f32,
+        #[serde(rename = "Rotation.z", default)]
+        pub rotation_z: f32,
+        #[serde(rename = "Rotation.w", default = "one")]
+        pub rotation_w: f32,
+        #[serde(rename = "Scale.x", default = "one")]
+        pub scale_x: f32,
+        #[serde(rename = "Scale.y", default = "one")]
+        pub scale_y: f32,
+        #[serde(rename = "Scale.z", default = "one")]
+        pub scale_z: f32,
+        #[serde(rename = "Flags", default)]
+        pub flags: u32,
+
+        #[serde(
+            rename = "$unknown",
+            default,
+            skip_serializing_if = "Unknown::is_empty"
+        )]
+        pub unknown: Unknown,
+    }
+
+    fn one() -> f32 {
+        1.0
+    }
+
+    impl Prop {
+        pub fn position(&self) -> [f32; 3] {
+            [self.position_x, self.position_y, self.position_z]
+        }
+
+        /// Quaternion x, y, z, w.
+        pub fn rotation(&self) -> [f32; 4] {
+            [
+                self.rotation_x,
+                self.rotation_y,
+                self.rotation_z,
+                self.rotation_w,
+            ]
+        }
+
+        pub fn scale(&self) -> [f32; 3] {
+            [self.scale_x, self.scale_y, self.scale_z]
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Decals {
+        #[serde(rename = "$value", default)]
+        pub decals_vec: Vec<Decal>,
+    }
+
+    /// A projected decal with its own materials.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Decal {
+        #[serde(rename = "Transformer", skip_serializing_if = "Option::is_none")]
+        pub transformer: Option<Transformer>,
+        #[serde(rename = "Materials", default)]
+        pub materials: Materials,
+
+        #[serde(rename = "Extents.x", default)]
+        pub extents_x: f32,
+        #[serde(rename = "Extents.y", default)]
+        pub extents_y: f32,
+        #[serde(rename = "Extents.z", default)]
+        pub extents_z: f32,
+
+        #[serde(
+            rename = "$unknown",
+            default,
+            skip_serializing_if = "Unknown::is_empty"
+        )]
+        pub unknown: Unknown,
     }
 
     pub fn parse_cfg(path: &Path) -> Result<AnnoCfg, Box<dyn std::error::Error + 'static>> {
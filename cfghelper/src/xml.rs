@@ -0,0 +1,325 @@
+//! Deserializer for cfg xml (after the `ConfigType` tags are applied).
+//!
+//! The xml is read into an element tree first. quick-xml's own deserializer cannot hand the
+//! text of an element to a catch-all field, so children without a typed field would lose their
+//! values. Here a struct field named [`UNKNOWN`] gets every child the struct has no field for,
+//! in document order.
+//!
+//! Besides that the usual quick-xml conventions apply: a [`VALUE`] field gets all children as a
+//! sequence (their tag names are ignored), repeated children fill a `Vec` field and a
+//! [`CONFIG_TYPE`] field gets the tag name of the element.
+
+use quick_xml::{events::Event, Reader};
+use serde::de::{self, value::Error, DeserializeOwned, Error as _, IntoDeserializer, Visitor};
+
+pub const UNKNOWN: &str = "$unknown";
+pub const VALUE: &str = "$value";
+pub const CONFIG_TYPE: &str = "ConfigType";
+
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    text: String,
+    children: Vec<Element>,
+}
+
+fn parse(xml: &str) -> Result<Element, Error> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut stack = vec![Element::default()];
+    loop {
+        let event = reader.read_event(&mut buf).map_err(|e| {
+            Error::custom(format!(
+                "error at position {}: {:?}",
+                reader.buffer_position(),
+                e
+            ))
+        })?;
+        match event {
+            Event::Start(ref e) => stack.push(Element {
+                name: String::from_utf8_lossy(e.name()).into_owned(),
+                ..Default::default()
+            }),
+            Event::Empty(ref e) => stack.last_mut().unwrap().children.push(Element {
+                name: String::from_utf8_lossy(e.name()).into_owned(),
+                ..Default::default()
+            }),
+            Event::Text(ref e) | Event::CData(ref e) => {
+                let text = e
+                    .unescape_and_decode(&reader)
+                    .map_err(|e| Error::custom(format!("{:?}", e)))?;
+                stack.last_mut().unwrap().text.push_str(&text);
+            }
+            Event::End(_) => {
+                let element = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Err(Error::custom("unexpected end tag")),
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+    let mut document = stack.pop().unwrap();
+    if !stack.is_empty() || document.children.len() != 1 {
+        return Err(Error::custom("expected exactly one root element"));
+    }
+    Ok(document.children.pop().unwrap())
+}
+
+pub fn from_str<T: DeserializeOwned>(xml: &str) -> Result<T, Error> {
+    let root = parse(xml)?;
+    T::deserialize(Node::Elements(vec![&root]))
+}
+
+enum Node<'a> {
+    /// One element or all children of the same name (for `Vec` fields)
+    Elements(Vec<&'a Element>),
+    /// Children of different names ([`VALUE`], [`UNKNOWN`])
+    Children(Vec<&'a Element>),
+    /// [`CONFIG_TYPE`]
+    Text(&'a str),
+}
+
+impl<'a> Node<'a> {
+    fn text(&self) -> Result<&'a str, Error> {
+        match self {
+            Node::Elements(elements) => Ok(&elements[0].text),
+            Node::Text(text) => Ok(text),
+            Node::Children(_) => Err(Error::custom("expected text, found children")),
+        }
+    }
+
+    fn parse<T: std::str::FromStr>(&self) -> Result<T, Error>
+    where
+        T::Err: std::fmt::Display,
+    {
+        let text = self.text()?;
+        text.trim()
+            .parse()
+            .map_err(|e| Error::custom(format!("{:?}: {}", text, e)))
+    }
+
+    fn children(self) -> Result<Vec<&'a Element>, Error> {
+        match self {
+            Node::Elements(elements) => Ok(elements[0].children.iter().collect()),
+            Node::Children(children) => Ok(children),
+            Node::Text(_) => Err(Error::custom("expected children, found text")),
+        }
+    }
+}
+
+/// Entries of a struct: typed fields by name, the rest in [`VALUE`] or [`UNKNOWN`].
+fn struct_entries<'a>(element: &'a Element, fields: &[&str]) -> Vec<(&'a str, Node<'a>)> {
+    let mut entries: Vec<(&str, Node)> = Vec::new();
+    let mut value = Vec::new();
+    let mut unknown = Vec::new();
+
+    if fields.contains(&CONFIG_TYPE) && !element.children.iter().any(|c| c.name == CONFIG_TYPE) {
+        entries.push((CONFIG_TYPE, Node::Text(&element.name)));
+    }
+    for child in &element.children {
+        let name = child.name.as_str();
+        if fields.contains(&name) {
+            match entries.iter_mut().find(|(key, _)| *key == name) {
+                Some((_, Node::Elements(elements))) => elements.push(child),
+                _ => entries.push((name, Node::Elements(vec![child]))),
+            }
+        } else if fields.contains(&VALUE) {
+            value.push(child);
+        } else if fields.contains(&UNKNOWN) {
+            unknown.push(child);
+        } else {
+            entries.push((name, Node::Elements(vec![child])));
+        }
+    }
+    if fields.contains(&VALUE) {
+        entries.push((VALUE, Node::Children(value)));
+    }
+    if fields.contains(&UNKNOWN) {
+        entries.push((UNKNOWN, Node::Children(unknown)));
+    }
+    entries
+}
+
+struct Entries<'a> {
+    entries: std::vec::IntoIter<(&'a str, Node<'a>)>,
+    value: Option<Node<'a>>,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for Entries<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(self.value.take().unwrap())
+    }
+}
+
+struct Elements<'a>(std::vec::IntoIter<&'a Element>);
+
+impl<'de, 'a> de::SeqAccess<'de> for Elements<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        self.0
+            .next()
+            .map(|element| seed.deserialize(Node::Elements(vec![element])))
+            .transpose()
+    }
+}
+
+fn visit_entries<'de, V: Visitor<'de>>(
+    entries: Vec<(&str, Node)>,
+    visitor: V,
+) -> Result<V::Value, Error> {
+    visitor.visit_map(Entries {
+        entries: entries.into_iter(),
+        value: None,
+    })
+}
+
+macro_rules! deserialize_parsed {
+    ($($deserialize:ident => $visit:ident,)*) => {
+        $(
+            fn $deserialize<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Node<'a> {
+    type Error = Error;
+
+    /// Leaf elements are text, others a map of their children.
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Node::Elements(ref elements) if elements[0].children.is_empty() => {
+                visitor.visit_str(&elements[0].text)
+            }
+            Node::Text(text) => visitor.visit_str(text),
+            _ => self.deserialize_map(visitor),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.text()?.trim() {
+            "true" | "1" | "True" | "TRUE" | "t" | "Yes" | "YES" | "yes" | "y" => {
+                visitor.visit_bool(true)
+            }
+            "false" | "0" | "False" | "FALSE" | "f" | "No" | "NO" | "no" | "n" => {
+                visitor.visit_bool(false)
+            }
+            text => Err(Error::custom(format!("invalid boolean {:?}", text))),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_str(self.text()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    /// A present element is always `Some`, also if it is empty.
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let elements = match self {
+            Node::Elements(elements) | Node::Children(elements) => elements,
+            Node::Text(_) => return Err(Error::custom("expected elements, found text")),
+        };
+        visitor.visit_seq(Elements(elements.into_iter()))
+    }
+
+    /// Every child is an entry, also if names repeat.
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let entries = self
+            .children()?
+            .into_iter()
+            .map(|child| (child.name.as_str(), Node::Elements(vec![child])))
+            .collect();
+        visit_entries(entries, visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            Node::Elements(elements) => visit_entries(struct_entries(elements[0], fields), visitor),
+            _ => self.deserialize_map(visitor),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 bytes byte_buf tuple tuple_struct enum
+    }
+}
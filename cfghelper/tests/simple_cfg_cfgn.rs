@@ -38,4 +38,286 @@ mod cfg_xml_tests {
         // https://github.com/tafia/quick-xml/issues/187
         // notice the serialized xml gets spammed with <$value> & </$value> tags.
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn battle_cruiser_material_map() {
+        let battle_cruiser_cfg: AnnoCfg =
+            cfg::parse_cfg(Path::new("tests/cfgs/battle_cruiser.cfg")).unwrap();
+        let map = battle_cruiser_cfg.models.models_vec[0].material_map();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["battlecruiser_LOD0"], 0);
+        assert_eq!(map["battlecruiser_LOD0_02"], 1);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn battle_cruiser_files_and_transforms() {
+        let battle_cruiser_cfg: AnnoCfg =
+            cfg::parse_cfg(Path::new("tests/cfgs/battle_cruiser.cfg")).unwrap();
+        let files = &battle_cruiser_cfg.files.as_ref().unwrap().files_vec;
+        assert_eq!(files.len(), 2);
+        assert_eq!(
+            files[0].file_name,
+            "data\\graphics\\props\\flags\\animated_flags\\flag_anim_horizontal_white_01.cfg"
+        );
+        let transformer = files[0].transformer.as_ref().unwrap();
+        // ORIENTATION_TRANSFORM and VISIBILITY_TRANSFORM
+        assert_eq!(transformer.transforms.len(), 2);
+        let orientations: Vec<&cfg::Transform> = transformer.orientations().collect();
+        assert_eq!(orientations.len(), 1);
+        assert_eq!(orientations[0].position(), [-2.580801, 5.252859, 0.034888]);
+        assert_eq!(orientations[0].rotation(), [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(orientations[0].scale(), 0.7);
+
+        // the destruct model lists VISIBILITY_TRANSFORM first
+        let model = &battle_cruiser_cfg.models.models_vec[1];
+        let orientation = model
+            .transformer
+            .as_ref()
+            .unwrap()
+            .orientations()
+            .next()
+            .unwrap();
+        assert_eq!(orientation.position(), [0.0, -0.278133, 0.0]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn battle_cruiser_pbr_material() {
+        let battle_cruiser_cfg: AnnoCfg =
+            cfg::parse_cfg(Path::new("tests/cfgs/battle_cruiser.cfg")).unwrap();
+        let material = &battle_cruiser_cfg.models.models_vec[0]
+            .materials
+            .materials_vec[0];
+        assert_eq!(material.NORMAL_ENABLED, Some(true));
+        assert_eq!(
+            material.cModelNormalTex.as_deref(),
+            Some("data/graphics/vehicle/battle_cruiser/maps/battle_cruiser_norm.psd")
+        );
+        assert_eq!(material.METALLIC_TEX_ENABLED, Some(true));
+        assert_eq!(
+            material.cModelMetallicTex.as_deref(),
+            Some("data/graphics/vehicle/battle_cruiser/maps/battle_cruiser_metal.psd")
+        );
+        assert_eq!(
+            [
+                material.cDiffuseColor_r,
+                material.cDiffuseColor_g,
+                material.cDiffuseColor_b
+            ],
+            [1.0; 3]
+        );
+        assert_eq!(material.ALPHA_BLEND_ENABLED, Some(false));
+        assert_eq!(material.GLOW_ENABLED, Some(true));
+        assert_eq!(material.cEmissiveColor_r, 2.0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn battle_cruiser_full_schema() {
+        let battle_cruiser_cfg: AnnoCfg =
+            cfg::parse_cfg(Path::new("tests/cfgs/battle_cruiser.cfg")).unwrap();
+        assert_eq!(battle_cruiser_cfg.center(), [0.022064, 4.670544, 0.081473]);
+        assert_eq!(battle_cruiser_cfg.radius, 24.177372);
+        assert_eq!(battle_cruiser_cfg.mass, 165.0);
+        assert_eq!(battle_cruiser_cfg.drag, 16.0);
+        assert_eq!(
+            battle_cruiser_cfg.mesh_extent(),
+            [5.851563, 3.083008, 1.490234]
+        );
+        assert_eq!(battle_cruiser_cfg.mesh_radius, 6.779861);
+
+        let transforms = &battle_cruiser_cfg.files.as_ref().unwrap().files_vec[0]
+            .transformer
+            .as_ref()
+            .unwrap()
+            .transforms;
+        assert_eq!(transforms[0].config_type, "ORIENTATION_TRANSFORM");
+        assert_eq!(transforms[1].config_type, "VISIBILITY_TRANSFORM");
+        assert_eq!(transforms[1].Conditions, Some(256));
+        assert_eq!(transforms[1].AnimationTime, Some(1.0));
+        assert!(!transforms[1].is_orientation());
+
+        let containers = &battle_cruiser_cfg
+            .prop_containers
+            .as_ref()
+            .unwrap()
+            .prop_containers_vec;
+        let color = &containers[0].transformer.as_ref().unwrap().transforms[0];
+        assert_eq!(color.config_type, "COLOR_TRANSFORM");
+        assert_eq!(color.State.len(), 2);
+        assert_eq!(color.State[0].Condition, 65536);
+        assert_eq!(color.State[1].Condition, 0);
+        assert_eq!(containers[0].variation_probability, Some(100));
+        let prop = &containers[0].props.props_vec[0];
+        assert_eq!(prop.position(), [-0.147604, 0.0, 1.116384]);
+        assert_eq!(prop.rotation(), [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(prop.scale(), [1.4; 3]);
+
+        let particle = &battle_cruiser_cfg.particles.as_ref().unwrap().particles_vec[0];
+        assert_eq!(particle.time_scale, Some(3.4));
+        assert!(particle.file_name.ends_with(".rdp"));
+
+        assert!(battle_cruiser_cfg.dummies.is_none());
+        assert!(battle_cruiser_cfg.lights.is_none());
+        assert!(battle_cruiser_cfg.decals.is_none());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn battle_cruiser_unknown_fields() {
+        use cfg::UnknownValue;
+
+        let battle_cruiser_cfg: AnnoCfg =
+            cfg::parse_cfg(Path::new("tests/cfgs/battle_cruiser.cfg")).unwrap();
+        let material = &battle_cruiser_cfg.models.models_vec[0]
+            .materials
+            .materials_vec[0];
+        assert_eq!(
+            material
+                .unknown
+                .get("cDyeMask")
+                .and_then(UnknownValue::as_text),
+            Some("data/graphics/vehicle/battle_cruiser/maps/battle_cruiser_dye.psd")
+        );
+
+        let particle = &battle_cruiser_cfg.particles.as_ref().unwrap().particles_vec[0];
+        let color = particle
+            .unknown
+            .get("Color")
+            .and_then(UnknownValue::as_element)
+            .unwrap();
+        assert_eq!(
+            color.get("x").and_then(UnknownValue::as_text),
+            Some("0.427000")
+        );
+        assert_eq!(
+            particle
+                .unknown
+                .get("TextureAtlas")
+                .and_then(UnknownValue::as_text),
+            Some("")
+        );
+
+        // the top-level Sequences have no typed struct, repeated tags are kept in order
+        let sequences = battle_cruiser_cfg
+            .unknown
+            .get("Sequences")
+            .and_then(UnknownValue::as_element)
+            .unwrap();
+        assert_eq!(sequences.0.len(), 3);
+        assert_eq!(sequences.0[2].0, "SEQUENCE");
+        let sequence = sequences.0[2].1.as_element().unwrap();
+        assert_eq!(
+            sequence.get("SequenceID").and_then(UnknownValue::as_text),
+            Some("1005")
+        );
+        let tracks = sequence
+            .0
+            .iter()
+            .filter(|(name, _)| name == "Track")
+            .count();
+        assert_eq!(tracks, 16);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn lighthouse_dummies_lights_decals() {
+        use cfg::UnknownValue;
+
+        let lighthouse_cfg: AnnoCfg =
+            cfg::parse_cfg(Path::new("tests/cfgs/lighthouse.cfg")).unwrap();
+        let orientation = |transformer: &Option<cfg::Transformer>| {
+            transformer
+                .as_ref()
+                .unwrap()
+                .orientations()
+                .next()
+                .unwrap()
+                .clone()
+        };
+
+        let dummies = &lighthouse_cfg.dummies.as_ref().unwrap().dummies_vec;
+        assert_eq!(dummies.len(), 2);
+        assert_eq!(dummies[0].name, "beacon");
+        assert_eq!(
+            orientation(&dummies[0].transformer).position(),
+            [1.25, 14.5, -0.75]
+        );
+        assert_eq!(
+            dummies[0]
+                .unknown
+                .get("RotationY")
+                .and_then(UnknownValue::as_text),
+            Some("0.000000")
+        );
+        assert_eq!(dummies[1].name, "entrance");
+        assert_eq!(
+            orientation(&dummies[1].transformer).rotation(),
+            [0.0, 0.5, 0.0, 0.866025]
+        );
+        assert!(dummies[1].unknown.is_empty());
+
+        let lights = &lighthouse_cfg.lights.as_ref().unwrap().lights_vec;
+        assert_eq!(lights.len(), 2);
+        assert_eq!(lights[0].config_type, "POINTLIGHT");
+        assert_eq!(lights[0].name, "lantern");
+        assert_eq!(
+            orientation(&lights[0].transformer).position(),
+            [0.0, 15.25, 0.0]
+        );
+        assert_eq!(
+            lights[0]
+                .unknown
+                .get("Range")
+                .and_then(UnknownValue::as_text),
+            Some("12.500000")
+        );
+        assert_eq!(
+            lights[0]
+                .unknown
+                .get("Diffuse.g")
+                .and_then(UnknownValue::as_text),
+            Some("0.850000")
+        );
+        assert_eq!(lights[1].config_type, "SPOTLIGHT");
+        assert_eq!(lights[1].name, "beam");
+        assert_eq!(
+            lights[1]
+                .unknown
+                .get("OuterAngle")
+                .and_then(UnknownValue::as_text),
+            Some("15.000000")
+        );
+
+        let decals = &lighthouse_cfg.decals.as_ref().unwrap().decals_vec;
+        assert_eq!(decals.len(), 1);
+        let decal = &decals[0];
+        assert_eq!(
+            [decal.extents_x, decal.extents_y, decal.extents_z],
+            [4.5, 0.25, 4.5]
+        );
+        assert_eq!(orientation(&decal.transformer).scale(), 1.0);
+        let material = &decal.materials.materials_vec[0];
+        assert_eq!(material.Name, "lighthouse_decal");
+        assert_eq!(
+            material.cModelDiffTex,
+            "data/graphics/buildings/lighthouse/maps/lighthouse_decal_diff.psd"
+        );
+        assert_eq!(
+            decal
+                .unknown
+                .get("TerrainAdaption")
+                .and_then(UnknownValue::as_text),
+            Some("1")
+        );
+
+        // the unknown fields are written back
+        let out = se::to_string(&lighthouse_cfg).unwrap();
+        assert!(out.contains("<RotationY>0.000000</RotationY>"));
+        assert!(out.contains("<Range>12.500000</Range>"));
+        assert!(out.contains("<OuterAngle>15.000000</OuterAngle>"));
+        assert!(out.contains("<TerrainAdaption>1</TerrainAdaption>"));
+    }
 }
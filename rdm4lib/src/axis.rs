@@ -0,0 +1,110 @@
+use nalgebra::{Isometry3, Matrix3, Rotation3, Translation3, UnitQuaternion, Vector3};
+use std::str::FromStr;
+
+/// A signed coordinate axis, parsed from "X", "+Y", "-Z", ...
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+    NegX,
+    NegY,
+    NegZ,
+}
+
+impl Axis {
+    fn vector(self) -> Vector3<f32> {
+        match self {
+            Axis::X => Vector3::x(),
+            Axis::Y => Vector3::y(),
+            Axis::Z => Vector3::z(),
+            Axis::NegX => -Vector3::x(),
+            Axis::NegY => -Vector3::y(),
+            Axis::NegZ => -Vector3::z(),
+        }
+    }
+}
+
+impl FromStr for Axis {
+    type Err = &'static str;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_ascii_uppercase().as_str() {
+            "X" | "+X" => Ok(Axis::X),
+            "Y" | "+Y" => Ok(Axis::Y),
+            "Z" | "+Z" => Ok(Axis::Z),
+            "-X" => Ok(Axis::NegX),
+            "-Y" => Ok(Axis::NegY),
+            "-Z" => Ok(Axis::NegZ),
+            _ => Err("axis must be one of X, Y, Z, -X, -Y, -Z"),
+        }
+    }
+}
+
+/// Axis convention and unit of the glTF side. rdm space is right-handed, +Y up and +Z forward
+/// (the glTF default), so the default conversion is the identity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AxisConversion {
+    /// glTF axis that points up.
+    pub up: Axis,
+    /// glTF axis that points forward.
+    pub forward: Axis,
+    /// The glTF coordinates are left-handed.
+    pub left_handed: bool,
+    /// glTF unit in rdm units, e.g. 0.01 for a glTF authored in centimetres.
+    pub scale: f32,
+}
+
+impl Default for AxisConversion {
+    fn default() -> Self {
+        AxisConversion {
+            up: Axis::Y,
+            forward: Axis::Z,
+            left_handed: false,
+            scale: 1.0,
+        }
+    }
+}
+
+impl AxisConversion {
+    pub fn is_identity(&self) -> bool {
+        *self == AxisConversion::default()
+    }
+
+    /// Linear map from glTF into rdm space.
+    pub fn to_rdm(&self) -> Matrix3<f32> {
+        let up = self.up.vector();
+        let forward = self.forward.vector();
+        assert_eq!(
+            up.dot(&forward),
+            0.0,
+            "up axis {:?} and forward axis {:?} must be perpendicular",
+            self.up,
+            self.forward
+        );
+        let right = if self.left_handed {
+            -up.cross(&forward)
+        } else {
+            up.cross(&forward)
+        };
+        // maps right -> +X, up -> +Y, forward -> +Z
+        Matrix3::from_rows(&[right.transpose(), up.transpose(), forward.transpose()]) * self.scale
+    }
+
+    /// Linear map from rdm into glTF space.
+    pub fn to_gltf(&self) -> Matrix3<f32> {
+        self.to_rdm().try_inverse().unwrap()
+    }
+}
+
+/// Rigid transform `iso` expressed in the space mapped by `m` (rotation or mirroring times a uniform
+/// scale): m · iso · m^-1. The result is rigid again, mirroring cancels out.
+pub(crate) fn conjugate_isometry(m: &Matrix3<f32>, iso: &Isometry3<f32>) -> Isometry3<f32> {
+    let scale = m.determinant().abs().cbrt();
+    let r = m / scale;
+    let rotation = r * iso.rotation.to_rotation_matrix().matrix() * r.transpose();
+    Isometry3::from_parts(
+        Translation3::from(m * iso.translation.vector),
+        UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(rotation)),
+    )
+}
@@ -0,0 +1,1188 @@
+//! DDS textures: decoding of BC1-BC5, BC7 and uncompressed formats (legacy and DX10 header) to
+//! RGBA8 and PNG. Only the first image (mip level 0, first array slice or cube face) is used by the
+//! glTF export. For the import, images are encoded to BC7 (mode 6) with the full mip chain.
+
+use std::fs;
+use std::path::Path;
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const HEADER_SIZE: usize = 4 + 124;
+const DX10_HEADER_SIZE: usize = 20;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_ALPHA: u32 = 0x2;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+
+/// 8 bit RGBA pixels, row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl RgbaImage {
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut png = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+            encoder.set_color(png::ColorType::RGBA);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().expect("PNG encoding error");
+            writer
+                .write_image_data(&self.rgba)
+                .expect("PNG encoding error");
+        }
+        png
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DdsFormat {
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4 {
+        signed: bool,
+    },
+    Bc5 {
+        signed: bool,
+    },
+    Bc7,
+    Rgba8,
+    Bgra8,
+    Bgrx8,
+    R8,
+    Rg8,
+    /// Legacy uncompressed formats described by bit masks.
+    Masked {
+        bytes: usize,
+        masks: [u32; 4],
+        luminance: bool,
+    },
+}
+
+impl DdsFormat {
+    fn from_dxgi(dxgi: u32) -> Self {
+        match dxgi {
+            27..=29 => DdsFormat::Rgba8,
+            87 | 90 | 91 => DdsFormat::Bgra8,
+            88 | 92 | 93 => DdsFormat::Bgrx8,
+            60 | 61 => DdsFormat::R8,
+            48 | 49 => DdsFormat::Rg8,
+            70..=72 => DdsFormat::Bc1,
+            73..=75 => DdsFormat::Bc2,
+            76..=78 => DdsFormat::Bc3,
+            79 | 80 => DdsFormat::Bc4 { signed: false },
+            81 => DdsFormat::Bc4 { signed: true },
+            82 | 83 => DdsFormat::Bc5 { signed: false },
+            84 => DdsFormat::Bc5 { signed: true },
+            97..=99 => DdsFormat::Bc7,
+            _ => panic!("DDS: unsupported DXGI format {}", dxgi),
+        }
+    }
+
+    fn from_four_cc(four_cc: &[u8]) -> Self {
+        match four_cc {
+            b"DXT1" => DdsFormat::Bc1,
+            b"DXT2" | b"DXT3" => DdsFormat::Bc2,
+            b"DXT4" | b"DXT5" => DdsFormat::Bc3,
+            b"ATI1" | b"BC4U" => DdsFormat::Bc4 { signed: false },
+            b"BC4S" => DdsFormat::Bc4 { signed: true },
+            b"ATI2" | b"BC5U" => DdsFormat::Bc5 { signed: false },
+            b"BC5S" => DdsFormat::Bc5 { signed: true },
+            _ => panic!(
+                "DDS: unsupported FourCC {:?}",
+                String::from_utf8_lossy(four_cc)
+            ),
+        }
+    }
+
+    /// Bytes per 4x4 block, None for formats stored per pixel.
+    fn block_size(&self) -> Option<usize> {
+        match self {
+            DdsFormat::Bc1 | DdsFormat::Bc4 { .. } => Some(8),
+            DdsFormat::Bc2 | DdsFormat::Bc3 | DdsFormat::Bc5 { .. } | DdsFormat::Bc7 => Some(16),
+            _ => None,
+        }
+    }
+
+    fn pixel_size(&self) -> usize {
+        match self {
+            DdsFormat::Rgba8 | DdsFormat::Bgra8 | DdsFormat::Bgrx8 => 4,
+            DdsFormat::R8 => 1,
+            DdsFormat::Rg8 => 2,
+            DdsFormat::Masked { bytes, .. } => *bytes,
+            _ => unreachable!(),
+        }
+    }
+
+    fn level_size(&self, width: u32, height: u32) -> usize {
+        match self.block_size() {
+            Some(block) => {
+                block * (width as usize).div_ceil(4).max(1) * (height as usize).div_ceil(4).max(1)
+            }
+            None => self.pixel_size() * width as usize * height as usize,
+        }
+    }
+}
+
+/// Parsed DDS file.
+struct Dds<'a> {
+    width: u32,
+    height: u32,
+    mip_count: u32,
+    format: DdsFormat,
+    data: &'a [u8],
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+impl<'a> Dds<'a> {
+    fn parse(data: &'a [u8]) -> Self {
+        assert!(
+            data.len() >= HEADER_SIZE && &data[0..4] == DDS_MAGIC,
+            "DDS: not a DDS file"
+        );
+        let height = read_u32(data, 12);
+        let width = read_u32(data, 16);
+        let mip_count = read_u32(data, 28).max(1);
+        let pf_flags = read_u32(data, 80);
+        let four_cc = &data[84..88];
+
+        let (format, offset) = if pf_flags & DDPF_FOURCC != 0 && four_cc == b"DX10" {
+            assert!(
+                data.len() >= HEADER_SIZE + DX10_HEADER_SIZE,
+                "DDS: truncated DX10 header"
+            );
+            let dxgi = read_u32(data, HEADER_SIZE);
+            let dimension = read_u32(data, HEADER_SIZE + 4);
+            assert_eq!(dimension, 3, "DDS: only 2D textures are supported");
+            (DdsFormat::from_dxgi(dxgi), HEADER_SIZE + DX10_HEADER_SIZE)
+        } else if pf_flags & DDPF_FOURCC != 0 {
+            (DdsFormat::from_four_cc(four_cc), HEADER_SIZE)
+        } else if pf_flags & (DDPF_RGB | DDPF_LUMINANCE | DDPF_ALPHA) != 0 {
+            let bits = read_u32(data, 88);
+            assert!(
+                matches!(bits, 8 | 16 | 24 | 32),
+                "DDS: unsupported bit count {}",
+                bits
+            );
+            let alpha_mask = if pf_flags & (DDPF_ALPHAPIXELS | DDPF_ALPHA) != 0 {
+                read_u32(data, 104)
+            } else {
+                0
+            };
+            let format = DdsFormat::Masked {
+                bytes: bits as usize / 8,
+                masks: [
+                    read_u32(data, 92),
+                    read_u32(data, 96),
+                    read_u32(data, 100),
+                    alpha_mask,
+                ],
+                luminance: pf_flags & DDPF_LUMINANCE != 0,
+            };
+            (format, HEADER_SIZE)
+        } else {
+            panic!("DDS: unsupported pixel format flags {:#x}", pf_flags);
+        };
+
+        Dds {
+            width,
+            height,
+            mip_count,
+            format,
+            data: &data[offset..],
+        }
+    }
+
+    fn decode_level(&self, level: u32) -> RgbaImage {
+        assert!(level < self.mip_count, "DDS: no mip level {}", level);
+        let mut offset = 0;
+        for l in 0..level {
+            offset += self
+                .format
+                .level_size((self.width >> l).max(1), (self.height >> l).max(1));
+        }
+        let width = (self.width >> level).max(1);
+        let height = (self.height >> level).max(1);
+        let size = self.format.level_size(width, height);
+        assert!(
+            self.data.len() >= offset + size,
+            "DDS: file is too short for {}x{} {:?}",
+            width,
+            height,
+            self.format
+        );
+        let data = &self.data[offset..offset + size];
+
+        let mut rgba = vec![0u8; width as usize * height as usize * 4];
+        match self.format.block_size() {
+            Some(block_size) => {
+                let blocks_x = (width as usize).div_ceil(4).max(1);
+                for (i, block) in data.chunks_exact(block_size).enumerate() {
+                    let pixels = decode_block(self.format, block);
+                    let (bx, by) = (i % blocks_x * 4, i / blocks_x * 4);
+                    for (p, pixel) in pixels.iter().enumerate() {
+                        let (x, y) = (bx + p % 4, by + p / 4);
+                        if x < width as usize && y < height as usize {
+                            let dst = (y * width as usize + x) * 4;
+                            rgba[dst..dst + 4].copy_from_slice(pixel);
+                        }
+                    }
+                }
+            }
+            None => {
+                let pixel_size = self.format.pixel_size();
+                for (src, dst) in data.chunks_exact(pixel_size).zip(rgba.chunks_exact_mut(4)) {
+                    dst.copy_from_slice(&decode_pixel(self.format, src));
+                }
+            }
+        }
+        RgbaImage {
+            width,
+            height,
+            rgba,
+        }
+    }
+}
+
+/// Decodes the first image of a DDS file.
+pub fn decode_dds(data: &[u8]) -> RgbaImage {
+    Dds::parse(data).decode_level(0)
+}
+
+/// Reads a DDS file and converts its first image to PNG.
+pub fn dds_to_png(path: &Path) -> Vec<u8> {
+    let data = fs::read(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    decode_dds(&data).to_png()
+}
+
+fn decode_pixel(format: DdsFormat, src: &[u8]) -> [u8; 4] {
+    match format {
+        DdsFormat::Rgba8 => [src[0], src[1], src[2], src[3]],
+        DdsFormat::Bgra8 => [src[2], src[1], src[0], src[3]],
+        DdsFormat::Bgrx8 => [src[2], src[1], src[0], 255],
+        DdsFormat::R8 => [src[0], src[0], src[0], 255],
+        DdsFormat::Rg8 => [src[0], src[1], 0, 255],
+        DdsFormat::Masked {
+            masks, luminance, ..
+        } => {
+            let mut value = 0u32;
+            for (i, b) in src.iter().enumerate() {
+                value |= (*b as u32) << (8 * i);
+            }
+            let channel = |mask: u32, default: u8| -> u8 {
+                if mask == 0 {
+                    return default;
+                }
+                let max = mask >> mask.trailing_zeros();
+                (((value & mask) >> mask.trailing_zeros()) * 255 + max / 2)
+                    .checked_div(max)
+                    .unwrap_or(0) as u8
+            };
+            let a = channel(masks[3], 255);
+            if luminance {
+                let l = channel(masks[0], 0);
+                [l, l, l, a]
+            } else {
+                [
+                    channel(masks[0], 0),
+                    channel(masks[1], 0),
+                    channel(masks[2], 0),
+                    a,
+                ]
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+type Block = [[u8; 4]; 16];
+
+fn decode_block(format: DdsFormat, block: &[u8]) -> Block {
+    match format {
+        DdsFormat::Bc1 => decode_bc1_color(block, false),
+        DdsFormat::Bc2 => {
+            let mut pixels = decode_bc1_color(&block[8..16], true);
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                pixel[3] = ((block[i / 2] >> (4 * (i % 2))) & 0xF) * 17;
+            }
+            pixels
+        }
+        DdsFormat::Bc3 => {
+            let mut pixels = decode_bc1_color(&block[8..16], true);
+            let alpha = decode_bc4_channel(&block[0..8], false);
+            for (pixel, a) in pixels.iter_mut().zip(alpha) {
+                pixel[3] = a;
+            }
+            pixels
+        }
+        DdsFormat::Bc4 { signed } => {
+            let r = decode_bc4_channel(block, signed);
+            let mut pixels = [[0, 0, 0, 255]; 16];
+            for (pixel, r) in pixels.iter_mut().zip(r) {
+                *pixel = [r, r, r, 255];
+            }
+            pixels
+        }
+        DdsFormat::Bc5 { signed } => {
+            let r = decode_bc4_channel(&block[0..8], signed);
+            let g = decode_bc4_channel(&block[8..16], signed);
+            let mut pixels = [[0, 0, 0, 255]; 16];
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = [r[i], g[i], 0, 255];
+            }
+            pixels
+        }
+        DdsFormat::Bc7 => decode_bc7(block),
+        _ => unreachable!(),
+    }
+}
+
+fn rgb565(c: u16) -> [u32; 3] {
+    let r = (c >> 11) as u32 & 0x1F;
+    let g = (c >> 5) as u32 & 0x3F;
+    let b = c as u32 & 0x1F;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+/// BC1 color block. BC2 and BC3 always use the 4 color mode.
+fn decode_bc1_color(block: &[u8], four_colors: bool) -> Block {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (e0, e1) = (rgb565(c0), rgb565(c1));
+    let mix = |w0: u32, w1: u32, d: u32| -> [u8; 4] {
+        let mut c = [0, 0, 0, 255];
+        for k in 0..3 {
+            c[k] = ((w0 * e0[k] + w1 * e1[k] + d / 2) / d) as u8;
+        }
+        c
+    };
+    let palette = if four_colors || c0 > c1 {
+        [mix(1, 0, 1), mix(0, 1, 1), mix(2, 1, 3), mix(1, 2, 3)]
+    } else {
+        [mix(1, 0, 1), mix(0, 1, 1), mix(1, 1, 2), [0, 0, 0, 0]]
+    };
+    let indices = read_u32(block, 4);
+    let mut pixels = [[0; 4]; 16];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = palette[(indices >> (2 * i)) as usize & 0x3];
+    }
+    pixels
+}
+
+/// BC4 block, also the alpha of BC3 and the channels of BC5.
+fn decode_bc4_channel(block: &[u8], signed: bool) -> [u8; 16] {
+    let endpoint = |b: u8| -> i32 {
+        if signed {
+            (b as i8).max(-127) as i32
+        } else {
+            b as i32
+        }
+    };
+    let (a0, a1) = (endpoint(block[0]), endpoint(block[1]));
+    let (min, max) = if signed { (-127, 127) } else { (0, 255) };
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 0];
+    if a0 > a1 {
+        for (i, p) in palette.iter_mut().enumerate().skip(2) {
+            *p = ((8 - i as i32) * a0 + (i as i32 - 1) * a1 + 3).div_euclid(7);
+        }
+    } else {
+        for (i, p) in palette.iter_mut().enumerate().skip(2).take(4) {
+            *p = ((6 - i as i32) * a0 + (i as i32 - 1) * a1 + 2).div_euclid(5);
+        }
+        palette[6] = min;
+        palette[7] = max;
+    }
+
+    let mut bits = 0u64;
+    for (i, b) in block[2..8].iter().enumerate() {
+        bits |= (*b as u64) << (8 * i);
+    }
+    let mut values = [0u8; 16];
+    for (i, v) in values.iter_mut().enumerate() {
+        let p = palette[(bits >> (3 * i)) as usize & 0x7];
+        *v = if signed {
+            // snorm -127..127 to unorm 0..255
+            ((p + 127) * 255 + 127).div_euclid(254) as u8
+        } else {
+            p as u8
+        };
+    }
+    values
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    index_bits2: u32,
+}
+
+const fn bc7_mode(m: [u32; 10]) -> Bc7Mode {
+    Bc7Mode {
+        subsets: m[0] as usize,
+        partition_bits: m[1],
+        rotation_bits: m[2],
+        index_selection_bits: m[3],
+        color_bits: m[4],
+        alpha_bits: m[5],
+        endpoint_pbits: m[6] == 1,
+        shared_pbits: m[7] == 1,
+        index_bits: m[8],
+        index_bits2: m[9],
+    }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    bc7_mode([3, 4, 0, 0, 4, 0, 1, 0, 3, 0]),
+    bc7_mode([2, 6, 0, 0, 6, 0, 0, 1, 3, 0]),
+    bc7_mode([3, 6, 0, 0, 5, 0, 0, 0, 2, 0]),
+    bc7_mode([2, 6, 0, 0, 7, 0, 1, 0, 2, 0]),
+    bc7_mode([1, 0, 2, 1, 5, 6, 0, 0, 2, 3]),
+    bc7_mode([1, 0, 2, 0, 7, 8, 0, 0, 2, 2]),
+    bc7_mode([1, 0, 0, 0, 7, 7, 1, 0, 4, 0]),
+    bc7_mode([2, 6, 0, 0, 5, 5, 1, 0, 2, 0]),
+];
+
+/// Subset of every pixel for the 2 subset partitions, bit i is pixel i.
+const BC7_PARTITIONS2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800,
+    0xFFE8, 0xFF00, 0xFFF0, 0xF000, 0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C, 0xAAAA, 0xF0F0, 0x5A5A, 0x33CC,
+    0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, 0x9336, 0x9CC6, 0x817E, 0xE718,
+    0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+/// Subset of every pixel for the 3 subset partitions, 2 bits per pixel.
+const BC7_PARTITIONS3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2],
+    [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0],
+    [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0],
+    [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2],
+    [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0],
+    [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0],
+    [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1],
+    [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1],
+    [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2],
+    [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2],
+    [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+    [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+/// Anchor pixel of the second subset of the 2 subset partitions.
+const BC7_ANCHOR2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Anchor pixels of the second and third subset of the 3 subset partitions.
+const BC7_ANCHOR3: [[u8; 64]; 2] = [
+    [
+        3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6,
+        8, 5, 15, 15, 8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8,
+        5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+    ],
+    [
+        15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3,
+        15, 6, 10, 15, 15, 10, 8, 15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15,
+        15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+    ],
+];
+
+const BC7_WEIGHTS2: [u32; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn bc7_subset(subsets: usize, partition: usize, pixel: usize) -> usize {
+    match subsets {
+        1 => 0,
+        2 => (BC7_PARTITIONS2[partition] >> pixel) as usize & 1,
+        _ => BC7_PARTITIONS3[partition][pixel] as usize,
+    }
+}
+
+fn bc7_is_anchor(subsets: usize, partition: usize, pixel: usize) -> bool {
+    pixel == 0
+        || match subsets {
+            2 => BC7_ANCHOR2[partition] as usize == pixel,
+            3 => {
+                BC7_ANCHOR3[0][partition] as usize == pixel
+                    || BC7_ANCHOR3[1][partition] as usize == pixel
+            }
+            _ => false,
+        }
+}
+
+fn bc7_interpolate(e0: u32, e1: u32, index: u32, bits: u32) -> u8 {
+    let w = match bits {
+        2 => BC7_WEIGHTS2[index as usize],
+        3 => BC7_WEIGHTS3[index as usize],
+        _ => BC7_WEIGHTS4[index as usize],
+    };
+    (((64 - w) * e0 + w * e1 + 32) >> 6) as u8
+}
+
+/// Little endian bit reader over one 128 bit block.
+struct Bits {
+    value: u128,
+    pos: u32,
+}
+
+impl Bits {
+    fn read(&mut self, n: u32) -> u32 {
+        let v = (self.value >> self.pos) as u32 & ((1u64 << n) - 1) as u32;
+        self.pos += n;
+        v
+    }
+}
+
+fn decode_bc7(block: &[u8]) -> Block {
+    let mut bits = Bits {
+        value: u128::from_le_bytes(block.try_into().unwrap()),
+        pos: 0,
+    };
+    let mode_idx = block[0].trailing_zeros();
+    if mode_idx >= 8 {
+        // reserved mode
+        return [[0; 4]; 16];
+    }
+    bits.read(mode_idx + 1);
+    let mode = &BC7_MODES[mode_idx as usize];
+
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    // endpoints[subset * 2 + e][channel]
+    let mut endpoints = [[0u32; 4]; 6];
+    let endpoint_count = mode.subsets * 2;
+    for channel in 0..3 {
+        for e in endpoints.iter_mut().take(endpoint_count) {
+            e[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for e in endpoints.iter_mut().take(endpoint_count) {
+        e[3] = if mode.alpha_bits > 0 {
+            bits.read(mode.alpha_bits)
+        } else {
+            255
+        };
+    }
+
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+    if mode.endpoint_pbits || mode.shared_pbits {
+        let pbits: Vec<u32> = if mode.endpoint_pbits {
+            (0..endpoint_count).map(|_| bits.read(1)).collect()
+        } else {
+            (0..mode.subsets)
+                .map(|_| bits.read(1))
+                .flat_map(|p| [p, p])
+                .collect()
+        };
+        for (e, p) in endpoints.iter_mut().zip(pbits) {
+            for c in e.iter_mut().take(if mode.alpha_bits > 0 { 4 } else { 3 }) {
+                *c = (*c << 1) | p;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+    let expand = |v: u32, bits: u32| -> u32 { (v << (8 - bits)) | (v >> (2 * bits - 8)) };
+    for e in endpoints.iter_mut().take(endpoint_count) {
+        for c in e.iter_mut().take(3) {
+            *c = expand(*c, color_bits);
+        }
+        if alpha_bits > 0 {
+            e[3] = expand(e[3], alpha_bits);
+        }
+    }
+
+    let read_indices = |bits: &mut Bits, index_bits: u32| -> [u32; 16] {
+        let mut indices = [0; 16];
+        for (pixel, index) in indices.iter_mut().enumerate() {
+            let n = if bc7_is_anchor(mode.subsets, partition, pixel) {
+                index_bits - 1
+            } else {
+                index_bits
+            };
+            *index = bits.read(n);
+        }
+        indices
+    };
+    let indices = read_indices(&mut bits, mode.index_bits);
+    let indices2 = (mode.index_bits2 > 0).then(|| read_indices(&mut bits, mode.index_bits2));
+
+    let mut pixels = [[0u8; 4]; 16];
+    for (pixel, out) in pixels.iter_mut().enumerate() {
+        let subset = bc7_subset(mode.subsets, partition, pixel);
+        let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+        let ((color_index, color_bits), (alpha_index, alpha_bits)) = match indices2 {
+            Some(indices2) if index_selection == 1 => (
+                (indices2[pixel], mode.index_bits2),
+                (indices[pixel], mode.index_bits),
+            ),
+            Some(indices2) => (
+                (indices[pixel], mode.index_bits),
+                (indices2[pixel], mode.index_bits2),
+            ),
+            None => (
+                (indices[pixel], mode.index_bits),
+                (indices[pixel], mode.index_bits),
+            ),
+        };
+        for c in 0..3 {
+            out[c] = bc7_interpolate(e0[c], e1[c], color_index, color_bits);
+        }
+        out[3] = bc7_interpolate(e0[3], e1[3], alpha_index, alpha_bits);
+        match rotation {
+            1 => out.swap(0, 3),
+            2 => out.swap(1, 3),
+            3 => out.swap(2, 3),
+            _ => {}
+        }
+    }
+    pixels
+}
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDSD_LINEARSIZE: u32 = 0x80000;
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x400000;
+const DXGI_FORMAT_BC7_UNORM: u32 = 98;
+const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+
+impl RgbaImage {
+    fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y.min(self.height - 1) * self.width + x.min(self.width - 1)) as usize * 4;
+        self.rgba[i..i + 4].try_into().unwrap()
+    }
+
+    /// Next mip level: half the size (at least 1x1) with a 2x2 box filter.
+    fn half_size(&self) -> RgbaImage {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let samples = [
+                    self.pixel(2 * x, 2 * y),
+                    self.pixel(2 * x + 1, 2 * y),
+                    self.pixel(2 * x, 2 * y + 1),
+                    self.pixel(2 * x + 1, 2 * y + 1),
+                ];
+                for c in 0..4 {
+                    let sum: u32 = samples.iter().map(|s| s[c] as u32).sum();
+                    rgba.push(((sum + 2) / 4) as u8);
+                }
+            }
+        }
+        RgbaImage {
+            width,
+            height,
+            rgba,
+        }
+    }
+
+    /// BC7 DDS (DX10 header) with the full mip chain down to 1x1, like the textures of the game.
+    pub fn to_dds(&self) -> Vec<u8> {
+        assert!(self.width > 0 && self.height > 0, "DDS: empty image");
+        let mut levels = vec![self.clone()];
+        while levels.last().is_some_and(|l| l.width > 1 || l.height > 1) {
+            let next = levels.last().unwrap().half_size();
+            levels.push(next);
+        }
+        let format = DdsFormat::Bc7;
+
+        let mut dds = Vec::new();
+        let mut put = |v: u32| dds.extend_from_slice(&v.to_le_bytes());
+        put(u32::from_le_bytes(*DDS_MAGIC));
+        put(124);
+        put(DDSD_CAPS
+            | DDSD_HEIGHT
+            | DDSD_WIDTH
+            | DDSD_PIXELFORMAT
+            | DDSD_MIPMAPCOUNT
+            | DDSD_LINEARSIZE);
+        put(self.height);
+        put(self.width);
+        put(format.level_size(self.width, self.height) as u32);
+        put(1);
+        put(levels.len() as u32);
+        for _ in 0..11 {
+            put(0);
+        }
+        // pixel format
+        put(32);
+        put(DDPF_FOURCC);
+        put(u32::from_le_bytes(*b"DX10"));
+        for _ in 0..5 {
+            put(0);
+        }
+        put(DDSCAPS_COMPLEX | DDSCAPS_TEXTURE | DDSCAPS_MIPMAP);
+        for _ in 0..4 {
+            put(0);
+        }
+        // DX10 header
+        put(DXGI_FORMAT_BC7_UNORM);
+        put(D3D10_RESOURCE_DIMENSION_TEXTURE2D);
+        put(0);
+        put(1);
+        put(0);
+        debug_assert_eq!(dds.len(), HEADER_SIZE + DX10_HEADER_SIZE);
+
+        for level in levels.iter() {
+            for by in (0..level.height).step_by(4) {
+                for bx in (0..level.width).step_by(4) {
+                    let mut block = [[0u8; 4]; 16];
+                    for (i, pixel) in block.iter_mut().enumerate() {
+                        // pixels outside of the image repeat the border
+                        *pixel = level.pixel(bx + i as u32 % 4, by + i as u32 / 4);
+                    }
+                    dds.extend_from_slice(&encode_bc7_mode6(&block));
+                }
+            }
+        }
+        dds
+    }
+}
+
+/// BC7 mode 6 block: one subset, RGBA endpoints with 7 bits and a P-bit, 4 bit indices.
+struct Bc7Mode6 {
+    endpoints: [[u8; 4]; 2],
+    indices: [u8; 16],
+    error: u32,
+}
+
+impl Bc7Mode6 {
+    /// Rounds the endpoints to 7 bits plus the P-bit (shared by the channels of an endpoint) and
+    /// picks the best index of every pixel.
+    fn fit(endpoints: [[f32; 4]; 2], pixels: &Block) -> Self {
+        let quantised = endpoints.map(|e| {
+            (0..2u8)
+                .map(|p| {
+                    e.map(|c| ((((c - p as f32) / 2.0).round()).clamp(0.0, 127.0) as u8) * 2 + p)
+                })
+                .min_by_key(|q| {
+                    q.iter()
+                        .zip(e.iter())
+                        .map(|(&q, &c)| ((q as f32 - c) * (q as f32 - c) * 16.0) as u32)
+                        .sum::<u32>()
+                })
+                .unwrap()
+        });
+        let palette: Vec<[u8; 4]> = (0..16)
+            .map(|i| {
+                let mut color = [0; 4];
+                for (c, v) in color.iter_mut().enumerate() {
+                    *v = bc7_interpolate(quantised[0][c] as u32, quantised[1][c] as u32, i, 4);
+                }
+                color
+            })
+            .collect();
+        let mut indices = [0u8; 16];
+        let mut error = 0;
+        for (pixel, index) in pixels.iter().zip(indices.iter_mut()) {
+            let (i, e) = palette
+                .iter()
+                .map(|color| {
+                    (0..4)
+                        .map(|c| (color[c] as i32 - pixel[c] as i32).pow(2) as u32)
+                        .sum::<u32>()
+                })
+                .enumerate()
+                .min_by_key(|(_, e)| *e)
+                .unwrap();
+            *index = i as u8;
+            error += e;
+        }
+        Bc7Mode6 {
+            endpoints: quantised,
+            indices,
+            error,
+        }
+    }
+
+    fn to_block(&self) -> [u8; 16] {
+        let (mut endpoints, mut indices) = (self.endpoints, self.indices);
+        // the MSB of the anchor index (pixel 0) is implicitly 0
+        if indices[0] >= 8 {
+            endpoints.swap(0, 1);
+            indices = indices.map(|i| 15 - i);
+        }
+        let mut value: u128 = 1 << 6;
+        let mut pos = 7;
+        let mut put = |v: u32, n: u32| {
+            value |= (v as u128) << pos;
+            pos += n;
+        };
+        for c in 0..4 {
+            for e in endpoints.iter() {
+                put(e[c] as u32 >> 1, 7);
+            }
+        }
+        for e in endpoints.iter() {
+            put(e[0] as u32 & 1, 1);
+        }
+        for (i, &index) in indices.iter().enumerate() {
+            put(index as u32, if i == 0 { 3 } else { 4 });
+        }
+        debug_assert_eq!(pos, 128);
+        value.to_le_bytes()
+    }
+}
+
+fn encode_bc7_mode6(pixels: &Block) -> [u8; 16] {
+    let points: Vec<[f32; 4]> = pixels.iter().map(|p| p.map(|c| c as f32)).collect();
+    let mut mean = [0f32; 4];
+    for p in points.iter() {
+        for c in 0..4 {
+            mean[c] += p[c] / 16.0;
+        }
+    }
+    let mut covariance = [[0f32; 4]; 4];
+    for p in points.iter() {
+        for i in 0..4 {
+            for j in 0..4 {
+                covariance[i][j] += (p[i] - mean[i]) * (p[j] - mean[j]);
+            }
+        }
+    }
+    // principal axis by power iteration, starting from the channel with the largest variance
+    let channel = (0..4)
+        .max_by(|&a, &b| covariance[a][a].total_cmp(&covariance[b][b]))
+        .unwrap();
+    let mut axis = [0f32; 4];
+    axis[channel] = 1.0;
+    for _ in 0..8 {
+        let mut next = [0f32; 4];
+        for i in 0..4 {
+            next[i] = (0..4).map(|j| covariance[i][j] * axis[j]).sum();
+        }
+        let norm = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm < 1e-6 {
+            axis = [0.0; 4];
+            break;
+        }
+        axis = next.map(|v| v / norm);
+    }
+    let projections: Vec<f32> = points
+        .iter()
+        .map(|p| (0..4).map(|c| (p[c] - mean[c]) * axis[c]).sum())
+        .collect();
+    let t_min = projections.iter().copied().fold(f32::MAX, f32::min);
+    let t_max = projections.iter().copied().fold(f32::MIN, f32::max);
+    let endpoint = |t: f32| -> [f32; 4] {
+        let mut e = [0.0; 4];
+        for c in 0..4 {
+            e[c] = (mean[c] + axis[c] * t).clamp(0.0, 255.0);
+        }
+        e
+    };
+    let mut best = Bc7Mode6::fit([endpoint(t_min), endpoint(t_max)], pixels);
+
+    // least squares endpoints for the chosen indices
+    for _ in 0..2 {
+        if best.error == 0 {
+            break;
+        }
+        let (mut aa, mut ab, mut bb) = (0f32, 0f32, 0f32);
+        let (mut ap, mut bp) = ([0f32; 4], [0f32; 4]);
+        for (p, &i) in points.iter().zip(best.indices.iter()) {
+            let w = BC7_WEIGHTS4[i as usize] as f32 / 64.0;
+            aa += (1.0 - w) * (1.0 - w);
+            ab += (1.0 - w) * w;
+            bb += w * w;
+            for c in 0..4 {
+                ap[c] += (1.0 - w) * p[c];
+                bp[c] += w * p[c];
+            }
+        }
+        let det = aa * bb - ab * ab;
+        if det.abs() < 1e-6 {
+            break;
+        }
+        let mut endpoints = [[0f32; 4]; 2];
+        for c in 0..4 {
+            endpoints[0][c] = ((bb * ap[c] - ab * bp[c]) / det).clamp(0.0, 255.0);
+            endpoints[1][c] = ((aa * bp[c] - ab * ap[c]) / det).clamp(0.0, 255.0);
+        }
+        let refined = Bc7Mode6::fit(endpoints, pixels);
+        if refined.error >= best.error {
+            break;
+        }
+        best = refined;
+    }
+    best.to_block()
+}
+
+#[cfg(test)]
+mod tests_dds {
+    use super::*;
+
+    /// Mean absolute difference between mip level 1 and the 2x2 box filtered level 0.
+    fn mip_error(path: &str) -> f64 {
+        let data = fs::read(path).unwrap();
+        let dds = Dds::parse(&data);
+        let level0 = dds.decode_level(0);
+        let level1 = dds.decode_level(1);
+        let w0 = level0.width as usize;
+        let mut error = 0u64;
+        for y in 0..level1.height as usize {
+            for x in 0..level1.width as usize {
+                for c in 0..4 {
+                    let sum: u32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                        .iter()
+                        .map(|(dx, dy)| {
+                            level0.rgba[((2 * y + dy) * w0 + 2 * x + dx) * 4 + c] as u32
+                        })
+                        .sum();
+                    let filtered = (sum + 2) / 4;
+                    let actual = level1.rgba[(y * level1.width as usize + x) * 4 + c] as u32;
+                    error += (filtered as i64 - actual as i64).unsigned_abs();
+                }
+            }
+        }
+        error as f64 / level1.rgba.len() as f64
+    }
+
+    #[test]
+    fn bc7_tables() {
+        for p in 0..64 {
+            assert_eq!(bc7_subset(2, p, BC7_ANCHOR2[p] as usize), 1, "{}", p);
+            assert_eq!(bc7_subset(3, p, BC7_ANCHOR3[0][p] as usize), 1, "{}", p);
+            assert_eq!(bc7_subset(3, p, BC7_ANCHOR3[1][p] as usize), 2, "{}", p);
+            assert_eq!(bc7_subset(2, p, 0), 0);
+            assert_eq!(bc7_subset(3, p, 0), 0);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn decode_bc1() {
+        let data = fs::read("rdm/excavator_tycoons_diff_0.dds").unwrap();
+        let dds = Dds::parse(&data);
+        assert_eq!(dds.format, DdsFormat::Bc1);
+        assert_eq!((dds.width, dds.height), (1024, 1024));
+        let image = decode_dds(&data);
+        assert_eq!(image.rgba.len(), 1024 * 1024 * 4);
+        assert!(mip_error("rdm/excavator_tycoons_diff_0.dds") < 6.0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn decode_bc7() {
+        for path in [
+            "rdm/residence_02_05_diff_0.dds",
+            "rdm/residence_tier02_04_diff_0.dds",
+            "rdm/brick_wall_white_estate_01_diff_0.dds",
+        ] {
+            let data = fs::read(path).unwrap();
+            assert_eq!(Dds::parse(&data).format, DdsFormat::Bc7);
+            let error = mip_error(path);
+            assert!(error < 3.0, "{}: {}", path, error);
+        }
+        let image = decode_dds(&fs::read("rdm/residence_02_05_diff_0.dds").unwrap());
+        assert_eq!((image.width, image.height), (768, 768));
+    }
+
+    #[test]
+    fn decode_bc1_block() {
+        // red and blue endpoints, pixel i uses index i % 4
+        let block = [0x00, 0xF8, 0x1F, 0x00, 0xE4, 0xE4, 0xE4, 0xE4];
+        let pixels = decode_bc1_color(&block, false);
+        assert_eq!(pixels[0], [255, 0, 0, 255]);
+        assert_eq!(pixels[1], [0, 0, 255, 255]);
+        assert_eq!(pixels[2], [170, 0, 85, 255]);
+        assert_eq!(pixels[3], [85, 0, 170, 255]);
+    }
+
+    #[test]
+    fn decode_bc4_block() {
+        let block = [255, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(decode_bc4_channel(&block, false), [255; 16]);
+        // index 1 everywhere: a1
+        let block = [200, 100, 0x49, 0x92, 0x24, 0x49, 0x92, 0x24];
+        assert_eq!(decode_bc4_channel(&block, false), [100; 16]);
+        // signed -127..127 maps to 0..255
+        let block = [0x81, 0x7F, 0, 0, 0, 0, 0, 0];
+        assert_eq!(decode_bc4_channel(&block, true), [0; 16]);
+    }
+
+    #[test]
+    fn uncompressed() {
+        let mut data = vec![0u8; HEADER_SIZE];
+        data[0..4].copy_from_slice(DDS_MAGIC);
+        data[12..16].copy_from_slice(&1u32.to_le_bytes());
+        data[16..20].copy_from_slice(&2u32.to_le_bytes());
+        data[80..84].copy_from_slice(&(DDPF_RGB | DDPF_ALPHAPIXELS).to_le_bytes());
+        data[88..92].copy_from_slice(&32u32.to_le_bytes());
+        for (i, mask) in [0x00FF0000u32, 0x0000FF00, 0x000000FF, 0xFF000000]
+            .iter()
+            .enumerate()
+        {
+            data[92 + 4 * i..96 + 4 * i].copy_from_slice(&mask.to_le_bytes());
+        }
+        // BGRA in memory
+        data.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        let image = decode_dds(&data);
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.rgba, vec![3, 2, 1, 4, 7, 6, 5, 8]);
+
+        let png = image.to_png();
+        let (info, mut reader) = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        let mut decoded = vec![0; info.buffer_size()];
+        reader.next_frame(&mut decoded).unwrap();
+        assert_eq!(decoded, image.rgba);
+    }
+
+    fn mean_error(a: &RgbaImage, b: &RgbaImage) -> f64 {
+        assert_eq!((a.width, a.height), (b.width, b.height));
+        let sum: u64 = a
+            .rgba
+            .iter()
+            .zip(b.rgba.iter())
+            .map(|(&x, &y)| (x as i64 - y as i64).unsigned_abs())
+            .sum();
+        sum as f64 / a.rgba.len() as f64
+    }
+
+    #[test]
+    fn encode_bc7_block() {
+        let mut block = [[0u8; 4]; 16];
+        for (i, pixel) in block.iter_mut().enumerate() {
+            let v = (i * 17) as u8;
+            *pixel = [v, 255 - v, 128, 255];
+        }
+        let decoded = decode_block(DdsFormat::Bc7, &encode_bc7_mode6(&block));
+        for (a, b) in block.iter().zip(decoded.iter()) {
+            for c in 0..4 {
+                assert!((a[c] as i32 - b[c] as i32).abs() <= 4, "{:?} {:?}", a, b);
+            }
+        }
+        // solid colour with odd and even channels
+        let solid = [[37, 200, 1, 254]; 16];
+        let decoded = decode_block(DdsFormat::Bc7, &encode_bc7_mode6(&solid));
+        assert!(decoded
+            .iter()
+            .all(|p| (0..4).all(|c| (p[c] as i32 - solid[0][c] as i32).abs() <= 1)));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn encode_round_trip() {
+        let original = decode_dds(&fs::read("rdm/excavator_tycoons_diff_0.dds").unwrap());
+        // odd size to cover partial blocks and the mip chain down to 1x1
+        let width = 203;
+        let height = 75;
+        let mut rgba = Vec::new();
+        for y in 0..height {
+            let i = (y * original.width) as usize * 4;
+            rgba.extend_from_slice(&original.rgba[i..i + width as usize * 4]);
+        }
+        let image = RgbaImage {
+            width,
+            height,
+            rgba,
+        };
+        let data = image.to_dds();
+        let dds = Dds::parse(&data);
+        assert_eq!(dds.format, DdsFormat::Bc7);
+        assert_eq!((dds.width, dds.height, dds.mip_count), (203, 75, 8));
+        let block_count: usize = (0..8)
+            .map(|l| {
+                ((203 >> l).max(1) as usize).div_ceil(4) * ((75 >> l).max(1) as usize).div_ceil(4)
+            })
+            .sum();
+        assert_eq!(
+            data.len(),
+            HEADER_SIZE + DX10_HEADER_SIZE + 16 * block_count
+        );
+
+        let decoded = dds.decode_level(0);
+        let error = mean_error(&image, &decoded);
+        assert!(error < 2.0, "{}", error);
+        let error = mean_error(&image.half_size(), &dds.decode_level(1));
+        assert!(error < 2.0, "{}", error);
+        let last = dds.decode_level(7);
+        assert_eq!((last.width, last.height), (1, 1));
+    }
+}
@@ -1,12 +1,12 @@
-use crate::{rdm_material::RdMaterial, vertex::*, MeshInstance, RdJoint, RdModell};
+use crate::rdm_material::{MaterialImage, MaterialSlot};
+use crate::{axis::AxisConversion, rdm_anim::RdAnim, vertex::*, MeshInstance, RdJoint, RdModell};
 use gltf::{json, json::validation::Checked::Valid, mesh::Semantic};
 use std::{
     borrow::Cow,
     collections::HashMap,
     convert::TryInto,
-    env,
-    fs::{self, File, OpenOptions},
-    io::{self, Read, Write},
+    fs::{self, OpenOptions},
+    io::{self, Write},
     path::PathBuf,
     str::FromStr,
 };
@@ -38,12 +38,14 @@ struct RdGltfBuilder {
     rdm: RdModell,
     obj: RdGltf, // private
     skin: Option<json::Skin>,
-    anim_node: Option<json::Animation>,
+    anim_nodes: Vec<json::Animation>,
     material_idx: Option<Vec<u32>>,
     material_vec: Vec<json::Material>,
     texture_vec: Vec<json::Texture>,
     image_vec: Vec<json::Image>,
     sampler_vec: Vec<json::texture::Sampler>,
+    /// source of every image in `image_vec`
+    image_sources: Vec<MaterialImage>,
 }
 
 impl RdGltfBuilder {
@@ -58,23 +60,50 @@ impl RdGltfBuilder {
             obj: RdGltf::new(),
             skin: None,
             idx: None,
-            anim_node: None,
+            anim_nodes: Vec::new(),
             material_idx: None,
             material_vec: vec![],
             texture_vec: vec![],
             image_vec: vec![],
             sampler_vec: vec![],
+            image_sources: vec![],
         }
     }
 
-    pub fn put_rdm_anim(&mut self) {
+    pub fn put_rdm_anim(&mut self, anim: RdAnim) {
         // TODO: must not circumvent PushBufferResult
         let buffv_idx = self.buffers.len() as u32;
         let mut bv_idx = self.buffer_views.len() as u32;
         let mut acc_idx = self.accessors.len() as u32;
 
-        let anim = self.rdm.anim.clone().unwrap();
-        let anim_vec = anim.anim_vec.clone();
+        let p = self.rdm.joints.clone().unwrap();
+        let check = anim.check_skeleton(&p);
+        if !check.is_compatible() {
+            warn!(
+                "animation {:?} does not fully match the skeleton. Unmatched tracks are dropped: {}",
+                anim.name, check
+            );
+        } else if !check.missing.is_empty() {
+            info!("animation {:?}: {}", anim.name, check);
+        }
+
+        let mut modell_nodes = HashMap::new();
+        for (i, joint) in p.iter().enumerate() {
+            modell_nodes.insert(joint.name.clone(), i);
+        }
+
+        let anim_vec: Vec<_> = anim
+            .anim_vec
+            .into_iter()
+            .filter(|janim| modell_nodes.contains_key(&janim.name))
+            .collect();
+        if anim_vec.is_empty() {
+            warn!(
+                "animation {:?} has no track for a joint of the skeleton and is skipped",
+                anim.name
+            );
+            return;
+        }
 
         let mut size: usize = 0;
         for janim in &anim_vec {
@@ -100,36 +129,8 @@ impl RdGltfBuilder {
         let mut sampler_vec = Vec::new();
         let mut chanel_vec = Vec::new();
 
-        let p = self.rdm.joints.clone().unwrap();
-        let mut modell_nodes = HashMap::new();
-
-        for (i, joint) in p.iter().enumerate() {
-            modell_nodes.insert(joint.name.clone(), i);
-        }
-        for (_, janim) in anim_vec.iter().enumerate() {
-            let target_node_idx = match modell_nodes.get(&janim.name) {
-                Some(idx) => *idx as u32,
-                None => {
-                    warn!(
-                        "Could not find animation target {:?} in base model {:?}",
-                        &janim.name, p
-                    );
-                    //TODO: proper fix for unused animation targets.
-                    for _ in 0..janim.frames.len() {
-                        rot_anim_buf.put_u32_le(0xDEAD_BEEF);
-                        rot_anim_buf.put_u32_le(0xDEAD_BEEF);
-                        rot_anim_buf.put_u32_le(0xDEAD_BEEF);
-                        rot_anim_buf.put_u32_le(0xDEAD_BEEF);
-
-                        trans_anim_buf.put_u32_le(0xDEAD_BEEF);
-                        trans_anim_buf.put_u32_le(0xDEAD_BEEF);
-                        trans_anim_buf.put_u32_le(0xDEAD_BEEF);
-
-                        t_anim_buf.put_u32_le(0xDEAD_BEEF);
-                    }
-                    continue;
-                }
-            };
+        for janim in anim_vec.iter() {
+            let target_node_idx = modell_nodes[&janim.name] as u32;
 
             let count = janim.len as usize;
 
@@ -286,7 +287,7 @@ impl RdGltfBuilder {
             let rot_chanel = json::animation::Channel {
                 sampler: json::Index::new(rot_sampler_chanel),
                 target: json::animation::Target {
-                    node: json::Index::new(target_node_idx as u32),
+                    node: json::Index::new(target_node_idx),
                     path: Valid(json::animation::Property::Rotation),
                     extensions: None,
                     extras: None,
@@ -298,7 +299,7 @@ impl RdGltfBuilder {
             let trans_chanel = json::animation::Channel {
                 sampler: json::Index::new(trans_sampler_chanel),
                 target: json::animation::Target {
-                    node: json::Index::new(target_node_idx as u32),
+                    node: json::Index::new(target_node_idx),
                     path: Valid(json::animation::Property::Translation),
                     extensions: None,
                     extras: None,
@@ -350,7 +351,7 @@ impl RdGltfBuilder {
 
         self.accessors.append(&mut acc_vec);
 
-        self.anim_node = Some(anim_node);
+        self.anim_nodes.push(anim_node);
     }
 
     fn put_joint_weight(&mut self, normalise: bool) {
@@ -364,6 +365,7 @@ impl RdGltfBuilder {
             .count();
         let mut ibuffers = Vec::with_capacity(n);
         let mut wbuffers = Vec::with_capacity(n);
+
         for i in 0..n {
             if let Some(iter) = self.rdm.vertex.iter::<I4b, I4b>(i) {
                 let mut weight_buf =
@@ -483,17 +485,20 @@ impl RdGltfBuilder {
             }
         }
 
-        let buff_freezed = invbind_buf.freeze();
-        let mat_accessor_idx = self.put_attr(
-            BufferContainer::Bytes(buff_freezed),
-            json::accessor::Type::Mat4,
-            json::accessor::ComponentType::F32,
-            Some(joints_vec.len() as u32),
-            None,
-            None,
-            None,
-            None,
-        );
+        // a skin needs a mesh. Without one only the joint node hierarchy is written.
+        let has_mesh = self.rdm.has_mesh();
+        let mat_accessor_idx = has_mesh.then(|| {
+            self.put_attr(
+                BufferContainer::Bytes(invbind_buf.freeze()),
+                json::accessor::Type::Mat4,
+                json::accessor::ComponentType::F32,
+                Some(joints_vec.len() as u32),
+                None,
+                None,
+                None,
+                None,
+            )
+        });
 
         // Convert from
         // rdm: a child joint knows their parent to
@@ -566,7 +571,7 @@ impl RdGltfBuilder {
             skin_nodes.push(ijoint);
         }
 
-        self.skin = Some(json::Skin {
+        self.skin = mat_accessor_idx.map(|mat_accessor_idx| json::Skin {
             joints: {
                 // includes all nodes except the mesh root node
                 let nodes_count_excluding_root_node = skin_nodes.len() as u32;
@@ -587,12 +592,12 @@ impl RdGltfBuilder {
             extensions: None,
             extras: None,
             matrix: None,
-            mesh: Some(json::Index::new(0)),
+            mesh: has_mesh.then(|| json::Index::new(0)),
             name: Some(String::from("armature")),
             rotation: None,
             scale: None,
             translation: None,
-            skin: Some(json::Index::new(0)),
+            skin: has_mesh.then(|| json::Index::new(0)),
             weights: None,
         };
         skin_nodes.push(root_node);
@@ -667,57 +672,62 @@ impl RdGltfBuilder {
     fn put_material(&mut self) {
         let material_len = MeshInstance::get_max_material(&self.rdm.mesh_info) as usize + 1;
         // get_max_material returns the max value used to index the material vec
-        let mut texture_info_descriptors = vec![None; material_len];
-        if let Some(mats) = self.rdm.mat.as_ref() {
-            for (i, (image_path, dst)) in mats
-                .into_iter()
-                .zip(texture_info_descriptors.iter_mut())
-                .enumerate()
-            {
-                let sampler = Default::default();
-                self.sampler_vec.push(sampler);
-
-                let fname = image_path.file_stem().unwrap().to_str().unwrap();
-                let image = json::Image {
-                    uri: Some(format!("{}{}", fname, ".PNG")),
-                    buffer_view: None,
-                    mime_type: None,
-                    extensions: None,
-                    extras: None,
-                    name: None,
-                };
-                self.image_vec.push(image);
-
-                let texture = json::Texture {
-                    sampler: Some(json::Index::new(i as u32)),
-                    source: json::Index::new(i as u32),
-                    extensions: None,
-                    extras: None,
-                    name: None,
-                };
-                self.texture_vec.push(texture);
-
-                *dst = Some(gltf::json::texture::Info {
-                    index: json::Index::new(i as u32),
-                    tex_coord: 0,
-                    extensions: None,
-                    extras: None,
-                });
-            }
-        }
+        let slots: Vec<MaterialSlot> = match self.rdm.mat.as_ref() {
+            Some(mats) => mats.slots().to_vec(),
+            None => Vec::new(),
+        };
 
         let mut material_idx_vec = Vec::with_capacity(material_len);
-        assert!(self.material_vec.is_empty());
-        for itex in texture_info_descriptors {
+        for i in 0..material_len {
+            let slot = slots.get(i).cloned().unwrap_or_default();
+            let [r, g, b] = slot.base_color;
             let pbr = json::material::PbrMetallicRoughness {
-                base_color_texture: itex,
+                base_color_factor: json::material::PbrBaseColorFactor([r, g, b, 1.0]),
+                base_color_texture: slot.base_color_image().map(|image| self.put_texture(image)),
+                metallic_roughness_texture: slot
+                    .metallic_roughness_image()
+                    .map(|image| self.put_texture(image)),
                 ..Default::default()
             };
+            let normal_texture = slot.normal_image().map(|image| {
+                let info = self.put_texture(image);
+                json::material::NormalTexture {
+                    index: info.index,
+                    scale: 1.0,
+                    tex_coord: 0,
+                    extensions: None,
+                    extras: None,
+                }
+            });
+            let emissive_texture = slot.emissive_image().map(|image| self.put_texture(image));
+            // glTF limits the factor to 1, the glow of the game is brighter (e.g. 2.0)
+            let emissive_factor = match slot.emissive {
+                Some(color) => {
+                    let strength = color.iter().copied().fold(0.0, f32::max);
+                    if strength > 1.0 {
+                        self.obj
+                            .emissive_strengths
+                            .push((self.material_vec.len(), strength));
+                        color.map(|c| c.max(0.0) / strength)
+                    } else {
+                        color.map(|c| c.max(0.0))
+                    }
+                }
+                None => [0.0; 3],
+            };
 
             let map = json::Material {
                 alpha_cutoff: None,
-                alpha_mode: Valid(json::material::AlphaMode::Opaque),
+                alpha_mode: Valid(if slot.alpha_blend {
+                    json::material::AlphaMode::Blend
+                } else {
+                    json::material::AlphaMode::Opaque
+                }),
+                name: slot.name.clone(),
                 pbr_metallic_roughness: pbr,
+                normal_texture,
+                emissive_texture,
+                emissive_factor: json::material::EmissiveFactor(emissive_factor),
                 ..Default::default()
             };
 
@@ -729,33 +739,57 @@ impl RdGltfBuilder {
         self.material_idx = Some(material_idx_vec);
     }
 
-    fn run_dds(&mut self, embed_image_buffer: bool) {
-        if let Some(mats) = self.rdm.mat.as_ref() {
-            let dir = env::temp_dir();
-            mats.run_dds_converter(&dir);
+    /// Texture with its own sampler and image. Images used by several materials are shared.
+    fn put_texture(&mut self, image: MaterialImage) -> json::texture::Info {
+        let idx = match self.image_sources.iter().position(|i| *i == image) {
+            Some(idx) => idx,
+            None => {
+                self.sampler_vec.push(Default::default());
+                self.image_vec.push(json::Image {
+                    uri: Some(image.png_name()),
+                    buffer_view: None,
+                    mime_type: None,
+                    extensions: None,
+                    extras: None,
+                    name: None,
+                });
+                let idx = self.image_sources.len();
+                self.texture_vec.push(json::Texture {
+                    sampler: Some(json::Index::new(idx as u32)),
+                    source: json::Index::new(idx as u32),
+                    extensions: None,
+                    extras: None,
+                    name: None,
+                });
+                self.image_sources.push(image);
+                idx
+            }
+        };
+        json::texture::Info {
+            index: json::Index::new(idx as u32),
+            tex_coord: 0,
+            extensions: None,
+            extras: None,
+        }
+    }
 
+    /// Decodes the DDS textures to PNG: embedded as buffer views (glb) or written next to the glTF.
+    fn put_images(&mut self, embed_image_buffer: bool) {
+        for (image, source) in self.image_vec.iter_mut().zip(self.image_sources.iter()) {
+            let png = source.png();
             if embed_image_buffer {
-                let tmp_dir = env::temp_dir();
-                for e in self.image_vec.iter_mut() {
-                    let src_dds_file = tmp_dir.join(e.uri.as_ref().unwrap());
-                    let mut f = File::open(src_dds_file).unwrap();
-                    // prealloc 10 mebibytes for the png
-                    let mut buffer = Vec::with_capacity(10 * 1024 * 1024);
-
-                    // read the whole file
-                    f.read_to_end(&mut buffer).unwrap();
-
-                    e.uri = None;
-                    e.mime_type = Some(json::image::MimeType("image/png".to_string()));
-                    let buffer_view_idx = RdGltfBuilder::put_buffer_and_view(
-                        &mut self.obj,
-                        BufferContainer::U8(buffer),
-                        &mut self.buffers,
-                        &mut self.buffer_views,
-                        None,
-                    );
-                    e.buffer_view = Some(json::Index::new(buffer_view_idx));
-                }
+                image.uri = None;
+                image.mime_type = Some(json::image::MimeType("image/png".to_string()));
+                let buffer_view_idx = RdGltfBuilder::put_buffer_and_view(
+                    &mut self.obj,
+                    BufferContainer::U8(png),
+                    &mut self.buffers,
+                    &mut self.buffer_views,
+                    None,
+                );
+                image.buffer_view = Some(json::Index::new(buffer_view_idx));
+            } else {
+                self.obj.images.push((image.uri.clone().unwrap(), png));
             }
         }
     }
@@ -906,7 +940,7 @@ impl RdGltfBuilder {
         let mut accessor_idx_meshes = Vec::with_capacity(self.rdm.mesh_info.len());
         for submesh in self.rdm.mesh_info.iter() {
             let mut buff = BytesMut::with_capacity(submesh.index_count as usize);
-            let r = (submesh.start_index_location as usize / 3) as usize
+            let r = (submesh.start_index_location as usize / 3)
                 ..((submesh.start_index_location / 3) + submesh.index_count / 3) as usize;
             unsafe { buff.put_slice(self.rdm.triangle_indices[r].align_to::<u8>().1) }
             bytes.push((BufferContainer::Bytes(buff.freeze()), submesh.index_count));
@@ -927,12 +961,11 @@ impl RdGltfBuilder {
         self.idx = Some(accessor_idx_meshes);
     }
 
-    pub fn build(mut self) -> RdGltf {
-        let animation = self.anim_node.map_or(Default::default(), |n| vec![n]);
-
+    /// The glTF mesh of the current rdm: one primitive per MeshInstance.
+    fn take_mesh(&mut self) -> json::Mesh {
         // put_material must already have been run otherwise this panics!
-        let mats = self.material_idx.unwrap();
-        let indices_vec = self.idx.unwrap();
+        let mats = self.material_idx.take().unwrap_or_default();
+        let indices_vec = self.idx.take().unwrap_or_default();
         assert_eq!(indices_vec.len(), self.rdm.mesh_info.len());
 
         let mut triangle_vec = Vec::with_capacity(indices_vec.len());
@@ -949,13 +982,18 @@ impl RdGltfBuilder {
             triangle_vec.push(primitive);
         }
 
-        let mesh = json::Mesh {
+        json::Mesh {
             extensions: Default::default(),
             extras: Default::default(),
             name: None,
             primitives: triangle_vec,
             weights: None,
-        };
+        }
+    }
+
+    pub fn build(mut self) -> RdGltf {
+        let has_mesh = self.rdm.has_mesh();
+        let mesh = self.take_mesh();
 
         // if nodes vec is non empty than put_joint_nodes already added a scene root node to the end
         if self.nodes.is_empty() {
@@ -983,7 +1021,7 @@ impl RdGltfBuilder {
             accessors: self.accessors,
             buffers: self.buffers,
             buffer_views: self.buffer_views,
-            meshes: vec![mesh],
+            meshes: if has_mesh { vec![mesh] } else { vec![] },
             nodes: self.nodes,
             scene: Some(json::Index::new(0)),
             scenes: vec![json::Scene {
@@ -997,7 +1035,7 @@ impl RdGltfBuilder {
             } else {
                 Default::default()
             },
-            animations: animation,
+            animations: self.anim_nodes,
             materials: self.material_vec,
             textures: self.texture_vec,
             images: self.image_vec,
@@ -1010,6 +1048,80 @@ impl RdGltfBuilder {
         self.obj
     }
 
+    /// Puts the mesh of the current rdm (no skin) with its materials. Textures shared between
+    /// the models of a scene are only added once.
+    fn put_scene_mesh(&mut self) -> json::Mesh {
+        self.attr_map.clear();
+        self.put_vertex();
+        self.put_idx();
+        self.put_tex();
+        self.put_material();
+        self.put_normal();
+        self.put_tangent();
+        self.take_mesh()
+    }
+
+    /// Adds `node` and its children, `mesh_ids` yields the glTF mesh of every node in pre-order.
+    fn put_scene_node(
+        &mut self,
+        node: SceneNode,
+        mesh_ids: &mut impl Iterator<Item = Option<usize>>,
+    ) -> json::Index<json::Node> {
+        let mesh = mesh_ids.next().unwrap();
+        let children: Vec<json::Index<json::Node>> = node
+            .children
+            .into_iter()
+            .map(|child| self.put_scene_node(child, mesh_ids))
+            .collect();
+        self.nodes.push(json::Node {
+            camera: None,
+            children: if children.is_empty() {
+                None
+            } else {
+                Some(children)
+            },
+            extensions: Default::default(),
+            extras: Default::default(),
+            matrix: None,
+            mesh: mesh.map(|m| json::Index::new(m as u32)),
+            name: node.name,
+            rotation: Some(json::scene::UnitQuaternion(node.rotation)),
+            scale: Some(node.scale),
+            translation: Some(node.translation),
+            skin: None,
+            weights: None,
+        });
+        json::Index::new((self.nodes.len() - 1) as u32)
+    }
+
+    fn build_scene_root(
+        mut self,
+        meshes: Vec<json::Mesh>,
+        root: json::Index<json::Node>,
+    ) -> RdGltf {
+        let root = json::Root {
+            accessors: self.accessors,
+            buffers: self.buffers,
+            buffer_views: self.buffer_views,
+            meshes,
+            nodes: self.nodes,
+            scene: Some(json::Index::new(0)),
+            scenes: vec![json::Scene {
+                extensions: Default::default(),
+                extras: Default::default(),
+                name: None,
+                nodes: vec![root],
+            }],
+            materials: self.material_vec,
+            textures: self.texture_vec,
+            images: self.image_vec,
+            samplers: self.sampler_vec,
+            ..Default::default()
+        };
+        self.obj.root = Some(root);
+        self.obj
+    }
+
     fn merge_buffers(&mut self) {
         let size_merge_buffer = self
             .obj
@@ -1032,7 +1144,7 @@ impl RdGltfBuilder {
 
         for view in self.buffer_views.iter_mut() {
             let n = view_off_mapping[view.buffer.value()];
-            view.byte_offset = Some(view.byte_offset.unwrap_or(0) + n as u32);
+            view.byte_offset = Some(view.byte_offset.unwrap_or(0) + n);
             view.buffer = json::Index::new(0);
         }
 
@@ -1050,27 +1162,33 @@ impl RdGltfBuilder {
 }
 
 impl From<RdModell> for RdGltfBuilder {
-    fn from(rdm: RdModell) -> Self {
+    fn from(mut rdm: RdModell) -> Self {
         let has_skin = rdm.has_skin();
-        let has_anim = rdm.anim.is_some();
+        let anims = std::mem::take(&mut rdm.anim);
+
+        let has_mesh = rdm.has_mesh();
 
         let mut b = RdGltfBuilder::new(rdm);
 
-        b.put_vertex();
-        b.put_idx();
+        if has_mesh {
+            b.put_vertex();
+            b.put_idx();
 
-        b.put_tex();
-        b.put_material();
+            b.put_tex();
+            b.put_material();
 
-        b.put_normal();
-        b.put_tangent();
+            b.put_normal();
+            b.put_tangent();
+        }
 
         if has_skin {
             b.put_joint_nodes(JointOption::ResolveParentNode);
-            b.put_joint_weight(true);
+            if has_mesh {
+                b.put_joint_weight(true);
+            }
 
-            if has_anim {
-                b.put_rdm_anim();
+            for anim in anims {
+                b.put_rdm_anim(anim);
             }
         }
 
@@ -1079,9 +1197,22 @@ impl From<RdModell> for RdGltfBuilder {
 }
 
 pub fn build(rdm: RdModell, dir: Option<PathBuf>, create_new: bool, config: GltfExportFormat) {
-    let mat_opt = rdm.mat.clone();
+    build_with_axes(rdm, dir, create_new, config, &AxisConversion::default());
+}
+
+/// Like [`build`], but writes the glTF in the axis convention and unit of `axes`.
+pub fn build_with_axes(
+    mut rdm: RdModell,
+    dir: Option<PathBuf>,
+    create_new: bool,
+    config: GltfExportFormat,
+    axes: &AxisConversion,
+) {
+    if !axes.is_identity() {
+        rdm.transform_space(&axes.to_gltf());
+    }
     let mut b = RdGltfBuilder::from(rdm);
-    b.run_dds(config == GltfExportFormat::Glb);
+    b.put_images(config == GltfExportFormat::Glb);
     if config == GltfExportFormat::Glb || config == GltfExportFormat::GltfSeparateMinimise {
         b.merge_buffers();
         if config == GltfExportFormat::Glb {
@@ -1092,12 +1223,91 @@ pub fn build(rdm: RdModell, dir: Option<PathBuf>, create_new: bool, config: Gltf
     let p = b.build();
     info!("gltf build end");
     info!("write_gltf");
-    p.write_gltf(dir, mat_opt, create_new, config);
+    p.write_gltf(dir, create_new, config);
+}
+
+/// Node of a scene assembled from several rdm models, e.g. the models and files of a cfg.
+pub struct SceneNode {
+    pub name: Option<String>,
+    pub translation: [f32; 3],
+    /// Quaternion x, y, z, w.
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+    /// Static mesh of the node, skins and animations are not exported.
+    pub model: Option<RdModell>,
+    pub children: Vec<SceneNode>,
+}
+
+impl SceneNode {
+    pub fn new(name: Option<String>) -> Self {
+        SceneNode {
+            name,
+            translation: [0.0; 3],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: [1.0; 3],
+            model: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Takes the models of the node and its children in pre-order. Returns the model index of
+    /// every node, models without mesh are dropped.
+    fn take_models(&mut self, models: &mut Vec<RdModell>, ids: &mut Vec<Option<usize>>) {
+        ids.push(self.model.take().filter(RdModell::has_mesh).map(|model| {
+            models.push(model);
+            models.len() - 1
+        }));
+        for child in self.children.iter_mut() {
+            child.take_models(models, ids);
+        }
+    }
+}
+
+/// Writes a scene of rdm models as one glTF: every [`SceneNode`] becomes a node with its
+/// transform, every model a mesh with its materials. Nothing is written if no node has a
+/// model with a mesh.
+pub fn build_scene(
+    mut root: SceneNode,
+    dir: Option<PathBuf>,
+    create_new: bool,
+    config: GltfExportFormat,
+) -> Result<(), String> {
+    let mut models = Vec::new();
+    let mut mesh_ids = Vec::new();
+    root.take_models(&mut models, &mut mesh_ids);
+    let mut models = models.into_iter();
+    let first = models
+        .next()
+        .ok_or_else(|| "the scene has no model with a mesh".to_owned())?;
+
+    let mut b = RdGltfBuilder::new(first);
+    let mut meshes = vec![b.put_scene_mesh()];
+    for rdm in models {
+        b.rdm = rdm;
+        meshes.push(b.put_scene_mesh());
+    }
+    let root_idx = b.put_scene_node(root, &mut mesh_ids.into_iter());
+    info!("scene: {} nodes, {} meshes", b.nodes.len(), meshes.len());
+
+    b.put_images(config == GltfExportFormat::Glb);
+    if config == GltfExportFormat::Glb || config == GltfExportFormat::GltfSeparateMinimise {
+        b.merge_buffers();
+        if config == GltfExportFormat::Glb {
+            b.buffers[0].uri = None;
+        }
+    }
+    b.build_scene_root(meshes, root_idx)
+        .write_gltf(dir, create_new, config);
+    Ok(())
 }
 
 struct RdGltf {
     buffers: Vec<BufferContainer>,
+    /// PNG files (uri, data) written next to a .gltf.
+    images: Vec<(String, Vec<u8>)>,
     root: Option<json::Root>,
+    /// (material, strength) for KHR_materials_emissive_strength, which gltf-json does not model.
+    emissive_strengths: Vec<(usize, f32)>,
 }
 enum BufferContainer {
     U8(Vec<u8>),
@@ -1113,7 +1323,7 @@ impl BufferContainer {
     fn get_padded_added(&self) -> usize {
         let real_len = self.get_bytes_len_real();
         assert_ne!(real_len, 0);
-        if real_len % 4 == 0 {
+        if real_len.is_multiple_of(4) {
             0
         } else {
             4 - (real_len % 4)
@@ -1173,17 +1383,39 @@ impl RdGltf {
     fn new() -> Self {
         RdGltf {
             buffers: vec![],
+            images: vec![],
             root: None,
+            emissive_strengths: vec![],
+        }
+    }
+
+    const EMISSIVE_STRENGTH: &'static str = "KHR_materials_emissive_strength";
+
+    /// The root as JSON with the [`Self::emissive_strengths`] added.
+    fn json(&mut self, pretty: bool) -> Vec<u8> {
+        let mut root = self.root.take().unwrap();
+        if !self.emissive_strengths.is_empty() {
+            root.extensions_used
+                .push(Self::EMISSIVE_STRENGTH.to_owned());
+        }
+        let mut value = serde_json::to_value(&root).expect("Serialization error");
+        for &(material, strength) in self.emissive_strengths.iter() {
+            let material = value["materials"][material].as_object_mut().unwrap();
+            let extensions = material
+                .entry("extensions")
+                .or_insert_with(|| serde_json::json!({}));
+            extensions[Self::EMISSIVE_STRENGTH] =
+                serde_json::json!({ "emissiveStrength": strength });
+        }
+        if pretty {
+            serde_json::to_vec_pretty(&value)
+        } else {
+            serde_json::to_vec(&value)
         }
+        .expect("Serialization error")
     }
 
-    fn write_gltf(
-        self,
-        dir: Option<PathBuf>,
-        optmat: Option<RdMaterial>,
-        create_new: bool,
-        config: GltfExportFormat,
-    ) {
+    fn write_gltf(mut self, dir: Option<PathBuf>, create_new: bool, config: GltfExportFormat) {
         let mut file = dir.unwrap_or_else(|| {
             let f = PathBuf::from("gltf_out");
             let _ = fs::create_dir(&f);
@@ -1216,7 +1448,7 @@ impl RdGltf {
                     version: 2,
                     length: 0xDEAD_BEEF,
                 };
-                let j = json::serialize::to_vec(&self.root.unwrap()).expect("Serialization error");
+                let j = self.json(false);
                 let glb = gltf::Glb {
                     header,
                     json: Cow::from(&j),
@@ -1227,8 +1459,7 @@ impl RdGltf {
                 debug!("bin: {}", &self.buffers[0].get_bytes_len_padded());
             }
             _ => {
-                let vjson = json::serialize::to_vec_pretty(&self.root.unwrap())
-                    .expect("Serialization error");
+                let vjson = self.json(true);
                 writer.write_all(&vjson).expect("I/O error");
 
                 debug!("wrote json to disk!");
@@ -1250,17 +1481,17 @@ impl RdGltf {
                     bin.to_writer(&mut writer).unwrap();
                 }
 
-                // copy converted png from tmp to dest
-                if let Some(mat) = optmat.as_ref() {
-                    let tmp_dir = env::temp_dir();
-                    for e in mat.into_iter() {
-                        let mut src = tmp_dir.join(e.file_stem().unwrap());
-                        src.set_extension("PNG");
-                        let mut dst_file = udir.join(e.file_stem().unwrap());
-                        dst_file.set_extension("PNG");
-                        debug!("copy: {:?} to {:?}", &src, &dst_file);
-                        fs::copy(src, &dst_file).unwrap();
-                    }
+                for (uri, png) in self.images {
+                    let file_path = udir.join(uri);
+                    debug!("write_all {:?}", &file_path);
+                    let mut writer = OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .create_new(create_new)
+                        .open(&file_path)
+                        .expect("I/O error");
+                    writer.write_all(&png).expect("I/O error");
                 }
             }
         }
@@ -1,3 +1,5 @@
+use crate::axis::AxisConversion;
+use crate::dds::RgbaImage;
 use crate::vertex::*;
 use crate::{rdm_writer::PutVertex, RdJoint};
 use crate::{vertex::TargetVertexFormat, Triangle};
@@ -15,18 +17,15 @@ use crate::rdm_anim::*;
 use gltf::animation::util::ReadOutputs::*;
 
 use crate::VertexFormat2;
-use std::collections::hash_map::DefaultHasher;
 use std::collections::HashSet;
-use std::hash::{Hash, Hasher};
 use std::path::Path;
 use std::str::FromStr;
-use std::u16;
 use std::{
     collections::HashMap,
     convert::{TryFrom, TryInto},
 };
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ResolveNodeName {
     UnstableIndex,
     UniqueName,
@@ -47,11 +46,116 @@ impl FromStr for ResolveNodeName {
 pub struct ImportedGltf {
     gltf: gltf::Document,
     buffers: Vec<gltf::buffer::Data>,
+    images: Vec<gltf::image::Data>,
     pub name_setting: ResolveNodeName,
+    /// Drops skin joints that neither influence a vertex of the mesh nor are an ancestor of such a joint.
+    pub prune_joints: bool,
+    /// Converts every mesh node of the scene: mesh and animated nodes become joints and the
+    /// vertices of a mesh are bound rigidly to the joint of their node (no skin needed).
+    pub rigid_nodes: bool,
+    /// Converts every mesh node of the scene into one static model: the world transform of each node
+    /// is baked into its vertices and the glTF materials become the material slots.
+    pub merge_scene: bool,
+    /// glTF material name -> rdm material index. Primitives get the material slot of their material
+    /// name instead of their position, see [`MaterialMap`].
+    pub material_map: Option<MaterialMap>,
+    /// Merges vertices whose packed (quantised) data is byte-identical, within and across primitives.
+    pub weld: bool,
+    /// Axis convention and unit of the glTF file, converted into rdm space on import.
+    pub axes: AxisConversion,
     mesh_idx: u32,
     mesh_node: u32,
 }
 
+/// Texture of a glTF material, see [`ImportedGltf::material_textures`].
+pub struct MaterialTexture {
+    /// e.g. `body_diff_0.dds`
+    pub file_name: String,
+    pub image: RgbaImage,
+}
+
+impl MaterialTexture {
+    /// BC7 DDS with the full mip chain.
+    pub fn to_dds(&self) -> Vec<u8> {
+        self.image.to_dds()
+    }
+}
+
+/// Replaces characters that are not alphanumeric, `-`, `_` or `.` by `_` to get a file name.
+fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Converts a decoded glTF image to RGBA8. 16 bit channels keep their high byte, two channel
+/// images are luminance and alpha.
+fn rgba_image(image: &gltf::image::Data) -> RgbaImage {
+    use gltf::image::Format;
+    // (channels, bytes per channel, swap red and blue)
+    let (channels, size, bgr) = match image.format {
+        Format::R8 => (1, 1, false),
+        Format::R8G8 => (2, 1, false),
+        Format::R8G8B8 => (3, 1, false),
+        Format::R8G8B8A8 => (4, 1, false),
+        Format::B8G8R8 => (3, 1, true),
+        Format::B8G8R8A8 => (4, 1, true),
+        Format::R16 => (1, 2, false),
+        Format::R16G16 => (2, 2, false),
+        Format::R16G16B16 => (3, 2, false),
+        Format::R16G16B16A16 => (4, 2, false),
+    };
+    let mut rgba = Vec::with_capacity(image.width as usize * image.height as usize * 4);
+    for pixel in image.pixels.chunks_exact(channels * size) {
+        // little endian: the high byte is the last one of a channel
+        let channel = |c: usize| pixel[c * size + size - 1];
+        let mut color = match channels {
+            1 => [channel(0), channel(0), channel(0), 255],
+            2 => [channel(0), channel(0), channel(0), channel(1)],
+            3 => [channel(0), channel(1), channel(2), 255],
+            _ => [channel(0), channel(1), channel(2), channel(3)],
+        };
+        if bgr {
+            color.swap(0, 2);
+        }
+        rgba.extend_from_slice(&color);
+    }
+    RgbaImage {
+        width: image.width,
+        height: image.height,
+        rgba,
+    }
+}
+
+/// `source` if it has the size of the `image` its channel is packed into.
+fn channel_source<'a>(
+    material: &str,
+    source: &'a Option<RgbaImage>,
+    what: &str,
+    image: &RgbaImage,
+) -> Option<&'a RgbaImage> {
+    source.as_ref().filter(|s| {
+        let same_size = (s.width, s.height) == (image.width, image.height);
+        if !same_size {
+            warn!(
+                "material {}: {} image is {}x{}, not {}x{}, skipped",
+                material, what, s.width, s.height, image.width, image.height
+            );
+        }
+        same_size
+    })
+}
+
+/// glTF material name -> rdm material index, see [`ImportedGltf::material_map`].
+/// Can be loaded from a JSON object like `{ "body": 0, "glass": 1 }` with [`crate::load_json_map`].
+pub type MaterialMap = HashMap<String, u32>;
+
 impl<'a> TryFrom<&'a Path> for ImportedGltf {
     type Error = gltf::Error;
     fn try_from(f_path: &'a Path) -> Result<ImportedGltf, gltf::Error> {
@@ -61,16 +165,146 @@ impl<'a> TryFrom<&'a Path> for ImportedGltf {
 
 fn node_get_local_transform(target_node: &Node) -> Isometry3<f32> {
     let target_mat = target_node.transform().matrix();
-    let mut mat = Matrix4::from_fn(|i, j| target_mat[j][i]);
-    mat.m44 = 1.0;
-    let similarity: Similarity3<f32> = nalgebra::try_convert(mat).unwrap();
-    debug!("similarity.scaling: {}", similarity.scaling());
+    // negative scale factors are re-applied by `read_animation` (see `node_scale_signs`)
+    let mat = Matrix4::from_fn(|i, j| target_mat[j][i]) * node_scale_signs(target_node);
+    let name = format!("node {:?}", target_node.name().unwrap_or("unnamed"));
+    let (mut isometry, scaling) = decompose_uniform_scale(mat, &name);
+    debug!("{} scaling: {}", name, scaling);
 
-    let mut isometry: Isometry3<f32> = similarity.isometry;
     isometry.rotation = isometry.rotation.inverse();
     isometry
 }
 
+/// Signs of the node scale as diagonal matrix, e.g. diag(-1, 1, 1, 1) for a node mirrored on x.
+fn node_scale_signs(node: &Node) -> Matrix4<f32> {
+    let (_, _, scale) = node.transform().decomposed();
+    Matrix4::new_nonuniform_scaling(&Vector3::from(
+        scale.map(|s| if s < 0.0 { -1.0 } else { 1.0 }),
+    ))
+}
+
+/// rdm joints cannot be mirrored. A joint with a mirrored global transform G is stored as G·F
+/// with F mirroring the x axis, relative transforms between two such joints stay the same.
+fn unmirror(mat: Matrix4<f32>) -> (Matrix4<f32>, bool) {
+    if mat.determinant() < 0.0 {
+        (mat * mirror_x(), true)
+    } else {
+        (mat, false)
+    }
+}
+
+fn mirror_x() -> Matrix4<f32> {
+    Matrix4::new_nonuniform_scaling(&Vector3::new(-1.0, 1.0, 1.0))
+}
+
+/// Rotation and translation of `mat`, the (non-uniform) scale along its axes is removed.
+fn rigid_part(mut mat: Matrix4<f32>) -> Matrix4<f32> {
+    for c in 0..3 {
+        let norm = mat.fixed_slice::<3, 1>(0, c).norm();
+        mat.fixed_slice_mut::<3, 1>(0, c).unscale_mut(norm);
+    }
+    mat
+}
+
+/// Key frames of one animated node property (translation, rotation or scale).
+struct KeyTrack {
+    times: Vec<f32>,
+    values: Vec<[f32; 4]>,
+    step: bool,
+}
+
+impl KeyTrack {
+    fn sample(&self, t: f32, rotation: bool) -> [f32; 4] {
+        let k = self.times.partition_point(|&time| time <= t);
+        if k == 0 {
+            return self.values[0];
+        }
+        let (a, t0) = (self.values[k - 1], self.times[k - 1]);
+        if k == self.times.len() || self.step {
+            return a;
+        }
+        let (b, t1) = (self.values[k], self.times[k]);
+        let s = (t - t0) / (t1 - t0);
+        if rotation {
+            let q = |r: [f32; 4]| {
+                UnitQuaternion::from_quaternion(Quaternion::new(r[3], r[0], r[1], r[2]))
+            };
+            let (qa, qb) = (q(a), q(b));
+            let r = qa.try_slerp(&qb, s, 1.0e-6).unwrap_or(qa).coords;
+            [r.x, r.y, r.z, r.w]
+        } else {
+            [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * s)
+        }
+    }
+}
+
+#[derive(Default)]
+struct NodeTracks {
+    translation: Option<KeyTrack>,
+    rotation: Option<KeyTrack>,
+    scale: Option<KeyTrack>,
+}
+
+impl NodeTracks {
+    /// Local transform of `node` at time `t`, properties without track keep the node value.
+    fn local(&self, node: &Node, t: f32) -> Matrix4<f32> {
+        let (translation, rotation, scale) = node.transform().decomposed();
+        let translation = self.translation.as_ref().map_or(translation, |k| {
+            let v = k.sample(t, false);
+            [v[0], v[1], v[2]]
+        });
+        let r = self
+            .rotation
+            .as_ref()
+            .map_or(rotation, |k| k.sample(t, true));
+        let scale = self.scale.as_ref().map_or(scale, |k| {
+            let v = k.sample(t, false);
+            [v[0], v[1], v[2]]
+        });
+        Translation3::from(Vector3::from(translation)).to_homogeneous()
+            * UnitQuaternion::from_quaternion(Quaternion::new(r[3], r[0], r[1], r[2]))
+                .to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&Vector3::from(scale))
+    }
+}
+
+/// Splits a joint/node matrix into rotation + translation and a uniform scale.
+/// rdm joints cannot carry scale, so non-uniform or mirroring scale is rejected naming `what`.
+fn decompose_uniform_scale(mut mat: Matrix4<f32>, what: &str) -> (Isometry3<f32>, f32) {
+    mat.m44 = 1.0;
+    let linear: Matrix3<f32> = mat.fixed_resize::<3, 3>(0.0);
+    let scale = Vector3::new(
+        linear.column(0).norm(),
+        linear.column(1).norm(),
+        linear.column(2).norm(),
+    );
+    let uniform = scale.mean();
+    if (scale - Vector3::repeat(uniform)).amax() > 1.0e-3 * uniform {
+        panic!(
+            "{} has non-uniform scale [{}, {}, {}]. rdm joints only support uniform scale, apply the scale to the rig before exporting the glTF.",
+            what, scale.x, scale.y, scale.z
+        );
+    }
+    if linear.determinant() < 0.0 {
+        panic!(
+            "{} has a negative (mirroring) scale. rdm joints only support positive uniform scale, apply the scale to the rig before exporting the glTF.",
+            what
+        );
+    }
+    // `Rotation3::from_matrix` starts its iteration at the identity and gets stuck for
+    // rotations by ~180°, so start at the (not quite orthonormal) matrix itself.
+    let linear = linear / uniform;
+    let guess = UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(linear));
+    let rotation = UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_eps(
+        &linear,
+        f32::EPSILON,
+        0,
+        guess.to_rotation_matrix(),
+    ));
+    let translation = Translation3::new(mat.m14, mat.m24, mat.m34);
+    (Isometry3::from_parts(translation, rotation), uniform)
+}
+
 fn extract_rotations(
     time: impl Iterator<Item = f32>,
     origin_translation: [f32; 3],
@@ -162,11 +396,18 @@ impl<'a> ImportedGltf {
         joint_name_src: ResolveNodeName,
     ) -> Result<ImportedGltf, gltf::Error> {
         info!("gltf::import start!");
-        let (gltf, buffers, _) = gltf::import(f_path)?;
+        let (gltf, buffers, images) = gltf::import(f_path)?;
         let mut res = Self {
             gltf,
             buffers,
+            images,
             name_setting: joint_name_src,
+            prune_joints: false,
+            rigid_nodes: false,
+            merge_scene: false,
+            material_map: None,
+            weld: false,
+            axes: AxisConversion::default(),
             mesh_idx: 0,
             mesh_node: 0,
         };
@@ -180,6 +421,33 @@ impl<'a> ImportedGltf {
         self.set_mesh_node();
     }
 
+    /// Indices of all meshes that are instantiated by a node. Meshes without node are skipped.
+    pub fn instantiated_meshes(&self) -> Vec<u32> {
+        self.gltf
+            .meshes()
+            .filter(|mesh| {
+                let used = find_first_mesh_instantiating_node(&self.gltf, mesh.index()).is_some();
+                if !used {
+                    warn!("mesh #{} is not used by any node, skipped", mesh.index());
+                }
+                used
+            })
+            .map(|mesh| mesh.index() as u32)
+            .collect()
+    }
+
+    /// File name for the current mesh: its name, else the name of its node, else `mesh_<index>`.
+    pub fn mesh_name(&self) -> String {
+        let mesh = self.gltf.meshes().nth(self.mesh_idx as usize).unwrap();
+        let node = self.gltf.nodes().nth(self.mesh_node as usize).unwrap();
+        let name = mesh
+            .name()
+            .or_else(|| node.name())
+            .map(str::to_owned)
+            .unwrap_or_else(|| format!("mesh_{}", self.mesh_idx));
+        sanitize_name(&name)
+    }
+
     fn set_mesh_node(&mut self) {
         let mesh = self
             .gltf
@@ -228,13 +496,73 @@ impl<'a> ImportedGltf {
         joints: &[RdJoint],
         frames: usize,
         _tmax: f32,
+        no_transform: bool,
     ) -> Option<Vec<RdAnim>> {
+        if self.rigid_nodes {
+            let mut rd_animations = self.read_node_animations(joints);
+            self.convert_axes(&mut rd_animations);
+            return Some(rd_animations);
+        }
         let (gltf, buffers) = (&self.gltf, &self.buffers);
 
         let mut translation_map: HashMap<String, Vec<Frame>> = HashMap::new();
         let mut rd_animations = Vec::new();
 
         let real_joints: HashSet<_> = joints.iter().map(|e| e.name.as_str()).collect();
+        let base = self.mesh_node_transform(no_transform);
+        let mut translation_scale = self.joint_translation_scale(&base);
+        let binds = self.joint_bind_matrices(&base);
+
+        let joint_nodes: HashMap<&str, Node> = joints
+            .iter()
+            .map(|j| {
+                let node = gltf
+                    .nodes()
+                    .find(|n| self.node_get_name(n) == j.name)
+                    .unwrap();
+                (j.name.as_str(), node)
+            })
+            .collect();
+        let joint_scale_signs: HashMap<&str, Matrix4<f32>> = joint_nodes
+            .iter()
+            .map(|(name, node)| (*name, node_scale_signs(node)))
+            .collect();
+        // same test as `create_joint`, promoted (non skin) nodes use their global transform
+        let mirrored_joints: HashSet<&str> = joint_nodes
+            .iter()
+            .filter(|(name, node)| {
+                let bind = binds
+                    .get(**name)
+                    .copied()
+                    .unwrap_or_else(|| build_transform2(gltf, node.index()));
+                unmirror(bind).1
+            })
+            .map(|(name, _)| *name)
+            .collect();
+        if !mirrored_joints.is_empty() {
+            info!(
+                "{} joints have a mirrored transform, mirroring their animation tracks",
+                mirrored_joints.len()
+            );
+        }
+
+        // root joints are animated relative to their glTF parent node. When the node transforms
+        // are applied (binds are in world space) their tracks have to be in world space as well.
+        let root_parent_transforms: HashMap<&str, Matrix4<f32>> = if no_transform {
+            HashMap::new()
+        } else {
+            joints
+                .iter()
+                .filter(|j| j.parent == 255)
+                .map(|j| {
+                    let node = &joint_nodes[j.name.as_str()];
+                    (j.name.as_str(), node_parent_transform(gltf, node))
+                })
+                .collect()
+        };
+        for name in root_parent_transforms.keys() {
+            translation_scale.remove(*name);
+        }
 
         let interpolate_error_message = "Interpolate required but not supported ! Re-Export model in blender with 'Always sample animations' enabled and try again";
 
@@ -243,7 +571,7 @@ impl<'a> ImportedGltf {
             let mut interpolate_channel: HashMap<String, (Vec<Frame>, Vec<Frame>)> = HashMap::new();
 
             debug!("animation: {}", animation.name().unwrap_or("default"));
-            for (_, channel) in animation.channels().enumerate() {
+            for channel in animation.channels() {
                 let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
                 let time = reader.read_inputs().unwrap();
                 let output = reader.read_outputs().unwrap();
@@ -265,7 +593,7 @@ impl<'a> ImportedGltf {
                         .unwrap()
                         .as_array()
                         .unwrap()
-                        .get(0)
+                        .first()
                         .unwrap()
                         .as_f64()
                         .unwrap(),
@@ -347,6 +675,7 @@ impl<'a> ImportedGltf {
             }
 
             // TODO: finish interpolate
+            #[allow(clippy::never_loop)]
             for (name, (t, mut r)) in interpolate_channel.drain() {
                 let max_time_t = t.iter().map(|f| f.time).reduce(f32::max).unwrap();
                 let max_time_r = r.iter().map(|f| f.time).reduce(f32::max).unwrap();
@@ -391,6 +720,44 @@ impl<'a> ImportedGltf {
                 }
             }
 
+            for (name, frames) in translation_map.iter_mut() {
+                if let Some(k) = translation_scale.get(name) {
+                    for f in frames.iter_mut() {
+                        f.translation = f.translation.map(|t| t * k);
+                    }
+                }
+            }
+
+            for joint in joints {
+                let parent = root_parent_transforms.get(joint.name.as_str());
+                let signs = joint_scale_signs[joint.name.as_str()];
+                let parent_mirrored = joint.parent != 255
+                    && mirrored_joints.contains(joints[joint.parent as usize].name.as_str());
+                let mirrored = mirrored_joints.contains(joint.name.as_str());
+                if parent.is_none() && signs == Matrix4::identity() && !parent_mirrored && !mirrored
+                {
+                    continue;
+                }
+                for f in translation_map.get_mut(&joint.name).unwrap().iter_mut() {
+                    let local = Isometry3::from_parts(
+                        Translation3::from(Vector3::from(f.translation)),
+                        f.unit_rotation(),
+                    );
+                    let mut mat = parent.copied().unwrap_or_else(Matrix4::identity)
+                        * local.to_homogeneous()
+                        * signs;
+                    if parent_mirrored {
+                        mat = mirror_x() * mat;
+                    }
+                    if mirrored {
+                        mat *= mirror_x();
+                    }
+                    let (local, _) =
+                        decompose_uniform_scale(mat, &format!("joint {:?}", joint.name));
+                    *f = Frame::from_local(&local, f.time);
+                }
+            }
+
             let mut frame_collections: Vec<FrameCollection> = Vec::new();
             for (node_str, frames) in translation_map.drain() {
                 frame_collections.push(FrameCollection {
@@ -409,9 +776,20 @@ impl<'a> ImportedGltf {
                 name,
             });
         }
+        self.convert_axes(&mut rd_animations);
         Some(rd_animations)
     }
 
+    /// Moves animations read from the glTF into rdm space, see [`ImportedGltf::axes`].
+    fn convert_axes(&self, rd_animations: &mut [RdAnim]) {
+        if !self.axes.is_identity() {
+            let m = self.axes.to_rdm();
+            for anim in rd_animations.iter_mut() {
+                anim.transform_space(&m);
+            }
+        }
+    }
+
     pub fn gltf_to_rdm(
         &self,
         dst_format: TargetVertexFormat,
@@ -424,6 +802,19 @@ impl<'a> ImportedGltf {
             warn!("negative_x_and_v0v2v1: {}", negative_x_and_v0v2v1);
             warn!("negative_x_and_v0v2v1 may cause lighting artifacts !");
         }
+        let has_joint_indices = dst_format.max_influences() > 0;
+        if self.merge_scene {
+            assert!(
+                !load_skin && !self.rigid_nodes,
+                "merge scene makes a static model, it can not be combined with skins or rigid nodes"
+            );
+        }
+        if self.rigid_nodes {
+            assert!(
+                has_joint_indices,
+                "rigid nodes need a vertex format with joint indices (I4b)"
+            );
+        }
         let gltf_imp = self
             .read_mesh(
                 dst_format,
@@ -439,16 +830,19 @@ impl<'a> ImportedGltf {
 
         let triangles_idx_count = triangles.len() as u32 * 3;
 
-        let joints_vec = if load_skin {
+        let joints_vec = if self.rigid_nodes {
+            self.check_node_name_uniqueness();
+            Some(self.read_rigid_joints())
+        } else if load_skin {
             self.check_node_name_uniqueness();
-            Some(self.read_skin())
+            Some(self.read_skin(&self.mesh_node_transform(no_transform)))
         } else {
             None
         };
 
         // todo!("TODO : FIX ME !!!");
         let mesh_info_vec = gltf_imp.4;
-        RdModell {
+        let mut rdm = RdModell {
             size,
             buffer: Bytes::new(),
             mesh_info: mesh_info_vec,
@@ -460,12 +854,27 @@ impl<'a> ImportedGltf {
             triangles_offset: None,
             triangles_idx_count,
 
-            anim: None,
+            anim: Vec::new(),
             mat: None,
+        };
+
+        if !self.axes.is_identity() {
+            rdm.transform_space(&self.axes.to_rdm());
+        }
+        rdm
+    }
+
+    /// Global transform of the mesh node that gets applied to the vertices, joints and root tracks.
+    fn mesh_node_transform(&self, no_transform: bool) -> Matrix4<f32> {
+        if no_transform {
+            Matrix4::identity()
+        } else {
+            build_transform2(&self.gltf, self.mesh_node.try_into().unwrap())
         }
     }
 
-    fn read_skin(&self) -> Vec<RdJoint> {
+    /// `base`: mesh node transform, the inverse bind matrices are relative to the mesh.
+    fn read_skin(&self, base: &Matrix4<f32>) -> Vec<RdJoint> {
         let mut out_joints_vec = Vec::new();
         let node_with_skin = self.gltf.nodes().nth(self.mesh_node.try_into().unwrap());
 
@@ -480,24 +889,24 @@ impl<'a> ImportedGltf {
             }
 
             debug!("{:?}", node_names_vec);
-            // parentless nodes have 255 as "index"
-            let mut node_vec: Vec<u8> = vec![255; skin.joints().count()];
-
-            for (i, node) in skin.joints().enumerate() {
-                for child in node.children() {
-                    //rdm: children know their parent VS glTF parents know their children
-                    let c_name = self.node_get_name(&child);
-
-                    let child_idx = node_names_vec.iter().position(|r| r == &c_name).unwrap();
-                    node_vec[child_idx] = i as u8;
-                    debug!("{}: {} -> {}", c_name, i, node_names_vec[i]);
-                }
+            let remap = self.skin_joint_remap(&skin);
+            if self.prune_joints {
+                info!(
+                    "prune joints: kept {} of {} skin joints",
+                    remap.iter().flatten().count(),
+                    remap.len()
+                );
             }
+            // parentless nodes have 255 as "index"
+            let node_vec: Vec<u8> = self
+                .skin_parents(&skin)
+                .into_iter()
+                .map(|p| p.and_then(|p| remap[p]).unwrap_or(255))
+                .collect();
 
             debug!("node_vec: {:?}", node_vec);
             let node_vec_iter = node_vec.into_iter();
             let node_names_vec_iter = node_names_vec.into_iter();
-
             let reader = skin.reader(|buffer| Some(&self.buffers[buffer.index()]));
 
             let mats_iter = reader.read_inverse_bind_matrices().unwrap();
@@ -505,11 +914,12 @@ impl<'a> ImportedGltf {
                 .zip(node_vec_iter)
                 .zip(node_names_vec_iter)
                 .enumerate()
+                .filter(|(z, _)| remap[*z].is_some())
             {
                 let inverse_bind_matrix: Matrix4<f32> = Matrix4::from_fn(|i, j| mat[j][i]);
                 // inverseBindMatrix^-1 = BindMatrix
                 // BindMatrix: global transform of the respective joint
-                let mat4_init: Matrix4<f32> = inverse_bind_matrix.try_inverse().unwrap();
+                let mat4_init: Matrix4<f32> = base * inverse_bind_matrix.try_inverse().unwrap();
                 debug!("{} mat4_init: {}", z, mat4_init);
                 out_joints_vec.push(create_joint(mat4_init, name, parent));
             }
@@ -521,6 +931,359 @@ impl<'a> ImportedGltf {
         out_joints_vec
     }
 
+    fn scene(&self) -> gltf::Scene<'_> {
+        self.gltf
+            .default_scene()
+            .or_else(|| self.gltf.scenes().next())
+            .expect("glTF has no scene")
+    }
+
+    /// Every mesh node of the scene (depth first) for `merge_scene`.
+    fn scene_mesh_nodes(&self) -> Vec<(gltf::Mesh<'_>, usize, Option<u16>)> {
+        fn visit<'a>(node: Node<'a>, out: &mut Vec<(gltf::Mesh<'a>, usize, Option<u16>)>) {
+            if let Some(mesh) = node.mesh() {
+                out.push((mesh, node.index(), None));
+            }
+            for child in node.children() {
+                visit(child, out);
+            }
+        }
+        let mut out = Vec::new();
+        for node in self.scene().nodes() {
+            visit(node, &mut out);
+        }
+        for (_, node_idx, _) in out.iter() {
+            let node = self.gltf.nodes().nth(*node_idx).unwrap();
+            if node.skin().is_some() {
+                warn!(
+                    "merge scene: skin of node {:?} is ignored",
+                    self.node_get_name(&node)
+                );
+            }
+        }
+        info!("merge scene: {} mesh nodes", out.len());
+        out
+    }
+
+    /// Nodes that become joints with `rigid_nodes`: mesh nodes, animated nodes and their ancestors.
+    /// Depth first, so parents come before their children. Returns (node index, parent joint).
+    fn rigid_joint_nodes(&self) -> Vec<(usize, Option<usize>)> {
+        fn needed(node: &Node, animated: &HashSet<usize>) -> bool {
+            node.mesh().is_some()
+                || animated.contains(&node.index())
+                || node.children().any(|c| needed(&c, animated))
+        }
+        fn visit(
+            node: Node,
+            parent: Option<usize>,
+            animated: &HashSet<usize>,
+            out: &mut Vec<(usize, Option<usize>)>,
+        ) {
+            if !needed(&node, animated) {
+                return;
+            }
+            let joint = out.len();
+            out.push((node.index(), parent));
+            for child in node.children() {
+                visit(child, Some(joint), animated, out);
+            }
+        }
+
+        let mut animated = HashSet::new();
+        for animation in self.gltf.animations() {
+            for channel in animation.channels() {
+                animated.insert(channel.target().node().index());
+            }
+        }
+        let mut out = Vec::new();
+        for node in self.scene().nodes() {
+            visit(node, None, &animated, &mut out);
+        }
+        assert!(
+            out.len() <= 255,
+            "{} nodes would become joints, rdm supports at most 255 joints",
+            out.len()
+        );
+        out
+    }
+
+    /// Every mesh of the scene with its node and the joint its vertices are bound to.
+    fn rigid_mesh_nodes(&self) -> Vec<(gltf::Mesh<'_>, usize, Option<u16>)> {
+        self.rigid_joint_nodes()
+            .into_iter()
+            .enumerate()
+            .filter_map(|(joint, (node_idx, _))| {
+                let node = self.gltf.nodes().nth(node_idx).unwrap();
+                if node.skin().is_some() {
+                    warn!(
+                        "rigid nodes: skin of node {:?} is ignored",
+                        self.node_get_name(&node)
+                    );
+                }
+                node.mesh()
+                    .map(|mesh| (mesh, node_idx, Some(u16::try_from(joint).unwrap())))
+            })
+            .collect()
+    }
+
+    fn read_rigid_joints(&self) -> Vec<RdJoint> {
+        let joints: Vec<RdJoint> = self
+            .rigid_joint_nodes()
+            .into_iter()
+            .map(|(node_idx, parent)| {
+                let node = self.gltf.nodes().nth(node_idx).unwrap();
+                create_joint(
+                    rigid_part(build_transform2(&self.gltf, node_idx)),
+                    self.node_get_name(&node),
+                    parent.map_or(255, |p| u8::try_from(p).unwrap()),
+                )
+            })
+            .collect();
+        info!("rigid nodes: {} nodes converted to joints", joints.len());
+        joints
+    }
+
+    /// Samples the node TRS animations at every key frame time (of any channel) and converts
+    /// them into rdm tracks of the joints made by `read_rigid_joints`.
+    fn read_node_animations(&self, joints: &[RdJoint]) -> Vec<RdAnim> {
+        let nodes = self.rigid_joint_nodes();
+        assert_eq!(nodes.len(), joints.len());
+
+        let mut rd_animations = Vec::new();
+        for (anim_idx, animation) in self.gltf.animations().enumerate() {
+            debug!("animation: {}", animation.name().unwrap_or("default"));
+            let mut node_tracks: HashMap<usize, NodeTracks> = HashMap::new();
+            let mut times: Vec<f32> = Vec::new();
+            for channel in animation.channels() {
+                let reader = channel.reader(|buffer| Some(&self.buffers[buffer.index()]));
+                let input: Vec<f32> = reader.read_inputs().unwrap().collect();
+                let mut values: Vec<[f32; 4]> = match reader.read_outputs().unwrap() {
+                    Translations(t) => t.map(|[x, y, z]| [x, y, z, 0.0]).collect(),
+                    Rotations(r) => r.into_f32().collect(),
+                    Scales(s) => s.map(|[x, y, z]| [x, y, z, 0.0]).collect(),
+                    MorphTargetWeights(_) => {
+                        warn!("output sampler not supported: 'MorphTargetWeights'");
+                        continue;
+                    }
+                };
+                let interpolation = channel.sampler().interpolation();
+                if interpolation == gltf::animation::Interpolation::CubicSpline {
+                    warn!("cubic spline interpolation is sampled linearly");
+                    // in-tangent, value, out-tangent
+                    values = values.chunks(3).map(|c| c[1]).collect();
+                }
+                assert_eq!(input.len(), values.len());
+                times.extend(input.iter());
+
+                let track = KeyTrack {
+                    times: input,
+                    values,
+                    step: interpolation == gltf::animation::Interpolation::Step,
+                };
+                let tracks = node_tracks
+                    .entry(channel.target().node().index())
+                    .or_default();
+                match channel.target().property() {
+                    gltf::animation::Property::Translation => tracks.translation = Some(track),
+                    gltf::animation::Property::Rotation => tracks.rotation = Some(track),
+                    gltf::animation::Property::Scale => tracks.scale = Some(track),
+                    gltf::animation::Property::MorphTargetWeights => unreachable!(),
+                }
+            }
+            times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            times.dedup_by(|a, b| relative_eq!(*a, *b, epsilon = 1.0e-5));
+
+            let mut frames: Vec<Vec<Frame>> = vec![Vec::with_capacity(times.len()); joints.len()];
+            for &t in &times {
+                let mut globals: Vec<Matrix4<f32>> = Vec::with_capacity(nodes.len());
+                for (node_idx, parent) in &nodes {
+                    let node = self.gltf.nodes().nth(*node_idx).unwrap();
+                    let local = match node_tracks.get(node_idx) {
+                        Some(tracks) => tracks.local(&node, t),
+                        None => {
+                            let mat = node.transform().matrix();
+                            Matrix4::from_fn(|i, j| mat[j][i])
+                        }
+                    };
+                    globals.push(parent.map_or(local, |p| globals[p] * local));
+                }
+                // same rigid (and unmirrored) global transforms as the bind pose
+                let rigid: Vec<Matrix4<f32>> =
+                    globals.iter().map(|g| unmirror(rigid_part(*g)).0).collect();
+                for (k, (_, parent)) in nodes.iter().enumerate() {
+                    let local = match parent {
+                        Some(p) => rigid[*p].try_inverse().unwrap() * rigid[k],
+                        None => rigid[k],
+                    };
+                    let (local, _) =
+                        decompose_uniform_scale(local, &format!("joint {:?}", joints[k].name));
+                    frames[k].push(Frame::from_local(&local, t));
+                }
+            }
+
+            let t_max = times.last().copied().unwrap_or(0.0);
+            rd_animations.push(RdAnim {
+                time_max: (t_max * 1000.0) as u32,
+                anim_vec: joints
+                    .iter()
+                    .zip(frames)
+                    .map(|(joint, frames)| FrameCollection {
+                        len: frames.len() as u32,
+                        frames,
+                        name: joint.name.clone(),
+                    })
+                    .collect(),
+                name: format!("anim_{}", anim_idx),
+            });
+        }
+        rd_animations
+    }
+
+    /// Joints whose local translations are in a scaled space: bind scale / local node scale.
+    /// Animation translations of these joints are multiplied by the factor so they match the
+    /// (scale free) rdm bind pose.
+    fn joint_translation_scale(&self, base: &Matrix4<f32>) -> HashMap<String, f32> {
+        let mut factors = HashMap::new();
+        let node_with_skin = self.gltf.nodes().nth(self.mesh_node.try_into().unwrap());
+        let skin = match node_with_skin.and_then(|n| n.skin()) {
+            Some(skin) => skin,
+            None => return factors,
+        };
+        let binds = self.joint_bind_matrices(base);
+        for node in skin.joints() {
+            let name = self.node_get_name(&node);
+            let bind = match binds.get(&name) {
+                Some(bind) => unmirror(*bind).0,
+                None => continue,
+            };
+            let (_, bind_scale) = decompose_uniform_scale(bind, &format!("joint {:?}", name));
+            let local = node.transform().matrix();
+            let (_, local_scale) = decompose_uniform_scale(
+                Matrix4::from_fn(|i, j| local[j][i]) * node_scale_signs(&node),
+                &format!("joint {:?}", name),
+            );
+            let k = bind_scale / local_scale;
+            if !relative_eq!(k, 1.0, epsilon = 1.0e-4) {
+                debug!("joint {:?}: animation translation scale {}", name, k);
+                factors.insert(name, k);
+            }
+        }
+        if !factors.is_empty() {
+            info!(
+                "{} joints have scaled parents, scale is baked into their animation translations",
+                factors.len()
+            );
+        }
+        factors
+    }
+
+    /// Global bind matrix (`base` · inverseBindMatrix^-1) of every skin joint.
+    fn joint_bind_matrices(&self, base: &Matrix4<f32>) -> HashMap<String, Matrix4<f32>> {
+        let node_with_skin = self.gltf.nodes().nth(self.mesh_node.try_into().unwrap());
+        let skin = match node_with_skin.and_then(|n| n.skin()) {
+            Some(skin) => skin,
+            None => return HashMap::new(),
+        };
+        let reader = skin.reader(|buffer| Some(&self.buffers[buffer.index()]));
+        match reader.read_inverse_bind_matrices() {
+            Some(ibms) => skin
+                .joints()
+                .zip(ibms)
+                .map(|(node, ibm)| {
+                    let ibm = Matrix4::from_fn(|i, j| ibm[j][i]);
+                    (self.node_get_name(&node), base * ibm.try_inverse().unwrap())
+                })
+                .collect(),
+            None => HashMap::new(),
+        }
+    }
+
+    /// Parent joint (skin joint index) of every skin joint.
+    fn skin_parents(&self, skin: &gltf::Skin) -> Vec<Option<usize>> {
+        let names: Vec<String> = skin.joints().map(|n| self.node_get_name(&n)).collect();
+        let mut parents = vec![None; names.len()];
+        for (i, node) in skin.joints().enumerate() {
+            for child in node.children() {
+                //rdm: children know their parent VS glTF parents know their children
+                let c_name = self.node_get_name(&child);
+                if let Some(child_idx) = names.iter().position(|r| r == &c_name) {
+                    parents[child_idx] = Some(i);
+                    debug!("{}: {} -> {}", c_name, i, names[i]);
+                }
+            }
+        }
+        parents
+    }
+
+    /// New rdm joint index of every skin joint, `None` for pruned joints.
+    /// Without `prune_joints` every joint keeps its index.
+    fn skin_joint_remap(&self, skin: &gltf::Skin) -> Vec<Option<u8>> {
+        let count = skin.joints().count();
+        let mut keep = vec![!self.prune_joints; count];
+        let mesh = self
+            .gltf
+            .meshes()
+            .nth(self.mesh_idx.try_into().unwrap())
+            .unwrap();
+        for primitive in mesh.primitives().filter(|_| self.prune_joints) {
+            let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+            let mut set = 0;
+            while let (Some(joints), Some(weights)) =
+                (reader.read_joints(set), reader.read_weights(set))
+            {
+                for (j, w) in joints.into_u16().zip(weights.into_f32()) {
+                    for (j, w) in j.iter().zip(w.iter()) {
+                        if *w > 0.0 {
+                            keep[*j as usize] = true;
+                        }
+                    }
+                }
+                set += 1;
+            }
+        }
+
+        let parents = self.skin_parents(skin);
+        for i in 0..count {
+            if keep[i] {
+                let mut p = parents[i];
+                while let Some(parent) = p {
+                    keep[parent] = true;
+                    p = parents[parent];
+                }
+            }
+        }
+
+        let kept = keep.iter().filter(|&&k| k).count();
+        // rdm stores parents and I4b joint indices as u8, 255 marks a parentless joint.
+        assert!(
+            kept <= 255,
+            "skin #{} has {} joints, rdm skins are limited to 255 joints. Try --prune-joints",
+            skin.index(),
+            kept
+        );
+
+        let mut next = 0;
+        let remap: Vec<Option<u8>> = keep
+            .iter()
+            .map(|&k| {
+                k.then(|| {
+                    next += 1;
+                    next - 1
+                })
+            })
+            .collect();
+        if self.prune_joints {
+            let pruned: Vec<String> = skin
+                .joints()
+                .zip(remap.iter())
+                .filter(|(_, r)| r.is_none())
+                .map(|(n, _)| self.node_get_name(&n))
+                .collect();
+            debug!("pruned {} of {} joints: {:?}", pruned.len(), count, pruned);
+        }
+        remap
+    }
+
     fn create_joints_from_non_skin_nodes(&self, rdjoint: &mut Vec<RdJoint>) -> bool {
         // TODO: refactor this ugly mess
         // If a joint has a parent that is not a joint itself convert the parent
@@ -558,6 +1321,144 @@ impl<'a> ImportedGltf {
         has_converted
     }
 
+    /// Meshes that are read into the rdm, with their instantiating node and rigid joint.
+    fn mesh_sources(&self) -> Vec<(gltf::Mesh<'_>, usize, Option<u16>)> {
+        if self.rigid_nodes {
+            self.rigid_mesh_nodes()
+        } else if self.merge_scene {
+            self.scene_mesh_nodes()
+        } else {
+            // only the nth mesh of file gets read
+            match self.gltf.meshes().nth(self.mesh_idx.try_into().unwrap()) {
+                Some(mesh) => vec![(mesh, self.mesh_node.try_into().unwrap(), None)],
+                None => Vec::new(),
+            }
+        }
+    }
+
+    /// DDS textures for the materials of the mesh(es) that [`Self::gltf_to_rdm`] reads, in order of
+    /// first use: `<material>_diff_0.dds`, `<material>_norm_0.dds` and `<material>_metal_0.dds`.
+    /// The channels are packed the way [`MaterialImage::decode`](crate::rdm_material::MaterialImage::decode)
+    /// reads them, see [`MaterialSlot`](crate::rdm_material::MaterialSlot): the glossiness
+    /// (255 - roughness) goes to the blue channel of the normal map, the metalness and the
+    /// emissive mask go to the red and alpha channel of the metal map.
+    pub fn material_textures(&self) -> Vec<MaterialTexture> {
+        let meshes = self.mesh_sources().into_iter().map(|(mesh, _, _)| mesh);
+        self.textures_of_meshes(meshes)
+    }
+
+    /// [`Self::material_textures`] of the meshes `mesh_indices`, e.g. all
+    /// [`Self::instantiated_meshes`]. Materials shared by several meshes are encoded once.
+    pub fn material_textures_of(&self, mesh_indices: &[u32]) -> Vec<MaterialTexture> {
+        let meshes = mesh_indices
+            .iter()
+            .map(|&i| self.gltf.meshes().nth(i as usize).unwrap());
+        self.textures_of_meshes(meshes)
+    }
+
+    fn textures_of_meshes<'m>(
+        &self,
+        meshes: impl Iterator<Item = gltf::Mesh<'m>>,
+    ) -> Vec<MaterialTexture> {
+        let mut textures: Vec<MaterialTexture> = Vec::new();
+        let mut materials = Vec::new();
+        for mesh in meshes {
+            for primitive in mesh.primitives() {
+                let material = primitive.material();
+                match material.index() {
+                    Some(idx) if !materials.contains(&idx) => materials.push(idx),
+                    _ => {}
+                }
+            }
+        }
+        for material in materials
+            .into_iter()
+            .map(|i| self.gltf.materials().nth(i).unwrap())
+        {
+            let base = sanitize_name(
+                &material
+                    .name()
+                    .map(str::to_owned)
+                    .unwrap_or_else(|| format!("material_{}", material.index().unwrap())),
+            );
+            let pbr = material.pbr_metallic_roughness();
+            let texture_image = |texture: Option<gltf::Texture>| {
+                texture.map(|t| {
+                    let image = &self.images[t.source().index()];
+                    debug!(
+                        "material {} uses image #{} ({}x{})",
+                        base,
+                        t.source().index(),
+                        image.width,
+                        image.height
+                    );
+                    rgba_image(image)
+                })
+            };
+            let base_color = texture_image(pbr.base_color_texture().map(|t| t.texture()));
+            let normal = texture_image(material.normal_texture().map(|t| t.texture()));
+            let metallic_roughness =
+                texture_image(pbr.metallic_roughness_texture().map(|t| t.texture()));
+            let emissive = texture_image(material.emissive_texture().map(|t| t.texture()));
+
+            let norm = normal.map(|mut image| {
+                let roughness =
+                    channel_source(&base, &metallic_roughness, "metallic-roughness", &image);
+                for (i, pixel) in image.rgba.chunks_exact_mut(4).enumerate() {
+                    // without a roughness the glossiness is 0 (roughness 1)
+                    pixel[2] = roughness.map_or(0, |r| 255 - r.rgba[i * 4 + 1]);
+                    pixel[3] = 255;
+                }
+                image
+            });
+            if norm.is_none() && metallic_roughness.is_some() {
+                warn!(
+                    "material {} has no normal texture, its roughness is dropped",
+                    base
+                );
+            }
+
+            let metal = metallic_roughness
+                .as_ref()
+                .or(emissive.as_ref())
+                .map(|size| {
+                    let mut image = RgbaImage {
+                        width: size.width,
+                        height: size.height,
+                        rgba: vec![0; size.rgba.len()],
+                    };
+                    let metallic =
+                        channel_source(&base, &metallic_roughness, "metallic-roughness", &image);
+                    let mask = channel_source(&base, &emissive, "emissive", &image);
+                    for (i, pixel) in image.rgba.chunks_exact_mut(4).enumerate() {
+                        pixel[0] = metallic.map_or(0, |m| m.rgba[i * 4 + 2]);
+                        pixel[3] = mask.map_or(0, |e| {
+                            e.rgba[i * 4..i * 4 + 3].iter().copied().max().unwrap()
+                        });
+                    }
+                    image
+                });
+
+            for (suffix, image) in [("diff", base_color), ("norm", norm), ("metal", metal)] {
+                let image = match image {
+                    Some(image) => image,
+                    None => continue,
+                };
+                let file_name = format!("{}_{}_0.dds", base, suffix);
+                if textures.iter().any(|t| t.file_name == file_name) {
+                    warn!(
+                        "texture {} is used by several materials, skipped",
+                        file_name
+                    );
+                    continue;
+                }
+                info!("{} ({}x{})", file_name, image.width, image.height);
+                textures.push(MaterialTexture { file_name, image });
+            }
+        }
+        textures
+    }
+
     fn read_mesh(
         &self,
         dst_format: TargetVertexFormat,
@@ -567,31 +1468,67 @@ impl<'a> ImportedGltf {
         overide_mesh_idx: Option<Vec<u32>>,
     ) -> ReadMeshOutput {
         let (gltf, buffers) = (&self.gltf, &self.buffers);
-        // only the nth mesh of file gets read
-        if let Some(mesh) = gltf.meshes().nth(self.mesh_idx.try_into().unwrap()) {
-            info!("Mesh #{}", mesh.index());
+        let sources = self.mesh_sources();
+        if sources.is_empty() {
+            return None;
+        }
+        let mirror_x = negative_x_and_v0v2v1;
+        let ident = match dst_format {
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h => {
+                crate::vertex::p4h_n4b_g4b_b4b_t2h().to_vec()
+            }
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b => {
+                crate::vertex::p4h_n4b_g4b_b4b_t2h_i4b().to_vec()
+            }
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b => {
+                crate::vertex::p4h_n4b_g4b_b4b_t2h_i4b_w4b().to_vec()
+            }
+        };
+        let vertsize = ident.iter().map(|x| x.get_size()).sum();
+
+        let mut mesh_info: Vec<MeshInstance> = Vec::new();
+        let mut merged_triangle_vec = Vec::new();
+        let mut vertices_count: u32 = 0;
+        let mut verts_vec = BytesMut::with_capacity(64000 * vertsize as usize);
+
+        // packed vertex data of every primitive -> first vertex, for primitives sharing their vertices
+        let mut kown_vbuffers: HashMap<Vec<u8>, u32> = HashMap::new();
+        // packed vertex -> vertex index, for `weld`
+        let mut welded_vertices: HashMap<Vec<u8>, u32> = HashMap::new();
+        // glTF material of every material slot, in order of first use
+        let mut material_slots: Vec<Option<usize>> = Vec::new();
+
+        if let Some(v) = overide_mesh_idx.as_ref() {
+            let primitives: usize = sources.iter().map(|(m, _, _)| m.primitives().len()).sum();
+            assert_eq!(primitives, v.len());
+        }
 
-            let mesh_instantiating_node = self.mesh_node.try_into().unwrap();
+        for (mesh, mesh_instantiating_node, rigid_joint) in sources {
+            info!("Mesh #{}", mesh.index());
             debug!("mesh_instantiating_node: {}", mesh_instantiating_node);
 
-            let mut base: Matrix4<f32> = if no_transform {
+            // rigid nodes are bound to joints in world space, merged nodes are baked into it
+            let world = rigid_joint.is_some() || self.merge_scene;
+            let mut base: Matrix4<f32> = if no_transform && !world {
                 Matrix4::identity()
             } else {
                 build_transform2(gltf, mesh_instantiating_node)
             };
 
-            if negative_x_and_v0v2v1 {
+            negative_x_and_v0v2v1 = false;
+            if mirror_x {
                 let m = Matrix3::new(-1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0);
                 base *= m.to_homogeneous();
-                negative_x_and_v0v2v1 = false;
             }
 
             debug!("base: {}", &base);
 
             let det = base.determinant();
             if det.is_sign_negative() {
-                warn!("determinant is negative: {}", det);
-                warn!("negative determinant requires special code path!");
+                info!(
+                    "mesh node transform is mirrored (determinant {}): flipping triangle winding and tangent handedness",
+                    det
+                );
                 negative_x_and_v0v2v1 = true;
             }
 
@@ -599,30 +1536,17 @@ impl<'a> ImportedGltf {
             let inv_transform_mat3 = mat3.try_inverse().unwrap();
             let transpose_inv_transform_mat3 = inv_transform_mat3.transpose();
 
-            let ident = match dst_format {
-                TargetVertexFormat::P4h_N4b_G4b_B4b_T2h => {
-                    crate::vertex::p4h_n4b_g4b_b4b_t2h().to_vec()
-                }
-                TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b => {
-                    crate::vertex::p4h_n4b_g4b_b4b_t2h_i4b().to_vec()
-                }
-                TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b => {
-                    crate::vertex::p4h_n4b_g4b_b4b_t2h_i4b_w4b().to_vec()
-                }
+            let joint_remap = if read_joints && rigid_joint.is_none() {
+                gltf.nodes()
+                    .nth(mesh_instantiating_node)
+                    .and_then(|n| n.skin())
+                    .map(|skin| self.skin_joint_remap(&skin))
+            } else {
+                None
             };
-            let vertsize = ident.iter().map(|x| x.get_size()).sum();
-
-            let mut mesh_info: Vec<MeshInstance> = Vec::new();
-            let mut merged_triangle_vec = Vec::new();
-            let mut vertices_count: u32 = 0;
-            let mut verts_vec = BytesMut::with_capacity(64000 * vertsize as usize);
-
-            let mut kown_vbuffers = HashMap::new();
-
-            if let Some(v) = overide_mesh_idx.as_ref() {
-                assert_eq!(mesh.primitives().len(), v.len());
-            }
-            for (i, primitive) in mesh.primitives().enumerate() {
+            for primitive in mesh.primitives() {
+                // running index over all primitives of all meshes
+                let i = mesh_info.len();
                 info!("- Primitive #{}", primitive.index());
                 let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
 
@@ -662,46 +1586,69 @@ impl<'a> ImportedGltf {
                 };
                 let mut tex_iter = p.into_iter().cycle();
 
-                let jvecarr: Vec<[u16; 4]> = match reader.read_joints(0) {
-                    Some(joints) if read_joints => {
-                        let j: Vec<[u16; 4]> = joints.into_u16().collect();
-                        assert_eq!(count, j.len());
-                        j
-                    }
-                    _ => {
-                        warn!("No joints in glTF file !");
-                        if read_joints {
-                            panic!("No joints in glTF file but --skeleton flag was set!")
-                        }
-                        vec![[0, 0, 0, 0]]
-                    }
+                // collect the influences of all JOINTS_n/WEIGHTS_n sets
+                let mut influence_sets = 0;
+                let mut vertex_influences: Vec<Vec<(u16, f32)>> = vec![Vec::new(); count];
+                let joint_index = |j: u16| match &joint_remap {
+                    // pruned joints have no weight
+                    Some(remap) => remap[j as usize].map_or(0, u16::from),
+                    None => j,
                 };
-
-                let mut joints_iter = jvecarr.into_iter().cycle();
-
-                let wvecarr: Vec<[f32; 4]> = match reader.read_weights(0) {
-                    Some(weight) if read_joints => {
-                        let j: Vec<[f32; 4]> = weight.into_f32().collect();
-                        assert_eq!(count, j.len());
-                        j
-                    }
-                    _ => {
-                        warn!("No weights in glTF file !");
-                        if read_joints {
-                            panic!("No joints/weights in glTF file but --skeleton flag was set!")
+                if let Some(joint) = rigid_joint {
+                    vertex_influences = vec![vec![(joint, 1.0)]; count];
+                    influence_sets = 1;
+                } else if read_joints {
+                    while let (Some(joints), Some(weights)) = (
+                        reader.read_joints(influence_sets),
+                        reader.read_weights(influence_sets),
+                    ) {
+                        let joints: Vec<[u16; 4]> = joints.into_u16().collect();
+                        let weights: Vec<[f32; 4]> = weights.into_f32().collect();
+                        assert_eq!(count, joints.len());
+                        assert_eq!(count, weights.len());
+                        for ((dst, j), w) in vertex_influences
+                            .iter_mut()
+                            .zip(joints.iter())
+                            .zip(weights.iter())
+                        {
+                            dst.extend(j.iter().map(|&j| joint_index(j)).zip(w.iter().copied()));
                         }
-                        vec![[0.0, 0.0, 0.0, 0.0]]
+                        influence_sets += 1;
                     }
-                };
+                }
+                if influence_sets == 0 {
+                    warn!("No joints/weights in glTF file !");
+                    if read_joints {
+                        panic!("No joints/weights in glTF file but --skeleton flag was set!")
+                    }
+                } else if influence_sets > 1 {
+                    info!(
+                        "{} JOINTS/WEIGHTS sets, keeping the {} strongest influences per vertex",
+                        influence_sets,
+                        dst_format.max_influences()
+                    );
+                }
 
-                let mut weights_iter = wvecarr.into_iter().cycle();
+                let mut discarded_vertices = 0;
+                let mut influences_iter = vertex_influences.into_iter().enumerate().map(
+                    |(vertex_idx, mut influences)| {
+                        let discarded =
+                            reduce_influences(&mut influences, dst_format.max_influences());
+                        if discarded > DISCARDED_WEIGHT_REPORT {
+                            discarded_vertices += 1;
+                            warn!(
+                                "primitive {} vertex {}: discarded bone weight {:.3}",
+                                i, vertex_idx, discarded
+                            );
+                        }
+                        influences
+                    },
+                );
 
                 info!("dst_format: {:?}", dst_format);
                 //let mut verts_vec = BytesMut::with_capacity(count * vertsize as usize);
 
                 trace!("vertex read loop");
-                let mut start_vertices_count = verts_vec.len() as u32 / vertsize;
-
                 let pre_vertices_added = verts_vec.len();
 
                 while count > 0 {
@@ -809,50 +1756,76 @@ impl<'a> ImportedGltf {
                         || dst_format == TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b
                     {
                         // joints idx
-                        let joint = joints_iter.next().unwrap();
-
-                        let i4b = I4b {
-                            data: [
-                                joint[0] as u8,
-                                joint[1] as u8,
-                                joint[2] as u8,
-                                joint[3] as u8,
-                            ],
-                        };
+                        let influences = influences_iter.next().unwrap();
+                        let mut i4b = I4b { data: [0; 4] };
+                        for (dst, (j, _)) in i4b.data.iter_mut().zip(influences.iter()) {
+                            *dst = *j as u8;
+                        }
                         verts_vec.put_vertex_data(&i4b);
-                    }
 
-                    if dst_format == TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b {
-                        let weight = weights_iter.next().unwrap();
-                        let w4b = W4b {
-                            data: [
-                                (weight[0] * 255.0).round() as u8,
-                                (weight[1] * 255.0).round() as u8,
-                                (weight[2] * 255.0).round() as u8,
-                                (weight[3] * 255.0).round() as u8,
-                            ],
-                        };
-                        verts_vec.put_vertex_data(&w4b);
+                        if dst_format == TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b {
+                            let mut weight = [0.0; 4];
+                            for (dst, (_, w)) in weight.iter_mut().zip(influences.iter()) {
+                                *dst = *w;
+                            }
+                            let w4b = W4b {
+                                data: quantize_weights(weight),
+                            };
+                            verts_vec.put_vertex_data(&w4b);
+                        }
                     }
 
                     count -= 1;
                 }
-
-                let mut hasher = DefaultHasher::new();
-                Hash::hash_slice(&verts_vec[pre_vertices_added..verts_vec.len()], &mut hasher);
-                let hash_v = hasher.finish();
-                debug!("Primivive {} hash is {:x}.", i, &hash_v);
-                match kown_vbuffers.get(&hash_v) {
-                    Some(found_start_vertices_count) => {
-                        info!("found hash {:x}! Buffer already written.", &hash_v);
-                        start_vertices_count = *found_start_vertices_count;
-                        verts_vec.resize(pre_vertices_added, 0)
-                    }
-                    None => {
-                        kown_vbuffers.insert(hash_v, start_vertices_count);
-                    }
+                drop(influences_iter);
+                if discarded_vertices > 0 {
+                    warn!(
+                        "primitive {}: {} vertices lost more than {} bone weight, the target format keeps {} influences",
+                        i,
+                        discarded_vertices,
+                        DISCARDED_WEIGHT_REPORT,
+                        dst_format.max_influences()
+                    );
                 }
 
+                // the packed vertices are compared exactly, not by hash
+                let new_vertices = verts_vec.split_off(pre_vertices_added);
+                let new_count = new_vertices.len() as u32 / vertsize;
+                let vertex_index: Vec<u32> = if self.weld {
+                    let index: Vec<u32> = new_vertices
+                        .chunks_exact(vertsize as usize)
+                        .map(|v| {
+                            *welded_vertices.entry(v.to_vec()).or_insert_with(|| {
+                                verts_vec.extend_from_slice(v);
+                                verts_vec.len() as u32 / vertsize - 1
+                            })
+                        })
+                        .collect();
+                    let added =
+                        verts_vec.len() as u32 / vertsize - pre_vertices_added as u32 / vertsize;
+                    info!(
+                        "Primitive {}: welded {} of {} vertices",
+                        i,
+                        new_count - added,
+                        new_count
+                    );
+                    index
+                } else {
+                    let start = match kown_vbuffers.get(&new_vertices[..]) {
+                        Some(&start) => {
+                            info!("Primitive {}: vertex buffer already written.", i);
+                            start
+                        }
+                        None => {
+                            let start = verts_vec.len() as u32 / vertsize;
+                            kown_vbuffers.insert(new_vertices.to_vec(), start);
+                            verts_vec.extend_from_slice(&new_vertices);
+                            start
+                        }
+                    };
+                    (start..start + new_count).collect()
+                };
+
                 vertices_count = verts_vec.len() as u32 / vertsize;
                 info!("Vertex count: {}", vertices_count);
                 if vertices_count > (u16::MAX - 1).into() {
@@ -869,38 +1842,75 @@ impl<'a> ImportedGltf {
 
                 let mut tcount = triangle_iter.len() / 3;
 
+                let mut degenerated = 0;
                 while tcount > 0 {
-                    //let ctri = triangle_iter.next().unwrap();
-                    let v0 = triangle_iter.next().unwrap();
-                    let v1 = triangle_iter.next().unwrap();
-                    let v2 = triangle_iter.next().unwrap();
+                    let mut corner = || {
+                        let v = triangle_iter.next().unwrap();
+                        u16::try_from(vertex_index[v as usize]).unwrap()
+                    };
+                    let (v0, v1, v2) = (corner(), corner(), corner());
+                    tcount -= 1;
+                    if self.weld && (v0 == v1 || v1 == v2 || v0 == v2) {
+                        degenerated += 1;
+                        continue;
+                    }
                     let t = if negative_x_and_v0v2v1 {
                         Triangle {
-                            indices: [
-                                u16::try_from(start_vertices_count + v0).unwrap(),
-                                u16::try_from(start_vertices_count + v2).unwrap(),
-                                u16::try_from(start_vertices_count + v1).unwrap(),
-                            ],
+                            indices: [v0, v2, v1],
                         }
                     } else {
                         Triangle {
-                            indices: [
-                                u16::try_from(start_vertices_count + v0).unwrap(),
-                                u16::try_from(start_vertices_count + v1).unwrap(),
-                                u16::try_from(start_vertices_count + v2).unwrap(),
-                            ],
+                            indices: [v0, v1, v2],
                         }
                     };
-                    tcount -= 1;
                     triangle_vec.push(t);
                 }
+                if degenerated > 0 {
+                    info!(
+                        "Primitive {}: dropped {} triangles that degenerated by welding",
+                        i, degenerated
+                    );
+                }
 
                 mesh_info.push(MeshInstance {
                     start_index_location: merged_triangle_vec.len() as u32 * 3,
                     index_count: triangle_vec.len() as u32 * 3,
-                    material: match overide_mesh_idx.as_ref() {
-                        Some(j) => j[i],
-                        None => i.try_into().unwrap(),
+                    material: match (
+                        overide_mesh_idx.as_ref(),
+                        primitive.material().name(),
+                        &self.material_map,
+                    ) {
+                        (Some(j), _, _) => j[i],
+                        (None, Some(name), Some(map)) => *map.get(name).unwrap_or_else(|| {
+                            panic!(
+                                "material {:?} of mesh #{} is not in the material map {:?}",
+                                name,
+                                mesh.index(),
+                                map
+                            )
+                        }),
+                        (None, None, Some(_)) => panic!(
+                            "mesh #{} primitive #{} has no material name for the material map",
+                            mesh.index(),
+                            primitive.index()
+                        ),
+                        (None, _, None) if self.merge_scene => {
+                            let material = primitive.material();
+                            let slot = material_slots
+                                .iter()
+                                .position(|&m| m == material.index())
+                                .unwrap_or_else(|| {
+                                    info!(
+                                        "material slot {}: {:?}",
+                                        material_slots.len(),
+                                        material.name().unwrap_or("default material")
+                                    );
+                                    material_slots.push(material.index());
+                                    material_slots.len() - 1
+                                });
+                            slot.try_into().unwrap()
+                        }
+                        (None, _, None) => i.try_into().unwrap(),
                     },
                 });
 
@@ -909,34 +1919,37 @@ impl<'a> ImportedGltf {
                 info!("{:?}", &mesh_info);
                 //return Some((vertsize, verts, merged_triangle_vec, vertices_count, mesh_info));
             }
-            let verts = VertexFormat2::new(
-                ident.into_boxed_slice(),
-                vertices_count,
-                vertsize,
-                None,
-                verts_vec.freeze(),
-            );
-            return Some((
-                vertsize,
-                verts,
-                merged_triangle_vec,
-                vertices_count,
-                mesh_info,
-            ));
         }
-        None
+        let verts = VertexFormat2::new(
+            ident.into_boxed_slice(),
+            vertices_count,
+            vertsize,
+            None,
+            verts_vec.freeze(),
+        );
+        Some((
+            vertsize,
+            verts,
+            merged_triangle_vec,
+            vertices_count,
+            mesh_info,
+        ))
     }
 }
 
 #[inline]
-fn create_joint(mut mat4_init: Matrix4<f32>, name: String, parent: u8) -> RdJoint {
-    // may perform expensive checks ...
+fn create_joint(mat4_init: Matrix4<f32>, name: String, parent: u8) -> RdJoint {
     debug!("node_to_joint mat4_init: {}", mat4_init);
-    mat4_init.m44 = 1.0;
-    let similarity: Similarity3<f32> = nalgebra::try_convert(mat4_init).unwrap();
-    debug!("similarity.scaling: {}", similarity.scaling());
-
-    let isometry: Isometry3<f32> = similarity.isometry;
+    let (mat4_init, mirrored) = unmirror(mat4_init);
+    if mirrored {
+        debug!("joint {:?}: mirrored bind pose", name);
+    }
+    // the scale is already contained in the global bind position, the animations
+    // get their translations scaled accordingly (see `joint_translation_scale`).
+    let (isometry, scaling) = decompose_uniform_scale(mat4_init, &format!("joint {:?}", name));
+    if !relative_eq!(scaling, 1.0, epsilon = 1.0e-4) {
+        debug!("joint {:?}: bind scale {} dropped", name, scaling);
+    }
     let unit_quaternion = isometry.rotation;
     let quaternion_raw = unit_quaternion.quaternion().coords;
 
@@ -956,6 +1969,39 @@ fn create_joint(mut mat4_init: Matrix4<f32>, name: String, parent: u8) -> RdJoin
     }
 }
 
+/// Discarded bone weight above which a vertex gets reported.
+const DISCARDED_WEIGHT_REPORT: f32 = 0.01;
+
+/// Keeps the `max` strongest influences (merging duplicate joints) and renormalises them to a sum of 1.
+/// Returns the discarded weight.
+fn reduce_influences(influences: &mut Vec<(u16, f32)>, max: usize) -> f32 {
+    influences.retain(|(_, w)| *w > 0.0);
+    let mut merged: Vec<(u16, f32)> = Vec::with_capacity(influences.len());
+    for (j, w) in influences.drain(..) {
+        match merged.iter_mut().find(|(mj, _)| *mj == j) {
+            Some((_, mw)) => *mw += w,
+            None => merged.push((j, w)),
+        }
+    }
+    merged.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+    let total: f32 = merged.iter().map(|(_, w)| w).sum();
+    merged.truncate(max);
+    let kept: f32 = merged.iter().map(|(_, w)| w).sum();
+    if kept > 0.0 {
+        for (_, w) in merged.iter_mut() {
+            *w /= kept;
+        }
+    }
+    *influences = merged;
+
+    if total > 0.0 {
+        (total - kept) / total
+    } else {
+        0.0
+    }
+}
+
 type ReadMeshOutput = Option<(u32, VertexFormat2, Vec<Triangle>, u32, Vec<MeshInstance>)>;
 
 fn find_first_mesh_instantiating_node(gltf: &gltf::Document, mesh_idx: usize) -> Option<usize> {
@@ -1009,6 +2055,13 @@ fn build_transform2(gltf: &gltf::Document, mesh_node: usize) -> Matrix4<f32> {
     calculate_global_transform(mesh_node, &tree, gltf)
 }
 
+/// Global transform of the parent of `node` (identity for scene root nodes).
+fn node_parent_transform(gltf: &gltf::Document, node: &Node) -> Matrix4<f32> {
+    let local = node.transform().matrix();
+    build_transform2(gltf, node.index())
+        * Matrix4::from_fn(|i, j| local[j][i]).try_inverse().unwrap()
+}
+
 fn calculate_global_transform(
     target_node: usize,
     tree: &[usize],
@@ -1051,3 +2104,596 @@ fn find_parent(mesh_node: usize, child_list: &[Option<Vec<usize>>]) -> Option<us
     }
     parent_idx
 }
+
+#[cfg(test)]
+mod tests_gltf_reader {
+    use super::*;
+
+    #[test]
+    fn reduce_influences_keeps_strongest() {
+        // JOINTS_0/WEIGHTS_0 + JOINTS_1/WEIGHTS_1, joint 3 is listed twice
+        let mut influences = vec![
+            (1, 0.1),
+            (2, 0.3),
+            (3, 0.05),
+            (4, 0.0),
+            (5, 0.21),
+            (3, 0.15),
+            (6, 0.12),
+            (7, 0.07),
+        ];
+        let discarded = reduce_influences(&mut influences, 4);
+        assert_relative_eq!(discarded, 0.17, epsilon = 1.0e-6);
+
+        let joints: Vec<u16> = influences.iter().map(|(j, _)| *j).collect();
+        assert_eq!(joints, vec![2, 5, 3, 6]);
+        let sum: f32 = influences.iter().map(|(_, w)| w).sum();
+        assert_relative_eq!(sum, 1.0, epsilon = 1.0e-6);
+
+        let w: Vec<f32> = influences.iter().map(|(_, w)| *w).collect();
+        let data = quantize_weights([w[0], w[1], w[2], w[3]]);
+        assert_eq!(data.iter().map(|&w| w as u32).sum::<u32>(), 255);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn prune_joints() {
+        let mut i_gltf =
+            ImportedGltf::try_from(Path::new("rdm/gltf/stormtrooper_with_tangent.gltf")).unwrap();
+        let format = || TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b;
+        let full = i_gltf.gltf_to_rdm(format(), true, false, false, None);
+        i_gltf.prune_joints = true;
+        let pruned = i_gltf.gltf_to_rdm(format(), true, false, false, None);
+
+        let full_joints = full.joints.as_ref().unwrap();
+        let joints = pruned.joints.as_ref().unwrap();
+        assert!(joints.len() < full_joints.len());
+        assert!(joints
+            .iter()
+            .all(|j| j.parent == 255 || (j.parent as usize) < joints.len()));
+
+        // every weighted joint index points to the same joint name as before
+        let i4b = |m: &RdModell| -> Vec<I4b> { m.vertex.iter(0).unwrap().collect() };
+        let w4b: Vec<W4b> = pruned.vertex.iter(0).unwrap().collect();
+        for ((old, new), w) in i4b(&full).iter().zip(i4b(&pruned).iter()).zip(w4b.iter()) {
+            for k in 0..4 {
+                if w.data[k] > 0 {
+                    assert_eq!(
+                        full_joints[old.data[k] as usize].name,
+                        joints[new.data[k] as usize].name
+                    );
+                }
+            }
+        }
+
+        let anims = i_gltf.read_animation(joints, 6, 0.33333, false).unwrap();
+        assert!(anims.iter().all(|a| a.anim_vec.len() == joints.len()));
+    }
+
+    const STORMTROOPER: &str = "rdm/gltf/stormtrooper_with_tangent.gltf";
+
+    /// Stormtrooper with a modified transform of the "Armature" node that holds mesh and skeleton.
+    fn stormtrooper_armature(name: &str, properties: serde_json::Value) -> ImportedGltf {
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(STORMTROOPER).unwrap()).unwrap();
+        let armature = &mut json["nodes"][69];
+        assert_eq!(armature["name"], "Armature");
+        for (k, v) in properties.as_object().unwrap() {
+            armature[k] = v.clone();
+        }
+        let f_path = std::env::temp_dir().join(format!("rdm4_{}.gltf", name));
+        std::fs::write(&f_path, json.to_string()).unwrap();
+        ImportedGltf::try_from(f_path.as_path()).unwrap()
+    }
+
+    /// Joint global transform of the first animation frame times inverse bind pose.
+    fn skinning(i_gltf: &ImportedGltf) -> Vec<Matrix4<f32>> {
+        let format = TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b;
+        let rdm = i_gltf.gltf_to_rdm(format, true, false, false, None);
+        let joints = rdm.joints.as_ref().unwrap();
+        let anim = i_gltf
+            .read_animation(joints, 6, 0.33333, false)
+            .unwrap()
+            .remove(0);
+        let local = |j: &RdJoint| {
+            let track = anim.anim_vec.iter().find(|c| c.name == j.name).unwrap();
+            let f = &track.frames[0];
+            Isometry3::from_parts(
+                Translation3::from(Vector3::from(f.translation)),
+                f.unit_rotation(),
+            )
+        };
+        joints
+            .iter()
+            .map(|j| {
+                let mut global = local(j);
+                let mut parent = j.parent;
+                while parent != 255 {
+                    global = local(&joints[parent as usize]) * global;
+                    parent = joints[parent as usize].parent;
+                }
+                let [x, y, z, w] = j.quaternion;
+                let bind = Isometry3::from_parts(
+                    Translation3::from(Vector3::from(j.transition)),
+                    UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z)),
+                );
+                (global * bind.inverse()).to_homogeneous()
+            })
+            .collect()
+    }
+
+    /// Vertices are transformed by the mesh node transform, so the skinning has to be conjugated by it.
+    fn assert_skinning_transformed(moved: &ImportedGltf) {
+        let original = ImportedGltf::try_from(Path::new(STORMTROOPER)).unwrap();
+        assert!((original.mesh_node_transform(false) - Matrix4::identity()).amax() < 1.0e-5);
+
+        let base = moved.mesh_node_transform(false);
+        let base_inv = base.try_inverse().unwrap();
+        for (o, m) in skinning(&original).iter().zip(skinning(moved).iter()) {
+            let expected = base * o * base_inv;
+            assert!((expected - m).amax() < 1.0e-3, "{} != {}", expected, m);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn node_transform_skinning() {
+        let moved = stormtrooper_armature(
+            "node_transform_skinning",
+            serde_json::json!({
+                "rotation": [0.0, 0.3826834, 0.0, 0.9238795],
+                "translation": [1.0, 2.0, 3.0]
+            }),
+        );
+        assert_skinning_transformed(&moved);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn mirrored_node_transform() {
+        let mirrored = stormtrooper_armature(
+            "mirrored_node_transform",
+            serde_json::json!({
+                "rotation": [0.0, 0.3826834, 0.0, 0.9238795],
+                "scale": [1.0, 1.0, -1.0]
+            }),
+        );
+        assert!(mirrored.mesh_node_transform(false).determinant() < 0.0);
+        assert_skinning_transformed(&mirrored);
+
+        // winding and tangent handedness are flipped
+        let format = || TargetVertexFormat::P4h_N4b_G4b_B4b_T2h;
+        let original = ImportedGltf::try_from(Path::new(STORMTROOPER)).unwrap();
+        let original = original.gltf_to_rdm(format(), false, false, false, None);
+        let mirrored = mirrored.gltf_to_rdm(format(), false, false, false, None);
+        for (o, m) in original
+            .triangle_indices
+            .iter()
+            .zip(mirrored.triangle_indices.iter())
+        {
+            let [a, b, c] = o.indices;
+            assert_eq!(m.indices, [a, c, b]);
+        }
+        let handedness = |rdm: &RdModell| -> Vec<f32> {
+            let n: Vec<N4b> = rdm.vertex.iter(0).unwrap().collect();
+            let g: Vec<G4b> = rdm.vertex.iter(0).unwrap().collect();
+            let b: Vec<B4b> = rdm.vertex.iter(0).unwrap().collect();
+            let v = |d: [u8; 4]| Vector3::new(d[0], d[1], d[2]).map(|c| c as f32 / 127.5 - 1.0);
+            n.iter()
+                .zip(g.iter())
+                .zip(b.iter())
+                .map(|((n, g), b)| v(n.data).cross(&v(g.data)).dot(&v(b.data)).signum())
+                .collect()
+        };
+        // the bitangent is mirrored with the mesh, normal × tangent points the other way
+        let flipped: Vec<f32> = handedness(&original).iter().map(|h| -h).collect();
+        assert_eq!(flipped, handedness(&mirrored));
+    }
+
+    #[test]
+    fn decompose_joint_scale() {
+        let rotation = UnitQuaternion::from_euler_angles(0.3, -1.2, 0.7);
+        let mat = Translation3::new(1.0, 2.0, 3.0).to_homogeneous()
+            * rotation.to_homogeneous()
+            * Matrix4::new_scaling(0.01);
+        let (isometry, scale) = decompose_uniform_scale(mat, "joint \"root\"");
+        assert_relative_eq!(scale, 0.01, epsilon = 1.0e-6);
+        assert!(isometry.rotation.angle_to(&rotation) < 1.0e-3);
+        assert!((isometry.translation.vector - Vector3::new(1.0, 2.0, 3.0)).norm() < 1.0e-5);
+    }
+
+    #[test]
+    #[should_panic(expected = "joint \"spine\" has non-uniform scale")]
+    fn decompose_joint_non_uniform_scale() {
+        let mat = Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 2.0, 1.0));
+        decompose_uniform_scale(mat, "joint \"spine\"");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn joint_translation_scale_unscaled_rig() {
+        let i_gltf = ImportedGltf::try_from(Path::new(STORMTROOPER)).unwrap();
+        for no_transform in [true, false] {
+            let base = i_gltf.mesh_node_transform(no_transform);
+            assert!(i_gltf.joint_translation_scale(&base).is_empty());
+        }
+    }
+
+    /// Windmill without skin: "Tower" with the animated "Blades" child, both a single triangle.
+    fn windmill() -> ImportedGltf {
+        let mut i_gltf = windmill_with(|_| {});
+        i_gltf.rigid_nodes = true;
+        i_gltf
+    }
+
+    /// Tower with an animated Blades child node, `patch` can change the glTF JSON.
+    fn windmill_with(patch: impl FnOnce(&mut serde_json::Value)) -> ImportedGltf {
+        fn put(bin: &mut Vec<u8>, values: &[f32]) {
+            for v in values {
+                bin.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        let mut bin: Vec<u8> = Vec::new();
+        put(&mut bin, &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        put(&mut bin, &[0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0]);
+        put(
+            &mut bin,
+            &[1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0],
+        );
+        put(&mut bin, &[0.0, 0.0, 1.0, 0.0, 0.0, 1.0]);
+        for i in [0u16, 1, 2, 0] {
+            bin.extend_from_slice(&i.to_le_bytes());
+        }
+        // key frames: rotation of the blades around z by 0°, 90° and 180°
+        put(&mut bin, &[0.0, 1.0, 2.0]);
+        put(&mut bin, &[0.0, 0.0, 0.0, 1.0]);
+        put(&mut bin, &[0.0, 0.0, 0.70710677, 0.70710677]);
+        put(&mut bin, &[0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(bin.len(), 212);
+
+        let mut json = serde_json::json!({
+            "asset": { "version": "2.0" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0, 2] }],
+            "nodes": [
+                { "name": "Tower", "mesh": 0, "children": [1] },
+                { "name": "Blades", "mesh": 0, "translation": [0.0, 5.0, 0.0] },
+                { "name": "Light" }
+            ],
+            "meshes": [{ "primitives": [{
+                "attributes": { "POSITION": 0, "NORMAL": 1, "TANGENT": 2, "TEXCOORD_0": 3 },
+                "indices": 4
+            }]}],
+            "animations": [{
+                "channels": [{ "sampler": 0, "target": { "node": 1, "path": "rotation" } }],
+                "samplers": [{ "input": 5, "output": 6 }]
+            }],
+            "buffers": [{ "byteLength": 212, "uri": "windmill.bin" }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 72, "byteLength": 48 },
+                { "buffer": 0, "byteOffset": 120, "byteLength": 24 },
+                { "buffer": 0, "byteOffset": 144, "byteLength": 6 },
+                { "buffer": 0, "byteOffset": 152, "byteLength": 12 },
+                { "buffer": 0, "byteOffset": 164, "byteLength": 48 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0] },
+                { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC4" },
+                { "bufferView": 3, "componentType": 5126, "count": 3, "type": "VEC2" },
+                { "bufferView": 4, "componentType": 5123, "count": 3, "type": "SCALAR" },
+                { "bufferView": 5, "componentType": 5126, "count": 3, "type": "SCALAR",
+                  "min": [0.0], "max": [2.0] },
+                { "bufferView": 6, "componentType": 5126, "count": 3, "type": "VEC4" }
+            ]
+        });
+        patch(&mut json);
+        let dir = std::env::temp_dir().join("rdm4_windmill");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("windmill.bin"), bin).unwrap();
+        let name = format!("windmill_{:x}.gltf", hash_str(&json.to_string()));
+        std::fs::write(dir.join(&name), json.to_string()).unwrap();
+        ImportedGltf::try_from(dir.join(&name).as_path()).unwrap()
+    }
+
+    fn hash_str(s: &str) -> u64 {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+        let mut hasher = DefaultHasher::new();
+        s.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn rigid_nodes() {
+        let i_gltf = windmill();
+        let format = TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b;
+        let rdm = i_gltf.gltf_to_rdm(format, true, false, false, None);
+
+        let joints = rdm.joints.as_ref().unwrap();
+        let names: Vec<&str> = joints.iter().map(|j| j.name.as_str()).collect();
+        assert_eq!(names, vec!["Tower", "Blades"]);
+        assert_eq!(joints[0].parent, 255);
+        assert_eq!(joints[1].parent, 0);
+        assert_eq!(joints[1].transition, [0.0, 5.0, 0.0]);
+
+        // both nodes are separate MeshInstances, vertices are bound to their node
+        assert_eq!(rdm.mesh_info.len(), 2);
+        let i4b: Vec<I4b> = rdm.vertex.iter(0).unwrap().collect();
+        let w4b: Vec<W4b> = rdm.vertex.iter(0).unwrap().collect();
+        let p4h: Vec<P4h> = rdm.vertex.iter(0).unwrap().collect();
+        let bound: Vec<u8> = i4b.iter().map(|i| i.data[0]).collect();
+        assert_eq!(bound, vec![0, 0, 0, 1, 1, 1]);
+        assert!(w4b.iter().all(|w| w.data == [255, 0, 0, 0]));
+        assert_eq!(p4h[4].data[1].to_f32(), 5.0);
+
+        let mut anims = i_gltf.read_animation(joints, 6, 0.33333, false).unwrap();
+        assert_eq!(anims.len(), 1);
+        let anim = anims.remove(0);
+        assert_eq!(anim.time_max, 2000);
+        assert!(anim.anim_vec.iter().all(|track| track.len == 3));
+
+        // at t=1s the blades are rotated by 90° around their pivot at (0, 5, 0)
+        let blades = anim.anim_vec.iter().find(|c| c.name == "Blades").unwrap();
+        let frame = &blades.frames[1];
+        assert_relative_eq!(frame.time, 1.0);
+        let local = Isometry3::from_parts(
+            Translation3::from(Vector3::from(frame.translation)),
+            frame.unit_rotation(),
+        );
+        let tip = local * Point3::new(1.0, 0.0, 0.0);
+        assert!((tip - Point3::new(0.0, 6.0, 0.0)).norm() < 1.0e-5);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn merge_scene() {
+        let mut i_gltf = windmill_with(|json| {
+            // the blades get their own mesh with a material
+            let mut blades = json["meshes"][0].clone();
+            blades["primitives"][0]["material"] = serde_json::json!(0);
+            json["meshes"].as_array_mut().unwrap().push(blades);
+            json["materials"] = serde_json::json!([{ "name": "Wood" }]);
+            json["nodes"][1]["mesh"] = serde_json::json!(1);
+            json["nodes"][2]["mesh"] = serde_json::json!(0);
+            json["nodes"][2]["translation"] = serde_json::json!([10.0, 0.0, 0.0]);
+        });
+        i_gltf.merge_scene = true;
+        let format = TargetVertexFormat::P4h_N4b_G4b_B4b_T2h;
+        let rdm = i_gltf.gltf_to_rdm(format, false, false, true, None);
+
+        assert!(rdm.joints.is_none());
+        assert_eq!(rdm.vertex.len(), 9);
+        let materials: Vec<u32> = rdm.mesh_info.iter().map(|m| m.material).collect();
+        // Tower (no material), Blades (Wood), Light (no material)
+        assert_eq!(materials, vec![0, 1, 0]);
+
+        // world transforms are baked, also with no_transform
+        let p4h: Vec<P4h> = rdm.vertex.iter(0).unwrap().collect();
+        assert_eq!(p4h[4].data[1].to_f32(), 5.0);
+        assert_eq!(p4h[7].data[0].to_f32(), 11.0);
+        let indices: Vec<[u16; 3]> = rdm.triangle_indices.iter().map(|t| t.indices).collect();
+        assert_eq!(indices, vec![[0, 1, 2], [3, 4, 5], [6, 7, 8]]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn mesh_names() {
+        let mut i_gltf = windmill_with(|json| {
+            let mut meshes = json["meshes"].as_array().unwrap().clone();
+            meshes[0]["name"] = serde_json::json!("tower/lod 0");
+            meshes.push(meshes[0].clone());
+            meshes.push(serde_json::json!({ "primitives": meshes[0]["primitives"].clone() }));
+            json["meshes"] = serde_json::json!(meshes);
+            json["nodes"][2]["mesh"] = serde_json::json!(2);
+        });
+        // mesh 1 is not used by any node
+        assert_eq!(i_gltf.instantiated_meshes(), vec![0, 2]);
+        assert_eq!(i_gltf.mesh_name(), "tower_lod_0");
+        i_gltf.change_mesh_index(2);
+        // unnamed mesh: name of its node
+        assert_eq!(i_gltf.mesh_name(), "Light");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn material_map() {
+        let mut i_gltf = windmill_with(|json| {
+            let mut blades = json["meshes"][0].clone();
+            json["meshes"][0]["primitives"][0]["material"] = serde_json::json!(0);
+            blades["primitives"][0]["material"] = serde_json::json!(1);
+            json["meshes"].as_array_mut().unwrap().push(blades);
+            json["materials"] = serde_json::json!([{ "name": "stone" }, { "name": "wood" }]);
+            json["nodes"][1]["mesh"] = serde_json::json!(1);
+        });
+        i_gltf.merge_scene = true;
+        i_gltf.material_map = Some(MaterialMap::from([
+            ("wood".to_owned(), 0),
+            ("stone".to_owned(), 3),
+        ]));
+        let format = TargetVertexFormat::P4h_N4b_G4b_B4b_T2h;
+        let rdm = i_gltf.gltf_to_rdm(format, false, false, false, None);
+        let materials: Vec<u32> = rdm.mesh_info.iter().map(|m| m.material).collect();
+        assert_eq!(materials, vec![3, 0]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn material_textures() {
+        let dir = std::env::temp_dir().join("rdm4_windmill");
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = RgbaImage {
+            width: 8,
+            height: 4,
+            rgba: (0..8 * 4 * 4).map(|i| i as u8).collect(),
+        };
+        std::fs::write(dir.join("windmill_color.png"), image.to_png()).unwrap();
+        let mut i_gltf = windmill_with(|json| {
+            let mut blades = json["meshes"][0].clone();
+            json["meshes"][0]["primitives"][0]["material"] = serde_json::json!(0);
+            blades["primitives"][0]["material"] = serde_json::json!(1);
+            json["meshes"].as_array_mut().unwrap().push(blades);
+            json["nodes"][1]["mesh"] = serde_json::json!(1);
+            json["images"] = serde_json::json!([{ "uri": "windmill_color.png" }]);
+            json["textures"] = serde_json::json!([{ "source": 0 }]);
+            json["materials"] = serde_json::json!([
+                {
+                    "name": "stone wall",
+                    "pbrMetallicRoughness": {
+                        "baseColorTexture": { "index": 0 },
+                        "metallicRoughnessTexture": { "index": 0 }
+                    },
+                    "normalTexture": { "index": 0 }
+                },
+                {
+                    "pbrMetallicRoughness": { "metallicRoughnessTexture": { "index": 0 } },
+                    "emissiveTexture": { "index": 0 }
+                }
+            ]);
+        });
+        let names = |i_gltf: &ImportedGltf| -> Vec<String> {
+            i_gltf
+                .material_textures()
+                .into_iter()
+                .map(|t| t.file_name)
+                .collect()
+        };
+        assert_eq!(
+            names(&i_gltf),
+            vec![
+                "stone_wall_diff_0.dds",
+                "stone_wall_norm_0.dds",
+                "stone_wall_metal_0.dds"
+            ]
+        );
+        i_gltf.merge_scene = true;
+        let textures = i_gltf.material_textures();
+        assert_eq!(
+            names(&i_gltf),
+            vec![
+                "stone_wall_diff_0.dds",
+                "stone_wall_norm_0.dds",
+                "stone_wall_metal_0.dds",
+                "material_1_metal_0.dds"
+            ]
+        );
+        assert_eq!(textures[0].image, image);
+        let of_all = i_gltf.material_textures_of(&i_gltf.instantiated_meshes());
+        assert_eq!(
+            of_all.iter().map(|t| &t.file_name).collect::<Vec<_>>(),
+            names(&i_gltf).iter().collect::<Vec<_>>()
+        );
+        let pixels = |t: &MaterialTexture| -> Vec<[u8; 4]> {
+            t.image
+                .rgba
+                .chunks_exact(4)
+                .map(|p| p.try_into().unwrap())
+                .collect()
+        };
+        for (i, p) in image.rgba.chunks_exact(4).enumerate() {
+            // normal in red and green, glossiness in blue
+            assert_eq!(pixels(&textures[1])[i], [p[0], p[1], 255 - p[1], 255]);
+            // metalness in red, no emissive texture
+            assert_eq!(pixels(&textures[2])[i], [p[2], 0, 0, 0]);
+            // emissive mask in alpha
+            assert_eq!(pixels(&textures[3])[i], [p[2], 0, 0, p[2]]);
+        }
+        let dds = textures[3].to_dds();
+        assert_eq!(dds_header_size(&dds), (8, 4));
+    }
+
+    fn dds_header_size(dds: &[u8]) -> (u32, u32) {
+        let read = |offset: usize| u32::from_le_bytes(dds[offset..offset + 4].try_into().unwrap());
+        (read(16), read(12))
+    }
+
+    #[test]
+    fn rgba_image_formats() {
+        let image = |format, pixels: Vec<u8>| {
+            rgba_image(&gltf::image::Data {
+                pixels,
+                format,
+                width: 1,
+                height: 1,
+            })
+            .rgba
+        };
+        use gltf::image::Format;
+        assert_eq!(image(Format::R8, vec![7]), vec![7, 7, 7, 255]);
+        assert_eq!(image(Format::R8G8, vec![1, 2]), vec![1, 1, 1, 2]);
+        assert_eq!(image(Format::R16G16, vec![0, 1, 0, 2]), vec![1, 1, 1, 2]);
+        assert_eq!(image(Format::B8G8R8, vec![1, 2, 3]), vec![3, 2, 1, 255]);
+        assert_eq!(
+            image(Format::R16G16B16A16, vec![0, 1, 0, 2, 0, 3, 0xFF, 0xFF]),
+            vec![1, 2, 3, 255]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    #[should_panic(expected = "is not in the material map")]
+    fn material_map_missing_name() {
+        let mut i_gltf = windmill_with(|json| {
+            json["meshes"][0]["primitives"][0]["material"] = serde_json::json!(0);
+            json["materials"] = serde_json::json!([{ "name": "stone" }]);
+        });
+        i_gltf.material_map = Some(MaterialMap::from([("wood".to_owned(), 0)]));
+        let format = TargetVertexFormat::P4h_N4b_G4b_B4b_T2h;
+        i_gltf.gltf_to_rdm(format, false, false, false, None);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn weld_vertices() {
+        let mut i_gltf = ImportedGltf::try_from(Path::new(STORMTROOPER)).unwrap();
+        let format = || TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b;
+        let rdm = i_gltf.gltf_to_rdm(format(), true, false, false, None);
+        i_gltf.weld = true;
+        let welded = i_gltf.gltf_to_rdm(format(), true, false, false, None);
+        assert!(welded.vertex.len() < rdm.vertex.len());
+
+        let size = rdm.vertex.get_size() as usize;
+        let corners = |r: &RdModell, t: &Triangle| -> Vec<Vec<u8>> {
+            t.indices
+                .iter()
+                .map(|&v| r.vertex.as_bytes()[v as usize * size..][..size].to_vec())
+                .collect()
+        };
+        // welded vertices are unique and every triangle keeps its exact vertex data
+        let unique: HashSet<&[u8]> = welded.vertex.as_bytes().chunks_exact(size).collect();
+        assert_eq!(unique.len(), welded.vertex.len() as usize);
+        let triangles: HashSet<Vec<Vec<u8>>> = rdm
+            .triangle_indices
+            .iter()
+            .map(|t| corners(&rdm, t))
+            .collect();
+        for t in welded.triangle_indices.iter() {
+            assert!(triangles.contains(&corners(&welded, t)));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn shared_primitive_buffers() {
+        let i_gltf = windmill_with(|json| {
+            let primitive = json["meshes"][0]["primitives"][0].clone();
+            json["meshes"][0]["primitives"]
+                .as_array_mut()
+                .unwrap()
+                .push(primitive);
+        });
+        let format = TargetVertexFormat::P4h_N4b_G4b_B4b_T2h;
+        let rdm = i_gltf.gltf_to_rdm(format, false, false, true, None);
+        // both primitives use the same vertices
+        assert_eq!(rdm.mesh_info.len(), 2);
+        assert_eq!(rdm.vertex.len(), 3);
+        assert_eq!(
+            rdm.triangle_indices[0].indices,
+            rdm.triangle_indices[1].indices
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use bytes::{Buf, Bytes};
+use std::collections::HashMap;
 use std::num::NonZeroU32;
 use std::path::Path;
 
@@ -16,12 +17,16 @@ extern crate log;
 #[macro_use]
 extern crate approx;
 
+pub mod axis;
+pub mod dds;
 pub mod gltf_export;
 pub mod gltf_reader;
+pub mod optimize;
 pub mod rdm_anim;
 pub mod rdm_anim_writer;
 pub mod rdm_material;
 pub mod rdm_writer;
+pub mod simplify;
 pub mod vertex;
 use crate::rdm_anim::RdAnim;
 use rdm_material::RdMaterial;
@@ -44,10 +49,19 @@ pub struct RdModell {
     triangles_offset: Option<NonZeroU32>,
     pub triangles_idx_count: u32,
 
-    anim: Option<RdAnim>,
+    anim: Vec<RdAnim>,
     pub mat: Option<RdMaterial>,
 }
 
+/// Loads a JSON object of name -> value, e.g. a [`rdm_anim::JointMap`] or a
+/// [`gltf_reader::MaterialMap`].
+pub fn load_json_map<V: serde::de::DeserializeOwned, P: AsRef<Path>>(
+    f_path: P,
+) -> Result<HashMap<String, V>, Box<dyn std::error::Error>> {
+    let f = File::open(f_path)?;
+    Ok(serde_json::from_reader(std::io::BufReader::new(f))?)
+}
+
 trait Seek {
     fn seek(&mut self, from_start: u32, file_size: u32);
 }
@@ -69,6 +83,28 @@ pub struct RdJoint {
     locked: bool,
 }
 
+/// Material entry stored in the rdm itself: the material name and texture path of the tool that
+/// exported it, plus bytes of unknown meaning around them. Kept when an rdm is rewritten.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RdmMaterialEntry {
+    pub name: Vec<u8>,
+    pub texture: Vec<u8>,
+    entry_tail: [u8; 24],
+    header_tail: [u8; 40],
+}
+
+impl Default for RdmMaterialEntry {
+    /// Placeholder written for models without rdm materials, e.g. glTF imports.
+    fn default() -> Self {
+        RdmMaterialEntry {
+            name: br"Default Standard12432142134".to_vec(),
+            texture: br"d:/projekte/anno5/game/testdata/graphics/dummy_objects/dummy_christian/rdm/basalt_crusher_others/diffuse.png".to_vec(),
+            entry_tail: [0; 24],
+            header_tail: [0; 40],
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct MeshInstance {
     start_index_location: u32,
@@ -94,8 +130,216 @@ impl RdModell {
         self.joints.is_some()
     }
 
+    /// Material entries of the rdm file this model was read from. Empty for models that were
+    /// not read from an rdm.
+    pub fn rdm_materials(&self) -> Vec<RdmMaterialEntry> {
+        let buf = &self.buffer[..];
+        let u32_at = |off: usize| -> Option<usize> {
+            let bytes = buf.get(off..off + 4)?;
+            Some(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+        };
+        // length prefixed data: count at ptr - 8, element size at ptr - 4
+        let string_at = |ptr: usize| -> Option<Vec<u8>> {
+            let len = u32_at(ptr.checked_sub(8)?)?;
+            Some(buf.get(ptr..ptr + len)?.to_vec())
+        };
+        let entry_at = |ptr: usize| -> Option<RdmMaterialEntry> {
+            let header = u32_at(ptr)?;
+            Some(RdmMaterialEntry {
+                name: string_at(u32_at(header)?)?,
+                texture: string_at(u32_at(header + 4)?)?,
+                entry_tail: buf.get(ptr + 4..ptr + 28)?.try_into().unwrap(),
+                header_tail: buf.get(header + 8..header + 48)?.try_into().unwrap(),
+            })
+        };
+
+        let blob = match u32_at(36) {
+            Some(ptr) if ptr >= 8 => ptr,
+            _ => return Vec::new(),
+        };
+        let (count, size) = match (u32_at(blob - 8), u32_at(blob - 4)) {
+            (Some(count), Some(28)) => (count, 28),
+            _ => return Vec::new(),
+        };
+        let entries: Option<Vec<_>> = (0..count).map(|i| entry_at(blob + i * size)).collect();
+        entries.unwrap_or_else(|| {
+            warn!("could not read the materials of the rdm, they are not kept");
+            Vec::new()
+        })
+    }
+
+    /// Joint name -> parent joint name of the skeleton, see [`RdModell::skeleton_from_anim`].
+    pub fn joint_parents(&self) -> HashMap<String, String> {
+        let joints = self.joints.as_ref().expect("joint_parents needs add_skin");
+        joints
+            .iter()
+            .filter(|j| j.parent != 255)
+            .map(|j| (j.name.clone(), joints[j.parent as usize].name.clone()))
+            .collect()
+    }
+
+    /// Builds a mesh-less model with a bone-only skeleton for `anim`, the rest pose is the first
+    /// key frame of every track.
+    /// Animation files do not store the joint hierarchy: `parents` maps a track name to the track
+    /// name of its parent joint, e.g. from [`RdModell::joint_parents`] of the model the
+    /// animation was made for. Tracks without a parent track become root joints, with an empty
+    /// map the hierarchy is flat and only the motion of root bones is correct.
+    pub fn skeleton_from_anim(anim: &RdAnim, parents: &HashMap<String, String>) -> Self {
+        if parents.is_empty() {
+            warn!(
+                "no bone hierarchy for {:?}: all bones are roots, only the motion of root bones is correct",
+                anim.name
+            );
+        }
+
+        let track_idx: HashMap<&str, usize> = anim
+            .anim_vec
+            .iter()
+            .enumerate()
+            .map(|(i, track)| (track.name.as_str(), i))
+            .collect();
+        let parent_of: Vec<Option<usize>> = anim
+            .anim_vec
+            .iter()
+            .map(|track| {
+                let parent = parents.get(&track.name)?;
+                match track_idx.get(parent.as_str()) {
+                    Some(&i) if *parent != track.name => Some(i),
+                    _ => {
+                        warn!(
+                            "parent {:?} of bone {:?} is not a track of the animation, {:?} becomes a root",
+                            parent, track.name, track.name
+                        );
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        // parents before children; bones of a parent cycle are made roots
+        let mut order = Vec::with_capacity(parent_of.len());
+        let mut visited = vec![false; parent_of.len()];
+        let mut new_parent: Vec<Option<usize>> = parent_of.clone();
+        let roots: Vec<usize> = (0..parent_of.len())
+            .filter(|&i| parent_of[i].is_none())
+            .collect();
+        let cyclic = (0..parent_of.len()).filter(|&i| parent_of[i].is_some());
+        for root in roots.into_iter().chain(cyclic) {
+            if visited[root] {
+                continue;
+            }
+            if parent_of[root].is_some() {
+                warn!(
+                    "bone {:?} is part of a parent cycle and becomes a root",
+                    anim.anim_vec[root].name
+                );
+                new_parent[root] = None;
+            }
+            let mut stack = vec![root];
+            while let Some(i) = stack.pop() {
+                visited[i] = true;
+                order.push(i);
+                let children = (0..parent_of.len())
+                    .rev()
+                    .filter(|&c| !visited[c] && parent_of[c] == Some(i));
+                stack.extend(children);
+            }
+        }
+        assert!(
+            order.len() <= 255,
+            "{} bones, a skeleton can have at most 255",
+            order.len()
+        );
+
+        let mut joint_idx = vec![0; order.len()];
+        for (j, &i) in order.iter().enumerate() {
+            joint_idx[i] = j;
+        }
+        // RdJoint stores the global pose, key frames are relative to the parent
+        let mut global: Vec<Isometry3<f32>> = Vec::with_capacity(order.len());
+        let mut joints = Vec::with_capacity(order.len());
+        for &i in &order {
+            let track = &anim.anim_vec[i];
+            let local = match track.frames.first() {
+                // rdm key frames store the rotation with negated w
+                Some(f) => Isometry3::from_parts(
+                    Translation3::from(Vector3::from(f.translation)),
+                    UnitQuaternion::from_quaternion(Quaternion::new(
+                        -f.rotation[3],
+                        f.rotation[0],
+                        f.rotation[1],
+                        f.rotation[2],
+                    )),
+                ),
+                None => Isometry3::identity(),
+            };
+            let parent = new_parent[i].map(|p| joint_idx[p]);
+            let pose = match parent {
+                Some(p) => global[p] * local,
+                None => local,
+            };
+            global.push(pose);
+
+            let q = pose.rotation.quaternion();
+            joints.push(RdJoint {
+                name: track.name.clone(),
+                transition: pose.translation.vector.into(),
+                quaternion: [q.i, q.j, q.k, q.w],
+                parent: parent.map_or(255, |p| p as u8),
+                locked: false,
+            });
+        }
+
+        RdModell {
+            size: 0,
+            buffer: Bytes::new(),
+            mesh_info: Vec::new(),
+            joints: Some(joints),
+            triangle_indices: Vec::new(),
+            meta: None,
+            vertex: VertexFormat2::new(Box::new([]), 0, 0, None, Bytes::new()),
+            triangles_offset: None,
+            triangles_idx_count: 0,
+            anim: Vec::new(),
+            mat: None,
+        }
+    }
+
+    pub fn has_mesh(&self) -> bool {
+        !self.mesh_info.is_empty()
+    }
+
     pub fn add_anim(&mut self, anim: RdAnim) {
-        self.anim = Some(anim);
+        self.anim.push(anim);
+    }
+
+    /// Moves the model into the space mapped by `m`, a rotation or mirroring times a uniform scale
+    /// (see [`axis::AxisConversion`]). Vertices are mapped by `m`, joints and attached animations are
+    /// conjugated so they stay rigid. A mirroring `m` flips the triangle winding.
+    pub fn transform_space(&mut self, m: &Matrix3<f32>) {
+        self.vertex.transform(m);
+        if m.determinant() < 0.0 {
+            for t in self.triangle_indices.iter_mut() {
+                t.indices.swap(1, 2);
+            }
+        }
+        if let Some(joints) = self.joints.as_mut() {
+            for j in joints.iter_mut() {
+                let [x, y, z, w] = j.quaternion;
+                let bind = Isometry3::from_parts(
+                    Translation3::from(Vector3::from(j.transition)),
+                    UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z)),
+                );
+                let bind = axis::conjugate_isometry(m, &bind);
+                let t = bind.translation.vector;
+                let r = bind.rotation.coords;
+                j.transition = [t.x, t.y, t.z];
+                j.quaternion = [r.x, r.y, r.z, r.w];
+            }
+        }
+        for anim in self.anim.iter_mut() {
+            anim.transform_space(m);
+        }
     }
 
     pub fn check_has_magic_byte(bytes: &[u8]) {
@@ -276,7 +520,7 @@ impl RdModell {
             vertex: vvert,
             triangles_offset: NonZeroU32::new(triangles_count_off),
             triangles_idx_count,
-            anim: None,
+            anim: Vec::new(),
             mat: None,
         }
     }
@@ -325,4 +569,189 @@ mod tests_intern {
             rdm.triangle_indices.len() * 3
         );
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn skeleton_from_anim_hierarchy() {
+        let mut model = RdModell::from("rdm/basalt_crusher_others_lod2.rdm");
+        model.add_skin();
+        let parents = model.joint_parents();
+        assert!(!parents.is_empty());
+
+        let anim = RdAnim::from("rdm/basalt_crusher_others_work01.rdm");
+        let rdm = RdModell::skeleton_from_anim(&anim, &parents);
+        let joints = rdm.joints.as_ref().unwrap();
+        assert_eq!(joints.len(), anim.anim_vec.len());
+
+        let pose = |t: [f32; 3], q: [f32; 4]| {
+            Isometry3::from_parts(
+                Translation3::from(Vector3::from(t)),
+                UnitQuaternion::from_quaternion(Quaternion::new(q[3], q[0], q[1], q[2])),
+            )
+        };
+        let mut children = 0;
+        for (i, joint) in joints.iter().enumerate() {
+            if joint.parent == 255 {
+                assert!(!parents.contains_key(&joint.name));
+                continue;
+            }
+            children += 1;
+            let parent = &joints[joint.parent as usize];
+            assert!((joint.parent as usize) < i);
+            assert_eq!(parent.name, parents[&joint.name]);
+
+            // the rest pose is the first key frame relative to the parent
+            let track = anim.anim_vec.iter().find(|t| t.name == joint.name);
+            let frame = &track.unwrap().frames[0];
+            let [x, y, z, w] = frame.rotation;
+            let expected =
+                pose(parent.transition, parent.quaternion) * pose(frame.translation, [x, y, z, -w]);
+            let global = pose(joint.transition, joint.quaternion);
+            assert!((global.translation.vector - expected.translation.vector).norm() < 1e-4);
+            assert!(global.rotation.angle_to(&expected.rotation) < 1e-3);
+        }
+        assert!(children > 0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn write_skinned_round_trip() {
+        let i_gltf = gltf_reader::ImportedGltf::try_from(std::path::Path::new(
+            "rdm/gltf/stormtrooper_with_tangent.gltf",
+        ))
+        .unwrap();
+        let format = vertex::TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b;
+        let rdm = i_gltf.gltf_to_rdm(format, true, false, false, None);
+        let joints: Vec<vertex::I4b> = rdm.vertex.iter(0).unwrap().collect();
+        let joint_count = rdm.joints.as_ref().unwrap().len();
+
+        let dir = std::path::PathBuf::from("rdm_out/write_skinned_round_trip");
+        std::fs::create_dir_all(&dir).unwrap();
+        rdm_writer::RdWriter::from(rdm).write_rdm(Some(dir.clone()), false);
+
+        let mut read = RdModell::from(dir.join("out.rdm").as_path());
+        read.add_skin();
+        assert_eq!(read.joints.as_ref().unwrap().len(), joint_count);
+        let read_joints: Vec<vertex::I4b> = read.vertex.iter(0).unwrap().collect();
+        assert_eq!(read_joints, joints);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn transform_space_round_trip() {
+        use axis::{Axis, AxisConversion};
+        use vertex::{P3f, P4h};
+
+        let axes = AxisConversion {
+            up: Axis::X,
+            forward: Axis::Y,
+            left_handed: true,
+            scale: 4.0,
+        };
+        let rdm = RdModell::from("rdm/basalt_crusher_others_lod2.rdm");
+        let mut converted = RdModell::from("rdm/basalt_crusher_others_lod2.rdm");
+        converted.transform_space(&axes.to_gltf());
+        converted.transform_space(&axes.to_rdm());
+
+        let positions =
+            |r: &RdModell| -> Vec<P3f> { r.vertex.iter::<P4h, _>(0).unwrap().collect() };
+        for (p, q) in positions(&rdm).iter().zip(positions(&converted).iter()) {
+            for k in 0..3 {
+                assert!((p.data[k] - q.data[k]).abs() < 1e-3 * (1.0 + p.data[k].abs()));
+            }
+        }
+        for (t, u) in rdm
+            .triangle_indices
+            .iter()
+            .zip(converted.triangle_indices.iter())
+        {
+            assert_eq!(t.indices, u.indices);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn transform_space_axes() {
+        let path = std::path::Path::new("rdm/gltf/stormtrooper_with_tangent.gltf");
+        let format = || vertex::TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b;
+        let mut i_gltf = gltf_reader::ImportedGltf::try_from(path).unwrap();
+        let rdm = i_gltf.gltf_to_rdm(format(), true, false, false, None);
+        let anims = i_gltf
+            .read_animation(rdm.joints.as_ref().unwrap(), 0, 0.0, false)
+            .unwrap();
+
+        // Z up, -Y forward, centimetres
+        i_gltf.axes = axis::AxisConversion {
+            up: axis::Axis::Z,
+            forward: axis::Axis::NegY,
+            left_handed: false,
+            scale: 0.01,
+        };
+        let m = i_gltf.axes.to_rdm();
+        assert!((m * Vector3::z() - Vector3::y() * 0.01).norm() < 1e-6);
+        assert!((m * -Vector3::y() - Vector3::z() * 0.01).norm() < 1e-6);
+        let converted = i_gltf.gltf_to_rdm(format(), true, false, false, None);
+        let converted_anims = i_gltf
+            .read_animation(converted.joints.as_ref().unwrap(), 0, 0.0, false)
+            .unwrap();
+
+        let positions = |r: &RdModell| -> Vec<vertex::P3f> {
+            r.vertex.iter::<vertex::P4h, _>(0).unwrap().collect()
+        };
+        for (p, q) in positions(&rdm).iter().zip(positions(&converted).iter()) {
+            let expected = m * Vector3::from(p.data);
+            assert!((expected - Vector3::from(q.data)).amax() < 1e-3 * (1.0 + expected.amax()));
+        }
+        let normals = |r: &RdModell| -> Vec<vertex::N3f> {
+            r.vertex.iter::<vertex::N4b, _>(0).unwrap().collect()
+        };
+        for (n, k) in normals(&rdm).iter().zip(normals(&converted).iter()) {
+            let expected = (m * Vector3::from(n.data)).normalize();
+            assert!((expected - Vector3::from(k.data)).norm() < 0.05);
+        }
+        for (t, u) in rdm
+            .triangle_indices
+            .iter()
+            .zip(converted.triangle_indices.iter())
+        {
+            assert_eq!(t.indices, u.indices);
+        }
+
+        let r = m * 100.0;
+        let joint_iso = |j: &RdJoint| {
+            let [x, y, z, w] = j.quaternion;
+            Isometry3::from_parts(
+                Translation3::from(Vector3::from(j.transition)),
+                UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z)),
+            )
+        };
+        for (j, k) in rdm
+            .joints
+            .as_ref()
+            .unwrap()
+            .iter()
+            .zip(converted.joints.as_ref().unwrap().iter())
+        {
+            let (a, b) = (joint_iso(j), joint_iso(k));
+            assert!((m * a.translation.vector - b.translation.vector).norm() < 1e-4);
+            let expected = r * a.rotation.to_rotation_matrix().matrix() * r.transpose();
+            assert!((expected - b.rotation.to_rotation_matrix().matrix()).amax() < 1e-4);
+        }
+
+        for (a, b) in anims.iter().zip(converted_anims.iter()) {
+            for c in a.anim_vec.iter() {
+                let d = b.anim_vec.iter().find(|d| d.name == c.name).unwrap();
+                for (f, g) in c.frames.iter().zip(d.frames.iter()) {
+                    let expected = m * Vector3::from(f.translation);
+                    assert!((expected - Vector3::from(g.translation)).norm() < 1e-4);
+                }
+            }
+        }
+
+        // a left-handed source flips the winding
+        i_gltf.axes.left_handed = true;
+        let mirrored = i_gltf.gltf_to_rdm(format(), true, false, false, None);
+        let t = rdm.triangle_indices[0].indices;
+        assert_eq!(mirrored.triangle_indices[0].indices, [t[0], t[2], t[1]]);
+    }
 }
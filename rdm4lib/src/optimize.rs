@@ -0,0 +1,260 @@
+//! Post-transform vertex cache optimisation of the index buffer (Tom Forsyth, "Linear-Speed Vertex
+//! Cache Optimisation") followed by vertex fetch reordering.
+
+use bytes::Bytes;
+
+use crate::{RdModell, Triangle};
+
+/// Size of the LRU cache modelled by the triangle ordering.
+const CACHE_SIZE: usize = 32;
+/// FIFO cache size used to measure the ACMR, a typical post-transform cache of real hardware.
+const ACMR_CACHE_SIZE: usize = 16;
+
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRI_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+/// Average cache miss ratio before and after [`RdModell::optimize_vertex_cache`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheStats {
+    pub acmr_before: f32,
+    pub acmr_after: f32,
+}
+
+fn vertex_score(cache_pos: Option<usize>, remaining: u32) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_pos {
+        None => 0.0,
+        // the vertices of the last triangle: it is better not to reuse them immediately
+        Some(pos) if pos < 3 => LAST_TRI_SCORE,
+        Some(pos) => {
+            let scaler = 1.0 / (CACHE_SIZE - 3) as f32;
+            (1.0 - (pos - 3) as f32 * scaler).powf(CACHE_DECAY_POWER)
+        }
+    };
+    cache_score + VALENCE_BOOST_SCALE * (remaining as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+/// Forsyth ordering of `triangles`, returns the new order as indices into `triangles`.
+fn forsyth_order(triangles: &[Triangle], vertex_count: usize) -> Vec<usize> {
+    let mut vertex_triangles: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    for (t, triangle) in triangles.iter().enumerate() {
+        for &v in triangle.indices.iter() {
+            vertex_triangles[v as usize].push(t);
+        }
+    }
+    let mut remaining: Vec<u32> = vertex_triangles.iter().map(|t| t.len() as u32).collect();
+    let mut score: Vec<f32> = remaining.iter().map(|&r| vertex_score(None, r)).collect();
+    let mut added = vec![false; triangles.len()];
+    let triangle_score = |t: usize, score: &[f32]| -> f32 {
+        triangles[t]
+            .indices
+            .iter()
+            .map(|&v| score[v as usize])
+            .sum()
+    };
+
+    let mut order = Vec::with_capacity(triangles.len());
+    let mut cache: Vec<u16> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut cursor = 0;
+    while order.len() < triangles.len() {
+        // best triangle that uses a cached vertex, else the next one in input order
+        let best = cache
+            .iter()
+            .flat_map(|&v| vertex_triangles[v as usize].iter().copied())
+            .filter(|&t| !added[t])
+            .map(|t| (t, triangle_score(t, &score)))
+            .fold(None, |best: Option<(usize, f32)>, (t, s)| match best {
+                Some((_, best_score)) if best_score >= s => best,
+                _ => Some((t, s)),
+            })
+            .map(|(t, _)| t)
+            .unwrap_or_else(|| {
+                while added[cursor] {
+                    cursor += 1;
+                }
+                cursor
+            });
+
+        added[best] = true;
+        order.push(best);
+
+        let indices = triangles[best].indices;
+        for &v in indices.iter() {
+            remaining[v as usize] -= 1;
+        }
+        // move the triangle to the front of the LRU cache
+        let mut new_cache: Vec<u16> = indices.to_vec();
+        new_cache.extend(cache.iter().filter(|v| !indices.contains(v)));
+        for &evicted in new_cache.iter().skip(CACHE_SIZE) {
+            score[evicted as usize] = vertex_score(None, remaining[evicted as usize]);
+        }
+        new_cache.truncate(CACHE_SIZE);
+        for (pos, &v) in new_cache.iter().enumerate() {
+            score[v as usize] = vertex_score(Some(pos), remaining[v as usize]);
+        }
+        cache = new_cache;
+    }
+    order
+}
+
+/// Cache misses of drawing `triangles` with a FIFO post-transform cache.
+fn cache_misses(triangles: &[Triangle]) -> usize {
+    let mut cache: Vec<u16> = Vec::with_capacity(ACMR_CACHE_SIZE);
+    let mut misses = 0;
+    for v in triangles.iter().flat_map(|t| t.indices.iter()) {
+        if !cache.contains(v) {
+            misses += 1;
+            if cache.len() == ACMR_CACHE_SIZE {
+                cache.remove(0);
+            }
+            cache.push(*v);
+        }
+    }
+    misses
+}
+
+impl RdModell {
+    /// Triangles of every MeshInstance (index buffer range).
+    pub(crate) fn mesh_triangle_ranges(&self) -> Vec<std::ops::Range<usize>> {
+        self.mesh_info
+            .iter()
+            .map(|m| {
+                let first = m.start_index_location as usize / 3;
+                first..first + m.index_count as usize / 3
+            })
+            .collect()
+    }
+
+    /// Average cache miss ratio (vertex shader invocations per triangle) of the index buffer.
+    /// Every MeshInstance is a separate draw call and starts with an empty cache.
+    pub fn acmr(&self) -> f32 {
+        let triangles = self.triangle_indices.len();
+        if triangles == 0 {
+            return 0.0;
+        }
+        let misses: usize = self
+            .mesh_triangle_ranges()
+            .into_iter()
+            .map(|r| cache_misses(&self.triangle_indices[r]))
+            .sum();
+        misses as f32 / triangles as f32
+    }
+
+    /// Reorders the triangles of every MeshInstance for the post-transform vertex cache, then
+    /// reorders the vertex buffer into first use order. The MeshInstance ranges, materials and
+    /// bone palettes are unchanged.
+    pub fn optimize_vertex_cache(&mut self) -> CacheStats {
+        let acmr_before = self.acmr();
+        let vertex_count = self.vertex.len() as usize;
+
+        let mut triangles = Vec::with_capacity(self.triangle_indices.len());
+        for range in self.mesh_triangle_ranges() {
+            let mesh_triangles = &self.triangle_indices[range];
+            triangles.extend(
+                forsyth_order(mesh_triangles, vertex_count)
+                    .into_iter()
+                    .map(|t| mesh_triangles[t]),
+            );
+        }
+        assert_eq!(triangles.len(), self.triangle_indices.len());
+
+        // vertex fetch: renumber the vertices in order of first use, unused vertices go last
+        let mut remap: Vec<Option<u16>> = vec![None; vertex_count];
+        let mut vertex_order: Vec<usize> = Vec::with_capacity(vertex_count);
+        for t in triangles.iter_mut() {
+            for v in t.indices.iter_mut() {
+                *v = *remap[*v as usize].get_or_insert_with(|| {
+                    vertex_order.push(*v as usize);
+                    (vertex_order.len() - 1) as u16
+                });
+            }
+        }
+        vertex_order.extend((0..vertex_count).filter(|&v| remap[v].is_none()));
+
+        let size = self.vertex.get_size() as usize;
+        let src = self.vertex.as_bytes();
+        let mut buffer: Vec<u8> = Vec::with_capacity(src.len());
+        for v in vertex_order {
+            buffer.extend_from_slice(&src[v * size..(v + 1) * size]);
+        }
+        self.vertex = self
+            .vertex
+            .with_vertex_buffer(vertex_count as u32, Bytes::from(buffer));
+        self.triangle_indices = triangles;
+
+        CacheStats {
+            acmr_before,
+            acmr_after: self.acmr(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_optimize {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Vertex data of the corners of every triangle, per MeshInstance.
+    fn triangle_data(rdm: &RdModell) -> Vec<HashSet<Vec<u8>>> {
+        let size = rdm.vertex.get_size() as usize;
+        let bytes = rdm.vertex.as_bytes();
+        rdm.mesh_triangle_ranges()
+            .into_iter()
+            .map(|r| {
+                rdm.triangle_indices[r]
+                    .iter()
+                    .map(|t| {
+                        t.indices
+                            .iter()
+                            .flat_map(|&v| bytes[v as usize * size..][..size].to_vec())
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn optimize_vertex_cache() {
+        let mut rdm = RdModell::from("rdm/basalt_crusher_others_lod0.rdm");
+        let before = triangle_data(&rdm);
+        let vertex_count = rdm.vertex.len();
+
+        let stats = rdm.optimize_vertex_cache();
+        assert!(stats.acmr_after < stats.acmr_before, "{:?}", stats);
+        assert_eq!(stats.acmr_after, rdm.acmr());
+        assert_eq!(rdm.vertex.len(), vertex_count);
+        // same triangles (with the same vertex data and winding) in every MeshInstance
+        assert_eq!(triangle_data(&rdm), before);
+
+        // vertices are in order of first use
+        let mut next = 0;
+        for &v in rdm.triangle_indices.iter().flat_map(|t| t.indices.iter()) {
+            assert!(v <= next);
+            if v == next {
+                next += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn acmr_of_strip() {
+        // a strip of quads: every triangle adds one new vertex
+        let triangles: Vec<Triangle> = (0..100u16)
+            .map(|i| Triangle {
+                indices: [i, i + 1, i + 2],
+            })
+            .collect();
+        assert_eq!(cache_misses(&triangles), 102);
+
+        let order = forsyth_order(&triangles, 102);
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..100).collect::<Vec<_>>());
+    }
+}
@@ -1,11 +1,15 @@
 use bytes::{Buf, Bytes};
+use nalgebra::{Isometry3, Matrix3, Quaternion, Translation3, UnitQuaternion, Vector3};
 use std::path::Path;
+use std::str::FromStr;
 
 use std::fs::File;
 
+use crate::axis::conjugate_isometry;
 use crate::RdModell;
-use crate::Seek;
-use std::str;
+use crate::{RdJoint, Seek};
+use std::collections::{HashMap, HashSet};
+use std::{fmt, str};
 
 #[derive(Debug, Copy, Clone)]
 pub struct Frame {
@@ -14,6 +18,82 @@ pub struct Frame {
     pub time: f32,
 }
 
+impl Frame {
+    /// Linear interpolation of the translation and slerp of the rotation between two key frames.
+    fn interpolate(&self, next: &Frame, time: f32) -> Frame {
+        let span = next.time - self.time;
+        let t = if span > 0.0 {
+            ((time - self.time) / span).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let q0 = UnitQuaternion::from_quaternion(Quaternion::from(self.rotation));
+        let q1 = UnitQuaternion::from_quaternion(Quaternion::from(next.rotation));
+        let rotation = q0
+            .try_slerp(&q1, t, 1.0e-6)
+            .unwrap_or(if t < 0.5 { q0 } else { q1 })
+            .quaternion()
+            .coords;
+
+        let translation = Vector3::from(self.translation).lerp(&Vector3::from(next.translation), t);
+
+        Frame {
+            rotation: [rotation.x, rotation.y, rotation.z, rotation.w],
+            translation: [translation.x, translation.y, translation.z],
+            time,
+        }
+    }
+
+    /// rdm key frames store the rotation with negated w.
+    pub(crate) fn unit_rotation(&self) -> UnitQuaternion<f32> {
+        let [x, y, z, w] = self.rotation;
+        UnitQuaternion::from_quaternion(Quaternion::new(-w, x, y, z))
+    }
+
+    pub(crate) fn from_local(local: &Isometry3<f32>, time: f32) -> Frame {
+        let r = local.rotation.coords;
+        let t = local.translation.vector;
+        Frame {
+            rotation: [r.x, r.y, r.z, -r.w],
+            translation: [t.x, t.y, t.z],
+            time,
+        }
+    }
+}
+
+/// Joint name map for [`RdAnim::retarget`]: source skeleton joint -> target skeleton joint.
+/// Can be loaded from a JSON object like `{ "Bone_Hip": "pelvis", "Bone_Spine": "spine_01" }`
+/// with [`crate::load_json_map`].
+pub type JointMap = HashMap<String, String>;
+
+/// Local (parent relative) bind pose of every joint.
+/// RdJoint stores the global bind pose, see `put_joint_nodes` of the glTF export.
+fn local_bind_poses(joints: &[RdJoint]) -> Vec<Isometry3<f32>> {
+    let global: Vec<Isometry3<f32>> = joints
+        .iter()
+        .map(|j| {
+            let [x, y, z, w] = j.quaternion;
+            Isometry3::from_parts(
+                Translation3::from(Vector3::from(j.transition)),
+                UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z)),
+            )
+        })
+        .collect();
+
+    joints
+        .iter()
+        .zip(global.iter())
+        .map(|(j, g)| {
+            if j.parent == 255 || j.locked {
+                *g
+            } else {
+                global[j.parent as usize].inverse() * g
+            }
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub struct FrameCollection {
     pub name: String,
@@ -28,7 +108,287 @@ pub struct RdAnim {
     pub anim_vec: Vec<FrameCollection>,
 }
 
+impl FrameCollection {
+    fn sample(&self, time: f32) -> Frame {
+        let first = self.frames.first().unwrap();
+        let last = self.frames.last().unwrap();
+        if time <= first.time {
+            return Frame { time, ..*first };
+        }
+        if time >= last.time {
+            return Frame { time, ..*last };
+        }
+        let next_idx = self.frames.iter().position(|f| f.time > time).unwrap();
+        self.frames[next_idx - 1].interpolate(&self.frames[next_idx], time)
+    }
+
+    fn slice(&self, start: f32, end: f32) -> FrameCollection {
+        let mut frames = Vec::with_capacity(self.frames.len());
+        frames.push(self.sample(start));
+        for f in self
+            .frames
+            .iter()
+            .filter(|f| f.time > start && f.time < end)
+            .filter(|f| !relative_eq!(f.time, start) && !relative_eq!(f.time, end))
+        {
+            frames.push(*f);
+        }
+        frames.push(self.sample(end));
+
+        for f in frames.iter_mut() {
+            f.time -= start;
+        }
+
+        FrameCollection {
+            name: self.name.clone(),
+            len: frames.len() as u32,
+            frames,
+        }
+    }
+}
+
+/// A named time range (in seconds) of an animation timeline, e.g. `idle01=0.0..2.0`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimClip {
+    pub name: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl FromStr for AnimClip {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "Invalid value for AnimClip: {}, expected NAME=START..END (e.g. idle01=0.0..2.0)",
+                input
+            )
+        };
+        let (name, range) = input.split_once('=').ok_or_else(err)?;
+        let (start, end) = range.split_once("..").ok_or_else(err)?;
+        let start: f32 = start.trim().parse().map_err(|_| err())?;
+        let end: f32 = end.trim().parse().map_err(|_| err())?;
+        if name.is_empty() || start < 0.0 || end <= start {
+            return Err(err());
+        }
+        Ok(AnimClip {
+            name: name.to_owned(),
+            start,
+            end,
+        })
+    }
+}
+
+/// Result of matching the joint tracks of a [`RdAnim`] against the joints of a model skeleton.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SkeletonCheck {
+    /// Joints of the skeleton without a track in the animation. They stay in their bind pose.
+    pub missing: Vec<String>,
+    /// Tracks of the animation that target a joint the skeleton does not have.
+    pub extra: Vec<String>,
+    /// Tracks that only match a skeleton joint when ignoring case: (track, joint).
+    pub mismatched: Vec<(String, String)>,
+}
+
+impl SkeletonCheck {
+    /// The animation can be played on the skeleton without dropping any track.
+    pub fn is_compatible(&self) -> bool {
+        self.extra.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+impl fmt::Display for SkeletonCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut lines = Vec::new();
+        if !self.missing.is_empty() {
+            lines.push(format!("missing tracks (bind pose): {:?}", self.missing));
+        }
+        if !self.extra.is_empty() {
+            lines.push(format!("tracks without joint: {:?}", self.extra));
+        }
+        for (track, joint) in self.mismatched.iter() {
+            lines.push(format!(
+                "track {:?} only matches joint {:?} ignoring case",
+                track, joint
+            ));
+        }
+        if lines.is_empty() {
+            write!(f, "all joints match")
+        } else {
+            write!(f, "{}", lines.join("\n"))
+        }
+    }
+}
+
 impl RdAnim {
+    /// Compares the tracks of the animation with the joints of `joints`.
+    pub fn check_skeleton(&self, joints: &[RdJoint]) -> SkeletonCheck {
+        let joint_names: HashSet<&str> = joints.iter().map(|j| j.name.as_str()).collect();
+        let track_names: HashSet<&str> = self.anim_vec.iter().map(|c| c.name.as_str()).collect();
+
+        let mut check = SkeletonCheck::default();
+        for collection in self.anim_vec.iter() {
+            if joint_names.contains(collection.name.as_str()) {
+                continue;
+            }
+            match joints
+                .iter()
+                .find(|j| j.name.eq_ignore_ascii_case(&collection.name))
+            {
+                Some(joint) => check
+                    .mismatched
+                    .push((collection.name.clone(), joint.name.clone())),
+                None => check.extra.push(collection.name.clone()),
+            }
+        }
+        for joint in joints {
+            let matched_ignoring_case = check.mismatched.iter().any(|(_, j)| j == &joint.name);
+            if !track_names.contains(joint.name.as_str()) && !matched_ignoring_case {
+                check.missing.push(joint.name.clone());
+            }
+        }
+        check
+    }
+
+    /// Moves the animation from the skeleton `from` onto the skeleton `to`.
+    ///
+    /// Tracks are renamed with `mapping` (source joint -> target joint). The key frames are
+    /// re-expressed relative to the bind pose of the target joint, so differing rest poses of both
+    /// rigs are compensated. Target joints without a mapped track hold their bind pose, tracks of
+    /// joints missing in `from` are skipped with a warning. Fails if two source joints map to the
+    /// same target joint.
+    pub fn retarget(
+        &self,
+        from: &[RdJoint],
+        to: &[RdJoint],
+        mapping: &JointMap,
+    ) -> Result<RdAnim, String> {
+        let from_rest = local_bind_poses(from);
+        let to_rest = local_bind_poses(to);
+
+        let mut source_of: HashMap<&str, &str> = HashMap::new();
+        for (src, dst) in mapping.iter() {
+            if !to.iter().any(|j| &j.name == dst) {
+                warn!("joint map: target skeleton has no joint {:?}", dst);
+                continue;
+            }
+            if let Some(prev) = source_of.insert(dst.as_str(), src.as_str()) {
+                let mut both = [prev, src.as_str()];
+                both.sort_unstable();
+                return Err(format!(
+                    "joint map: {:?} and {:?} both map to joint {:?}",
+                    both[0], both[1], dst
+                ));
+            }
+        }
+
+        let end = self.time_max as f32 / 1000.0;
+        let mut anim_vec = Vec::with_capacity(to.len());
+        for (tjoint, t_rest) in to.iter().zip(to_rest.iter()) {
+            let source = source_of.get(tjoint.name.as_str()).and_then(|src| {
+                let track = self.anim_vec.iter().find(|c| &c.name == src);
+                if track.is_none() {
+                    warn!("joint map: animation has no track {:?}", src);
+                }
+                let s_idx = from.iter().position(|j| &j.name == src);
+                if track.is_some() && s_idx.is_none() {
+                    warn!(
+                        "joint map: source skeleton has no joint {:?}, its track is skipped",
+                        src
+                    );
+                }
+                track.zip(s_idx)
+            });
+
+            let frames: Vec<Frame> = match source {
+                Some((track, s_idx)) => {
+                    let s_rest = &from_rest[s_idx];
+                    let offset = t_rest.rotation * s_rest.rotation.inverse();
+                    track
+                        .frames
+                        .iter()
+                        .map(|f| {
+                            let local = Isometry3::from_parts(
+                                Translation3::from(
+                                    t_rest.translation.vector
+                                        + (Vector3::from(f.translation)
+                                            - s_rest.translation.vector),
+                                ),
+                                offset * f.unit_rotation(),
+                            );
+                            Frame::from_local(&local, f.time)
+                        })
+                        .collect()
+                }
+                None => vec![
+                    Frame::from_local(t_rest, 0.0),
+                    Frame::from_local(t_rest, end),
+                ],
+            };
+
+            anim_vec.push(FrameCollection {
+                name: tjoint.name.clone(),
+                len: frames.len() as u32,
+                frames,
+            });
+        }
+
+        for track in self.anim_vec.iter() {
+            if !mapping.contains_key(&track.name) {
+                debug!("track {:?} is not in the joint map, dropped", track.name);
+            }
+        }
+
+        Ok(RdAnim {
+            time_max: self.time_max,
+            name: self.name.clone(),
+            anim_vec,
+        })
+    }
+
+    /// Cuts the time range `start..end` (seconds) out of the animation.
+    /// Key frames are re-based to zero and the range borders are interpolated.
+    pub fn slice(&self, start: f32, end: f32) -> RdAnim {
+        assert!(
+            start >= 0.0 && end > start,
+            "invalid animation slice {}..{}",
+            start,
+            end
+        );
+        let time_1000_f32_max = self.time_max as f32 / 1000.0;
+        if end > time_1000_f32_max {
+            warn!(
+                "slice end {} is past the animation end {}. Last frame is held.",
+                end, time_1000_f32_max
+            );
+        }
+
+        let anim_vec = self
+            .anim_vec
+            .iter()
+            .filter(|c| !c.frames.is_empty())
+            .map(|c| c.slice(start, end))
+            .collect();
+
+        RdAnim {
+            time_max: ((end - start) * 1000.0).round() as u32,
+            name: self.name.clone(),
+            anim_vec,
+        }
+    }
+
+    /// Re-expresses all key frames in the space mapped by `m`, see [`RdModell::transform_space`].
+    pub fn transform_space(&mut self, m: &Matrix3<f32>) {
+        for f in self.anim_vec.iter_mut().flat_map(|c| c.frames.iter_mut()) {
+            let local = Isometry3::from_parts(
+                Translation3::from(Vector3::from(f.translation)),
+                f.unit_rotation(),
+            );
+            *f = Frame::from_local(&conjugate_isometry(m, &local), f.time);
+        }
+    }
+
     pub fn new(buffer: Vec<u8>, name_anim: String) -> Self {
         let mut buffer = Bytes::from(buffer);
         let size = buffer.len() as u32;
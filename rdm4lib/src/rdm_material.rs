@@ -1,65 +1,172 @@
-use std::process::Command;
-use std::{
-    fs,
-    path::{Path, PathBuf},
-};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::dds::{self, RgbaImage};
 
 #[derive(Clone, Debug)]
 pub struct RdMaterial {
-    c_model_diff_tex: Vec<PathBuf>,
+    slots: Vec<MaterialSlot>,
 }
 
-impl RdMaterial {
-    pub fn new<P: AsRef<Path> + Into<PathBuf>>(paths: Vec<P>) -> Self {
-        let mut v = Vec::with_capacity(paths.len());
-        for p in paths {
-            v.push(p.into());
-        }
-        RdMaterial {
-            c_model_diff_tex: v,
+/// Material of one rdm material slot, e.g. from a `MATERIAL` of the model's cfg.
+///
+/// Channel layout of the game textures: `_diff` is the base color (alpha is the opacity),
+/// `_norm` holds the normal in red and green and the glossiness in blue, `_metal` holds the
+/// metalness in red and the glow mask in alpha.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialSlot {
+    pub name: Option<String>,
+    pub diffuse: Option<PathBuf>,
+    pub normal: Option<PathBuf>,
+    pub metallic: Option<PathBuf>,
+    /// `cDiffuseColor`
+    pub base_color: [f32; 3],
+    /// `cEmissiveColor` of materials with `GLOW_ENABLED`, masked by the `_metal` alpha.
+    pub emissive: Option<[f32; 3]>,
+    /// `ALPHA_BLEND_ENABLED`
+    pub alpha_blend: bool,
+}
+
+impl Default for MaterialSlot {
+    fn default() -> Self {
+        MaterialSlot {
+            name: None,
+            diffuse: None,
+            normal: None,
+            metallic: None,
+            base_color: [1.0; 3],
+            emissive: None,
+            alpha_blend: false,
         }
     }
+}
 
-    pub fn run_dds_converter(&self, dst: &Path) {
-        if cfg!(windows) {
-            self.run_texconv(dst);
-        } else {
-            unimplemented!("DDS convert needs Windows texconv!");
+/// A glTF image that is built from game textures, see [`MaterialSlot`] for the channels.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MaterialImage {
+    BaseColor(PathBuf),
+    /// tangent space normal with the z component reconstructed
+    Normal(PathBuf),
+    /// metalness (blue) of the `_metal` texture, roughness (green) from the glossiness of the
+    /// optional `_norm` texture
+    MetallicRoughness {
+        metallic: PathBuf,
+        normal: Option<PathBuf>,
+    },
+    /// glow mask of the `_metal` texture
+    Emissive(PathBuf),
+}
+
+impl MaterialImage {
+    /// File name of the PNG written next to the glTF.
+    pub fn png_name(&self) -> String {
+        let stem = |p: &Path| p.file_stem().unwrap().to_string_lossy().into_owned();
+        match self {
+            MaterialImage::BaseColor(p) | MaterialImage::Normal(p) => format!("{}.PNG", stem(p)),
+            MaterialImage::MetallicRoughness { metallic, .. } => {
+                format!("{}_roughness.PNG", stem(metallic))
+            }
+            MaterialImage::Emissive(p) => format!("{}_glow.PNG", stem(p)),
         }
     }
 
-    fn run_texconv(&self, dst: &Path) {
-        warn!("running texconv ...");
-        for p in self.c_model_diff_tex.iter() {
-            let ab_path = p.canonicalize().unwrap();
-            let ab_dst = dst.canonicalize().unwrap();
-
-            let mut file_dst = ab_dst.join(ab_path.file_stem().unwrap());
-            file_dst.set_extension("PNG");
-            if file_dst.is_file() {
-                match fs::remove_file(&file_dst) {
-                    Ok(_) => {
-                        debug!("removed: {:?}", &file_dst)
-                    }
-                    Err(e) => {
-                        debug!("failed to remove: {:?}, {:?}", &file_dst, e)
+    pub fn decode(&self) -> RgbaImage {
+        match self {
+            MaterialImage::BaseColor(p) => read_dds(p),
+            MaterialImage::Normal(p) => {
+                let mut image = read_dds(p);
+                for pixel in image.rgba.chunks_exact_mut(4) {
+                    let x = pixel[0] as f32 / 127.5 - 1.0;
+                    let y = pixel[1] as f32 / 127.5 - 1.0;
+                    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+                    pixel[2] = ((z + 1.0) * 127.5).round() as u8;
+                    pixel[3] = 255;
+                }
+                image
+            }
+            MaterialImage::MetallicRoughness { metallic, normal } => {
+                let mut image = read_dds(metallic);
+                let gloss = normal.as_ref().map(|p| read_dds(p)).filter(|n| {
+                    let same_size = (n.width, n.height) == (image.width, image.height);
+                    if !same_size {
+                        warn!(
+                            "{:?}: size differs from {:?}, roughness 1",
+                            normal, metallic
+                        );
                     }
+                    same_size
+                });
+                for (i, pixel) in image.rgba.chunks_exact_mut(4).enumerate() {
+                    let roughness = gloss.as_ref().map_or(255, |n| 255 - n.rgba[i * 4 + 2]);
+                    pixel.copy_from_slice(&[0, roughness, pixel[0], 255]);
                 }
+                image
+            }
+            MaterialImage::Emissive(p) => {
+                let mut image = read_dds(p);
+                for pixel in image.rgba.chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&[pixel[3], pixel[3], pixel[3], 255]);
+                }
+                image
             }
-
-            let output = Command::new("texconv.exe")
-                .arg(&ab_path.as_os_str())
-                .arg(r"-o")
-                .arg(&ab_dst.as_os_str())
-                .arg(r"-ft")
-                .arg(r"png")
-                .output()
-                .expect("failed to execute texconv.exe");
-            trace!("{:?}", &ab_path.to_str().unwrap()[4..]);
-            trace!("{:?}", &ab_dst.to_str().unwrap()[4..]);
-            trace!("{:?}", output);
         }
     }
+
+    pub fn png(&self) -> Vec<u8> {
+        self.decode().to_png()
+    }
+}
+
+fn read_dds(path: &Path) -> RgbaImage {
+    info!("decoding {:?}", path);
+    let data = fs::read(path).unwrap_or_else(|e| panic!("could not read {:?}: {}", path, e));
+    dds::decode_dds(&data)
+}
+
+impl MaterialSlot {
+    pub fn base_color_image(&self) -> Option<MaterialImage> {
+        self.diffuse.clone().map(MaterialImage::BaseColor)
+    }
+
+    pub fn normal_image(&self) -> Option<MaterialImage> {
+        self.normal.clone().map(MaterialImage::Normal)
+    }
+
+    pub fn metallic_roughness_image(&self) -> Option<MaterialImage> {
+        self.metallic
+            .clone()
+            .map(|metallic| MaterialImage::MetallicRoughness {
+                metallic,
+                normal: self.normal.clone(),
+            })
+    }
+
+    pub fn emissive_image(&self) -> Option<MaterialImage> {
+        self.emissive?;
+        self.metallic.clone().map(MaterialImage::Emissive)
+    }
+}
+
+impl RdMaterial {
+    /// Diffuse textures only, one per material slot.
+    pub fn new<P: AsRef<Path> + Into<PathBuf>>(paths: Vec<P>) -> Self {
+        let slots = paths
+            .into_iter()
+            .map(|p| MaterialSlot {
+                diffuse: Some(p.into()),
+                ..Default::default()
+            })
+            .collect();
+        RdMaterial { slots }
+    }
+
+    pub fn from_slots(slots: Vec<MaterialSlot>) -> Self {
+        RdMaterial { slots }
+    }
+
+    pub fn slots(&self) -> &[MaterialSlot] {
+        &self.slots
+    }
 }
 
 impl<P: AsRef<Path>> From<P> for RdMaterial
@@ -72,10 +179,10 @@ where
 }
 
 impl<'a> IntoIterator for &'a RdMaterial {
-    type Item = &'a PathBuf;
-    type IntoIter = std::slice::Iter<'a, PathBuf>;
+    type Item = &'a MaterialSlot;
+    type IntoIter = std::slice::Iter<'a, MaterialSlot>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.c_model_diff_tex.iter()
+        self.slots.iter()
     }
 }
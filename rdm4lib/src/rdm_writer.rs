@@ -5,7 +5,7 @@ use nalgebra::*;
 use std::{convert::TryInto, fs, path::PathBuf};
 use std::{fs::OpenOptions, io::Write};
 
-use crate::{vertex::AnnoData, RdModell};
+use crate::{vertex::AnnoData, RdModell, RdmMaterialEntry};
 use byteorder::ByteOrder;
 
 pub struct RdWriter {
@@ -318,63 +318,56 @@ impl RdWriter {
         }
         max_mesh += 1;
 
+        // the materials of a rewritten rdm are kept, new material slots get a placeholder
+        let mut materials = self.input.rdm_materials();
+        if materials.len() < max_mesh as usize {
+            materials.resize(max_mesh as usize, RdmMaterialEntry::default());
+        }
+
         let start = self.buf.len();
-        self.buf.put_u32_le(max_mesh);
+        self.buf.put_u32_le(materials.len() as u32);
         self.buf.put_u32_le(28);
 
         let mut ptrvec = Vec::new();
 
-        for _ in 0..max_mesh {
+        for material in &materials {
             ptrvec.push(self.buf.len());
             self.buf.put_u32_le(0xDEAD_BEEF);
-
-            static UNKNOWN: [u8; 24] = [
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            ];
-            self.buf.put_slice(&UNKNOWN);
+            self.buf.put_slice(&material.entry_tail);
         }
 
-        for e in ptrvec {
+        let mut expected_len = 8;
+        for (e, material) in ptrvec.into_iter().zip(materials.iter()) {
             self.buf.put_u32_le(1);
             self.buf.put_u32_le(48);
             let cnt = self.buf.len() as u32;
             byteorder::LittleEndian::write_u32(&mut self.buf[e..e + 4], cnt);
 
-            static UNKNOWN2: [u8; 40] = [
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            ];
-
-            let material = br"Default Standard12432142134";
-            let dummy_png_path = br"d:/projekte/anno5/game/testdata/graphics/dummy_objects/dummy_christian/rdm/basalt_crusher_others/diffuse.png";
+            let name = &material.name;
+            let texture = &material.texture;
 
             self.buf.put_u32_le(self.buf.len() as u32 + 8 + 48);
             self.buf
-                .put_u32_le(self.buf.len() as u32 + 8 + 48 + material.len() as u32 + 8 - 4); // -4 advanced: 4 bytes
-            self.buf.put_slice(&UNKNOWN2);
+                .put_u32_le(self.buf.len() as u32 + 8 + 48 + name.len() as u32 + 8 - 4); // -4 advanced: 4 bytes
+            self.buf.put_slice(&material.header_tail);
 
-            self.buf.put_u32_le(material.len() as u32);
+            self.buf.put_u32_le(name.len() as u32);
             self.buf.put_u32_le(1);
-            self.buf.put_slice(material);
+            self.buf.put_slice(name);
 
-            self.buf.put_u32_le(dummy_png_path.len() as u32);
+            self.buf.put_u32_le(texture.len() as u32);
             self.buf.put_u32_le(1);
-            self.buf.put_slice(dummy_png_path);
+            self.buf.put_slice(texture);
+
+            // entry + header + name + texture
+            expected_len += 28 + 8 + 48 + 8 + name.len() + 8 + texture.len();
         }
 
         let end = self.buf.len();
 
         let written = end - start;
 
-        assert_eq!(written as u32, 235 * max_mesh + 8);
-
-        // 8+1*28 : -> (0 -> next)
-        // 8+1*48 : (0 -> next) (4 -> next+1)
-        // 8+27*1
-        // 8+108*1
-        // = 243
+        assert_eq!(written, expected_len);
     }
 
     fn put_skin(&mut self) {
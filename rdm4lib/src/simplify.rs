@@ -0,0 +1,573 @@
+//! Mesh simplification for LODs: quadric error metric (Garland & Heckbert) with half-edge collapses.
+//! Vertices at one position (split by UV or normal seams) are collapsed together onto their
+//! neighbours at another position, so the kept vertices and their attributes (normals, UVs, skin
+//! weights) are unchanged and seams stay closed.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use bytes::Bytes;
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+use crate::vertex::UniqueIdentifier;
+use crate::{MeshInstance, RdModell, Triangle};
+
+/// Weight of the quadrics that keep open borders and seams in place, relative to the surface.
+const BOUNDARY_WEIGHT: f64 = 10.0;
+
+/// Position group of every vertex: vertices at the same position get the same group.
+fn position_groups(positions: &[Vector3<f32>]) -> Vec<usize> {
+    let mut groups: HashMap<[u32; 3], usize> = HashMap::new();
+    positions
+        .iter()
+        .map(|p| {
+            let n = groups.len();
+            *groups
+                .entry([p.x.to_bits(), p.y.to_bits(), p.z.to_bits()])
+                .or_insert(n)
+        })
+        .collect()
+}
+
+/// Position groups used by more than one MeshInstance, they must not move or the LOD gets cracks.
+fn locked_groups(rdm: &RdModell, group: &[usize]) -> HashSet<usize> {
+    let mut instance_of: HashMap<usize, usize> = HashMap::new();
+    let mut locked = HashSet::new();
+    for (mesh_idx, range) in rdm.mesh_triangle_ranges().into_iter().enumerate() {
+        for t in rdm.triangle_indices[range].iter() {
+            for &v in t.indices.iter() {
+                let g = group[v as usize];
+                if *instance_of.entry(g).or_insert(mesh_idx) != mesh_idx {
+                    locked.insert(g);
+                }
+            }
+        }
+    }
+    locked
+}
+
+fn plane_quadric(plane: Vector4<f64>, weight: f64) -> Matrix4<f64> {
+    plane * plane.transpose() * weight
+}
+
+fn quadric_error(q: &Matrix4<f64>, p: &Vector3<f32>) -> f64 {
+    let p = Vector4::new(p.x as f64, p.y as f64, p.z as f64, 1.0);
+    (p.transpose() * q * p)[0].max(0.0)
+}
+
+/// Triangle, its corner where the edge starts and the sorted vertex pair of the edge.
+type EdgeUse = (usize, usize, (u16, u16));
+
+/// Quadrics of every position group: the area weighted triangle planes, plus planes perpendicular
+/// to the triangle along open borders and seams.
+fn group_quadrics(
+    triangles: &[[u16; 3]],
+    positions: &[Vector3<f32>],
+    group: &[usize],
+) -> HashMap<usize, Matrix4<f64>> {
+    let mut quadrics: HashMap<usize, Matrix4<f64>> = HashMap::new();
+    // vertex pairs of every edge between two position groups
+    let mut edges: HashMap<(usize, usize), Vec<EdgeUse>> = HashMap::new();
+    for (t, indices) in triangles.iter().enumerate() {
+        let p = indices.map(|i| positions[i as usize].cast::<f64>());
+        let n = (p[1] - p[0]).cross(&(p[2] - p[0]));
+        let area2 = n.norm();
+        for (k, &v) in indices.iter().enumerate() {
+            let q = quadrics
+                .entry(group[v as usize])
+                .or_insert_with(Matrix4::zeros);
+            if area2 > f64::EPSILON {
+                let n = n / area2;
+                *q += plane_quadric(n.push(-n.dot(&p[0])), area2 / 2.0);
+            }
+            let w = indices[(k + 1) % 3];
+            let (a, b) = (group[v as usize], group[w as usize]);
+            edges
+                .entry((a.min(b), a.max(b)))
+                .or_default()
+                .push((t, k, (v.min(w), v.max(w))));
+        }
+    }
+
+    for ((a, b), uses) in edges {
+        let seam = uses.iter().any(|u| u.2 != uses[0].2);
+        if uses.len() != 1 && !seam {
+            continue;
+        }
+        for (t, k, _) in uses {
+            let indices = triangles[t];
+            let p = indices.map(|i| positions[i as usize].cast::<f64>());
+            let edge = p[(k + 1) % 3] - p[k];
+            let n = (p[1] - p[0]).cross(&(p[2] - p[0]));
+            let perpendicular = edge.cross(&n);
+            let length = perpendicular.norm();
+            if length <= f64::EPSILON {
+                continue;
+            }
+            let perpendicular = perpendicular / length;
+            let q = plane_quadric(
+                perpendicular.push(-perpendicular.dot(&p[k])),
+                edge.norm_squared() * BOUNDARY_WEIGHT,
+            );
+            *quadrics.get_mut(&a).unwrap() += q;
+            *quadrics.get_mut(&b).unwrap() += q;
+        }
+    }
+    quadrics
+}
+
+/// Triangles around a position group.
+struct Star {
+    triangles: Vec<usize>,
+    /// Number of triangles shared with every neighbouring position group.
+    neighbours: HashMap<usize, u32>,
+    /// Part of an open border: some edge has only one triangle.
+    border: bool,
+}
+
+/// Half-edge collapse simplification of one MeshInstance, on position groups.
+struct Simplifier<'a> {
+    positions: &'a [Vector3<f32>],
+    group: &'a [usize],
+    locked: &'a HashSet<usize>,
+    /// Dominant joint and all weighted joints of every vertex, for skinned meshes.
+    joints: Option<&'a [(u8, Vec<u8>)]>,
+    triangles: Vec<[u16; 3]>,
+    alive: Vec<bool>,
+    group_vertices: HashMap<usize, Vec<u16>>,
+    vertex_triangles: HashMap<u16, Vec<usize>>,
+    quadrics: HashMap<usize, Matrix4<f64>>,
+    version: HashMap<usize, u32>,
+}
+
+impl<'a> Simplifier<'a> {
+    fn live_triangles(&self, v: u16) -> impl Iterator<Item = usize> + '_ {
+        self.vertex_triangles[&v]
+            .iter()
+            .copied()
+            .filter(move |&t| self.alive[t] && self.triangles[t].contains(&v))
+    }
+
+    fn group_triangles(&self, g: usize) -> HashSet<usize> {
+        self.group_vertices[&g]
+            .iter()
+            .flat_map(|&v| self.live_triangles(v))
+            .collect()
+    }
+
+    fn has_group(&self, t: usize, g: usize) -> bool {
+        self.triangles[t]
+            .iter()
+            .any(|&v| self.group[v as usize] == g)
+    }
+
+    fn neighbours(&self, g: usize) -> HashSet<usize> {
+        self.group_triangles(g)
+            .into_iter()
+            .flat_map(|t| self.triangles[t])
+            .map(|v| self.group[v as usize])
+            .filter(|&n| n != g)
+            .collect()
+    }
+
+    /// Target vertex in `to` for every live vertex of `from`, None if a vertex has no unique
+    /// neighbour in `to` (the collapse would cross a seam).
+    fn vertex_map(&self, from: usize, to: usize) -> Option<Vec<(u16, u16)>> {
+        let mut map = Vec::new();
+        for &u in self.group_vertices[&from].iter() {
+            let targets: HashSet<u16> = self
+                .live_triangles(u)
+                .flat_map(|t| self.triangles[t])
+                .filter(|&v| self.group[v as usize] == to)
+                .collect();
+            match targets.len() {
+                0 if self.live_triangles(u).next().is_none() => continue,
+                1 => map.push((u, targets.into_iter().next().unwrap())),
+                _ => return None,
+            }
+        }
+        Some(map)
+    }
+
+    fn star(&self, g: usize) -> Star {
+        let triangles: Vec<usize> = self.group_triangles(g).into_iter().collect();
+        let mut edge_triangles: HashMap<usize, u32> = HashMap::new();
+        for &t in triangles.iter() {
+            let groups: HashSet<usize> = self.triangles[t]
+                .iter()
+                .map(|&v| self.group[v as usize])
+                .filter(|&n| n != g)
+                .collect();
+            for n in groups {
+                *edge_triangles.entry(n).or_default() += 1;
+            }
+        }
+        Star {
+            triangles,
+            border: edge_triangles.values().any(|&n| n == 1),
+            neighbours: edge_triangles,
+        }
+    }
+
+    /// Cost of moving the group `from` (with its `star`) onto `to`, None if the collapse would break
+    /// the mesh.
+    fn collapse_cost(&self, from: usize, star: &Star, to: usize) -> Option<f64> {
+        let shared = *star.neighbours.get(&to)?;
+        // an open border may only shrink along itself
+        if star.border && shared != 1 {
+            return None;
+        }
+        // link condition: the common neighbours are exactly the triangles of the edge
+        let common = self
+            .neighbours(to)
+            .into_iter()
+            .filter(|n| star.neighbours.contains_key(n))
+            .count();
+        if common != shared as usize {
+            return None;
+        }
+
+        let map = self.vertex_map(from, to)?;
+        if let Some(joints) = self.joints {
+            // keep the skin binding: v must be influenced by the dominant joint of u
+            if map
+                .iter()
+                .any(|&(u, v)| !joints[v as usize].1.contains(&joints[u as usize].0))
+            {
+                return None;
+            }
+        }
+
+        // no triangle may flip or degenerate
+        let p_to = self.positions[self.group_vertices[&to][0] as usize];
+        for &t in star.triangles.iter().filter(|&&t| !self.has_group(t, to)) {
+            let p = self.triangles[t].map(|i| self.positions[i as usize]);
+            let moved = self.triangles[t].map(|i| {
+                if self.group[i as usize] == from {
+                    p_to
+                } else {
+                    self.positions[i as usize]
+                }
+            });
+            let before = (p[1] - p[0]).cross(&(p[2] - p[0]));
+            let after = (moved[1] - moved[0]).cross(&(moved[2] - moved[0]));
+            if after.norm() <= f32::EPSILON || before.dot(&after) <= 0.0 {
+                return None;
+            }
+        }
+        Some(quadric_error(
+            &(self.quadrics[&from] + self.quadrics[&to]),
+            &p_to,
+        ))
+    }
+
+    fn best_collapse(&self, from: usize) -> Option<(f64, usize)> {
+        if self.locked.contains(&from) {
+            return None;
+        }
+        let star = self.star(from);
+        star.neighbours
+            .keys()
+            .filter_map(|&to| self.collapse_cost(from, &star, to).map(|c| (c, to)))
+            .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)))
+    }
+
+    fn run(mut self, target: usize) -> Vec<[u16; 3]> {
+        let mut live = self.triangles.len();
+        // non-negative floats order like their bit patterns
+        let mut heap = BinaryHeap::new();
+        let mut groups: Vec<usize> = self.group_vertices.keys().copied().collect();
+        groups.sort_unstable();
+        for from in groups {
+            if let Some((cost, to)) = self.best_collapse(from) {
+                heap.push(Reverse((cost.to_bits(), from, to, 0u32)));
+            }
+        }
+
+        while live > target {
+            let Reverse((_, from, to, version)) = match heap.pop() {
+                Some(e) => e,
+                None => break,
+            };
+            if version != self.version[&from]
+                || self.collapse_cost(from, &self.star(from), to).is_none()
+            {
+                continue;
+            }
+
+            for (u, v) in self.vertex_map(from, to).unwrap() {
+                for t in self.live_triangles(u).collect::<Vec<_>>() {
+                    if self.has_group(t, to) {
+                        self.alive[t] = false;
+                        live -= 1;
+                    } else {
+                        for i in self.triangles[t].iter_mut().filter(|i| **i == u) {
+                            *i = v;
+                        }
+                        self.vertex_triangles.get_mut(&v).unwrap().push(t);
+                    }
+                }
+            }
+            let q = self.quadrics[&from];
+            *self.quadrics.get_mut(&to).unwrap() += q;
+
+            let mut affected: Vec<usize> = self.neighbours(to).into_iter().collect();
+            affected.push(to);
+            affected.sort_unstable();
+            for g in affected {
+                let version = self.version.get_mut(&g).unwrap();
+                *version += 1;
+                let version = *version;
+                if let Some((cost, to)) = self.best_collapse(g) {
+                    heap.push(Reverse((cost.to_bits(), g, to, version)));
+                }
+            }
+        }
+
+        self.triangles
+            .iter()
+            .zip(self.alive.iter())
+            .filter(|(_, &alive)| alive)
+            .map(|(t, _)| *t)
+            .collect()
+    }
+}
+
+impl RdModell {
+    /// Simplified copy for a LOD with about `ratio` of the triangles of every MeshInstance.
+    /// Open borders and seams only shrink along themselves, positions shared between MeshInstances
+    /// are kept and skinned vertices are only collapsed onto vertices influenced by their dominant
+    /// joint. The result has no animations.
+    pub fn simplified(&self, ratio: f32) -> RdModell {
+        assert!(
+            ratio > 0.0 && ratio <= 1.0,
+            "LOD ratio must be in (0, 1], got {}",
+            ratio
+        );
+        let vertex_count = self.vertex.len() as usize;
+        let size = self.vertex.get_size() as usize;
+        let src = self.vertex.as_bytes();
+
+        let positions = self.vertex.positions();
+        let group = position_groups(&positions);
+        let locked = locked_groups(self, &group);
+
+        let joints: Option<Vec<(u8, Vec<u8>)>> = self
+            .vertex
+            .component_offset(UniqueIdentifier::I4b)
+            .map(|i4b| {
+                let w4b = self.vertex.component_offset(UniqueIdentifier::W4b);
+                (0..vertex_count)
+                    .map(|v| {
+                        let base = v * size;
+                        let weights: [u8; 4] = match w4b {
+                            Some(w) => src[base + w..base + w + 4].try_into().unwrap(),
+                            None => [255, 0, 0, 0],
+                        };
+                        let dominant = (0..4).max_by_key(|&k| (weights[k], Reverse(k))).unwrap();
+                        let weighted = (0..4)
+                            .filter(|&k| weights[k] > 0)
+                            .map(|k| src[base + i4b + k])
+                            .collect();
+                        (src[base + i4b + dominant], weighted)
+                    })
+                    .collect()
+            });
+
+        let mut triangles: Vec<Triangle> = Vec::with_capacity(self.triangle_indices.len());
+        let mut mesh_info = Vec::with_capacity(self.mesh_info.len());
+        for (mesh, range) in self.mesh_info.iter().zip(self.mesh_triangle_ranges()) {
+            let mesh_triangles: Vec<[u16; 3]> = self.triangle_indices[range]
+                .iter()
+                .map(|t| t.indices)
+                .collect();
+            let mut vertex_triangles: HashMap<u16, Vec<usize>> = HashMap::new();
+            let mut group_vertices: HashMap<usize, Vec<u16>> = HashMap::new();
+            for (t, indices) in mesh_triangles.iter().enumerate() {
+                for &v in indices.iter() {
+                    let triangles = vertex_triangles.entry(v).or_default();
+                    if triangles.is_empty() {
+                        group_vertices.entry(group[v as usize]).or_default().push(v);
+                    }
+                    triangles.push(t);
+                }
+            }
+            let quadrics = group_quadrics(&mesh_triangles, &positions, &group);
+            let version = group_vertices.keys().map(|&g| (g, 0)).collect();
+            let target = ((mesh_triangles.len() as f32 * ratio).round() as usize).max(1);
+            let simplifier = Simplifier {
+                positions: &positions,
+                group: &group,
+                locked: &locked,
+                joints: joints.as_deref(),
+                alive: vec![true; mesh_triangles.len()],
+                triangles: mesh_triangles,
+                group_vertices,
+                vertex_triangles,
+                quadrics,
+                version,
+            };
+            let kept = simplifier.run(target);
+            mesh_info.push(MeshInstance {
+                start_index_location: triangles.len() as u32 * 3,
+                index_count: kept.len() as u32 * 3,
+                material: mesh.material,
+            });
+            triangles.extend(kept.into_iter().map(|indices| Triangle { indices }));
+        }
+
+        // drop the vertices that are no longer used
+        let mut remap: Vec<Option<u16>> = vec![None; vertex_count];
+        let mut buffer: Vec<u8> = Vec::with_capacity(src.len());
+        for t in triangles.iter_mut() {
+            for v in t.indices.iter_mut() {
+                *v = *remap[*v as usize].get_or_insert_with(|| {
+                    buffer.extend_from_slice(&src[*v as usize * size..][..size]);
+                    (buffer.len() / size - 1) as u16
+                });
+            }
+        }
+        info!(
+            "LOD {}: {} -> {} triangles, {} -> {} vertices",
+            ratio,
+            self.triangle_indices.len(),
+            triangles.len(),
+            vertex_count,
+            buffer.len() / size
+        );
+
+        RdModell {
+            size: 0,
+            buffer: Bytes::new(),
+            mesh_info,
+            joints: self.joints.clone(),
+            triangles_idx_count: triangles.len() as u32 * 3,
+            triangle_indices: triangles,
+            meta: None,
+            vertex: self
+                .vertex
+                .with_vertex_buffer((buffer.len() / size) as u32, Bytes::from(buffer)),
+            triangles_offset: None,
+            anim: Vec::new(),
+            mat: self.mat.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_simplify {
+    use super::*;
+
+    fn vertex_data(rdm: &RdModell) -> HashSet<&[u8]> {
+        rdm.vertex
+            .as_bytes()
+            .chunks_exact(rdm.vertex.get_size() as usize)
+            .collect()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn simplify_half() {
+        let rdm = RdModell::from("rdm/basalt_crusher_others_lod0.rdm");
+        let lod = rdm.simplified(0.5);
+
+        assert_eq!(lod.mesh_info.len(), rdm.mesh_info.len());
+        assert!(lod.anim.is_empty());
+        assert_eq!(
+            lod.triangles_idx_count as usize,
+            lod.triangle_indices.len() * 3
+        );
+        for (mesh, original) in lod.mesh_info.iter().zip(rdm.mesh_info.iter()) {
+            assert_eq!(mesh.material, original.material);
+            assert!(mesh.index_count <= original.index_count);
+        }
+        let (before, after) = (rdm.triangle_indices.len(), lod.triangle_indices.len());
+        assert!(after < before * 3 / 4, "{} -> {}", before, after);
+        // one collapse removes up to two triangles
+        assert!(after + 1 >= before / 2);
+
+        // half-edge collapses keep the vertex data, unused vertices are dropped
+        assert!(lod.vertex.len() < rdm.vertex.len());
+        assert!(vertex_data(&lod).is_subset(&vertex_data(&rdm)));
+        let mut used = vec![false; lod.vertex.len() as usize];
+        for t in lod.triangle_indices.iter() {
+            for &v in t.indices.iter() {
+                used[v as usize] = true;
+            }
+        }
+        assert!(used.iter().all(|&u| u));
+    }
+
+    /// Positions on an open border (a position group edge with one triangle).
+    fn border_positions(rdm: &RdModell) -> HashSet<[u32; 3]> {
+        let positions = rdm.vertex.positions();
+        let group = position_groups(&positions);
+        let mut edges: HashMap<(usize, usize), u32> = HashMap::new();
+        for t in rdm.triangle_indices.iter() {
+            for k in 0..3 {
+                let (a, b) = (
+                    group[t.indices[k] as usize],
+                    group[t.indices[(k + 1) % 3] as usize],
+                );
+                *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+        let border: HashSet<usize> = edges
+            .into_iter()
+            .filter(|(_, n)| *n == 1)
+            .flat_map(|((a, b), _)| [a, b])
+            .collect();
+        positions
+            .iter()
+            .zip(group.iter())
+            .filter(|(_, g)| border.contains(g))
+            .map(|(p, _)| [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()])
+            .collect()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn simplify_keeps_seams_and_borders() {
+        let rdm = RdModell::from("rdm/fishery_others_lod2.rdm");
+        let lod = rdm.simplified(0.25);
+        assert!(lod.triangle_indices.len() < rdm.triangle_indices.len() / 2);
+
+        // seams stay closed and no holes open: every border of the LOD was a border before
+        let before = border_positions(&rdm);
+        let after = border_positions(&lod);
+        assert!(!before.is_empty());
+        assert!(after.is_subset(&before));
+
+        // positions shared between MeshInstances do not move
+        let positions = rdm.vertex.positions();
+        let group = position_groups(&positions);
+        let locked = locked_groups(&rdm, &group);
+        let kept: HashSet<[u32; 3]> = lod
+            .vertex
+            .positions()
+            .iter()
+            .map(|p| [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()])
+            .collect();
+        for (p, g) in positions.iter().zip(group.iter()) {
+            if locked.contains(g) {
+                assert!(kept.contains(&[p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn simplify_full_ratio() {
+        let rdm = RdModell::from("rdm/basalt_crusher_others_lod0.rdm");
+        let lod = rdm.simplified(1.0);
+        assert_eq!(lod.triangle_indices.len(), rdm.triangle_indices.len());
+        assert_eq!(lod.vertex.len(), rdm.vertex.len());
+    }
+
+    #[test]
+    #[should_panic(expected = "LOD ratio must be in (0, 1]")]
+    fn simplify_invalid_ratio() {
+        let rdm = RdModell::from("rdm/basalt_crusher_others_lod0.rdm");
+        rdm.simplified(1.5);
+    }
+}
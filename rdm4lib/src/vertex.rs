@@ -201,9 +201,13 @@ impl VertexFormat2 {
 
     // TODO: remove for_each?
     pub fn set_weight_sum(&mut self) {
+        self.weight_sum = Some(self.weight_sums());
+    }
+
+    fn weight_sums(&self) -> Vec<u32> {
         let n = self.find_component_offsets(UniqueIdentifier::W4b).count();
         if n == 0 {
-            self.weight_sum = Some(vec![255; self.len() as usize]);
+            vec![255; self.len() as usize]
         } else {
             let mut vec: Vec<u32> = vec![0; self.len() as usize];
             for i in 0..n {
@@ -212,7 +216,7 @@ impl VertexFormat2 {
                         .for_each(|(e, dst)| *dst += e.data.iter().map(|&w| w as u32).sum::<u32>());
                 }
             }
-            self.weight_sum = Some(vec);
+            vec
         }
     }
 
@@ -239,7 +243,7 @@ impl VertexFormat2 {
             offsets.push(off);
             off += e.get_size() as usize;
         }
-        text.truncate((identifiers.len() * 4) - 1);
+        text.truncate((identifiers.len() * 4).saturating_sub(1));
 
         let size: u32 = identifiers.iter().map(|x| x.get_size()).sum();
         debug_assert_eq!(size, off as u32);
@@ -328,6 +332,32 @@ impl VertexFormat2 {
         self.vertex_count
     }
 
+    /// Byte offset of the first `ident` component inside one vertex.
+    pub(crate) fn component_offset(&self, ident: UniqueIdentifier) -> Option<usize> {
+        self.find_component_offsets(ident)
+            .next()
+            .map(|idx| self.offsets[idx])
+    }
+
+    /// Positions of all vertices, decoded from P4h or P3f.
+    pub(crate) fn positions(&self) -> Vec<Vector3<f32>> {
+        self.iter::<P4h, P3f>(0)
+            .expect("vertex format has no positions")
+            .map(|p| Vector3::new(p.data[0], p.data[1], p.data[2]))
+            .collect()
+    }
+
+    /// Same vertex format with other vertex data.
+    pub(crate) fn with_vertex_buffer(&self, vertex_count: u32, vertex_buffer: Bytes) -> Self {
+        Self::new(
+            self.identifiers.clone(),
+            vertex_count,
+            self.size,
+            None,
+            vertex_buffer,
+        )
+    }
+
     pub fn as_bytes(&self) -> &[u8] {
         &self.vertex_buffer
     }
@@ -367,7 +397,7 @@ impl VertexFormat2 {
         assert_eq!(self.vertex_count, n);
 
         vbuffer.advance(offset);
-        let it: _ = std::iter::from_fn(move || {
+        let it = std::iter::from_fn(move || {
             let ret = if count < n {
                 if need_convert {
                     Some(T::from(<Z as GetVertex>::get_unit(&mut vbuffer)))
@@ -386,6 +416,107 @@ impl VertexFormat2 {
         Some(it)
     }
 
+    /// Vertices whose W4b weights do not sum to 255, as (vertex index, weight sum).
+    pub fn check_weights(&self) -> Vec<(u32, u32)> {
+        self.weight_sums()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, sum)| *sum != 255)
+            .map(|(i, sum)| (i as u32, sum))
+            .collect()
+    }
+
+    /// Re-quantises the W4b weights of every vertex reported by [`VertexFormat2::check_weights`]
+    /// so they sum to exactly 255. Vertices without any weight get the full weight on their first joint.
+    /// Returns the number of repaired vertices.
+    pub fn repair_weights(&mut self) -> usize {
+        let bad = self.check_weights();
+        if bad.is_empty() {
+            return 0;
+        }
+        let sets: Vec<usize> = self
+            .find_component_offsets(UniqueIdentifier::W4b)
+            .map(|idx| self.offsets[idx])
+            .collect();
+        assert_eq!(
+            sets.len(),
+            1,
+            "repair_weights: only one W4b set per vertex is supported"
+        );
+        let offset = sets[0];
+
+        let mut buffer = self.vertex_buffer.to_vec();
+        for (vertex, _) in bad.iter() {
+            let start = *vertex as usize * self.size as usize + offset;
+            let w4b = &mut buffer[start..start + 4];
+            let weights = [w4b[0] as f32, w4b[1] as f32, w4b[2] as f32, w4b[3] as f32];
+            let data = if weights.iter().sum::<f32>() > 0.0 {
+                quantize_weights(weights)
+            } else {
+                [255, 0, 0, 0]
+            };
+            w4b.copy_from_slice(&data);
+        }
+        self.vertex_buffer = Bytes::from(buffer);
+        self.weight_sum = None;
+        bad.len()
+    }
+
+    /// Applies the linear map `m` to every vertex: positions and tangents with `m`, normals with its
+    /// inverse transpose. Directions are re-normalised, the 4th byte of packed directions is kept.
+    pub fn transform(&mut self, m: &Matrix3<f32>) {
+        let normal_m = m
+            .try_inverse()
+            .expect("singular vertex transform")
+            .transpose();
+        let size = self.size as usize;
+        let mut buffer = self.vertex_buffer.to_vec();
+        for (ident, &offset) in self.identifiers.iter().zip(self.offsets.iter()) {
+            let (map, is_direction) = match ident.uniq {
+                UniqueIdentifier::Position => (m, false),
+                UniqueIdentifier::Normal => (&normal_m, true),
+                UniqueIdentifier::GTangent | UniqueIdentifier::Bitangent => (m, true),
+                _ => continue,
+            };
+            for vertex in buffer.chunks_exact_mut(size) {
+                let unit = &mut vertex[offset..offset + ident.get_size() as usize];
+                match (&ident.unit_size, ident.count) {
+                    (IdentifierSize::U16, 4) => {
+                        let mut v = Vector3::from_fn(|i, _| {
+                            f16::from_le_bytes([unit[2 * i], unit[2 * i + 1]]).to_f32()
+                        });
+                        v = map * v;
+                        for i in 0..3 {
+                            unit[2 * i..2 * i + 2]
+                                .copy_from_slice(&f16::from_f32(v[i]).to_le_bytes());
+                        }
+                    }
+                    (IdentifierSize::U32, 1) => {
+                        let v = Vector3::from_fn(|i, _| 2.0 * unit[i] as f32 / 255.0 - 1.0);
+                        let v = (map * v).normalize();
+                        for i in 0..3 {
+                            unit[i] = (((v[i] + 1.0) / 2.0) * 255.0).round() as u8;
+                        }
+                    }
+                    (IdentifierSize::F32, 3) | (IdentifierSize::F32, 4) => {
+                        let mut v = Vector3::from_fn(|i, _| {
+                            f32::from_le_bytes(unit[4 * i..4 * i + 4].try_into().unwrap())
+                        });
+                        v = map * v;
+                        if is_direction {
+                            v.normalize_mut();
+                        }
+                        for i in 0..3 {
+                            unit[4 * i..4 * i + 4].copy_from_slice(&v[i].to_le_bytes());
+                        }
+                    }
+                    _ => panic!("transform: unsupported vertex component {}", ident),
+                }
+            }
+        }
+        self.vertex_buffer = Bytes::from(buffer);
+    }
+
     pub fn w4b_default_iter(&self) -> impl Iterator<Item = W4b> + '_ {
         std::iter::from_fn(|| {
             Some(W4b {
@@ -395,6 +526,30 @@ impl VertexFormat2 {
     }
 }
 
+/// Largest remainder quantisation of bone weights to W4b.
+/// The weights are normalised first, the result always sums to 255 (or 0 if all weights are 0).
+pub fn quantize_weights(weights: [f32; 4]) -> [u8; 4] {
+    let total: f32 = weights.iter().sum();
+    if total <= 0.0 {
+        return [0; 4];
+    }
+    let scaled = weights.map(|w| w.max(0.0) / total * 255.0);
+    let mut data = scaled.map(|w| w.floor() as u8);
+    let sum: u32 = data.iter().map(|&w| w as u32).sum();
+
+    // hand out the remaining units to the largest fractional parts
+    let mut order = [0, 1, 2, 3];
+    order.sort_by(|&a, &b| {
+        let fa = scaled[a] - scaled[a].floor();
+        let fb = scaled[b] - scaled[b].floor();
+        fb.partial_cmp(&fa).unwrap().then(a.cmp(&b))
+    });
+    for &i in order.iter().take(255u32.saturating_sub(sum) as usize) {
+        data[i] += 1;
+    }
+    data
+}
+
 #[repr(u32)]
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum IdentifierSize {
@@ -527,6 +682,18 @@ pub enum TargetVertexFormat {
     P4h_N4b_G4b_B4b_T2h_I4b,
     P4h_N4b_G4b_B4b_T2h_I4b_W4b,
 }
+impl TargetVertexFormat {
+    /// Bone influences one vertex can carry. Without W4b only the first joint of I4b is used
+    /// (the weight defaults to 255, see `w4b_default_iter`).
+    pub fn max_influences(&self) -> usize {
+        match self {
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h => 0,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b => 1,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b => 4,
+        }
+    }
+}
+
 impl FromStr for TargetVertexFormat {
     type Err = String;
 
@@ -607,4 +774,41 @@ mod tests_vertex {
             assert_eq!(&bytes2[i], &bytes[i]);
         }
     }
+
+    #[test]
+    fn quantize_weights_sum() {
+        let sum = |d: [u8; 4]| d.iter().map(|&w| w as u32).sum::<u32>();
+        // independent rounding gives 256
+        assert_eq!(quantize_weights([0.502, 0.498, 0.0, 0.0]), [128, 127, 0, 0]);
+        // independent rounding gives 254
+        let d = quantize_weights([1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0, 0.0]);
+        assert_eq!(sum(d), 255);
+        assert_eq!(d, [85, 85, 85, 0]);
+        let d = quantize_weights([0.4, 0.3, 0.2, 0.1]);
+        assert_eq!(sum(d), 255);
+        // not normalised input
+        assert_eq!(sum(quantize_weights([2.0, 1.0, 1.0, 0.0])), 255);
+        assert_eq!(quantize_weights([0.0; 4]), [0; 4]);
+    }
+
+    #[test]
+    fn repair_weights() {
+        let identifiers = vec![VertexIdentifier::i4b(), VertexIdentifier::w4b()].into_boxed_slice();
+        #[rustfmt::skip]
+        let buffer = vec![
+            0, 1, 0, 0, 128, 127, 0, 0,
+            0, 1, 2, 0, 85, 85, 85, 1,
+            3, 0, 0, 0, 0, 0, 0, 0,
+            0, 1, 2, 0, 86, 85, 85, 0,
+        ];
+        let mut vertex = VertexFormat2::new(identifiers, 4, 8, None, Bytes::from(buffer));
+        assert_eq!(vertex.check_weights(), vec![(1, 256), (2, 0), (3, 256)]);
+
+        assert_eq!(vertex.repair_weights(), 3);
+        assert!(vertex.check_weights().is_empty());
+        let w: Vec<W4b> = vertex.iter::<W4b, W4b>(0).unwrap().collect();
+        assert_eq!(w[0].data, [128, 127, 0, 0]);
+        assert_eq!(w[2].data, [255, 0, 0, 0]);
+        assert_eq!(w[3].data, [85, 85, 85, 0]);
+    }
 }
@@ -1,7 +1,7 @@
 use rdm4lib::RdModell;
 
 use rdm4lib::gltf_export;
-use rdm4lib::rdm_anim::RdAnim;
+use rdm4lib::rdm_anim::{JointMap, RdAnim};
 use rdm4lib::rdm_writer::RdWriter;
 
 use rdm4lib::rdm_anim_writer::RdAnimWriter;
@@ -16,13 +16,15 @@ use std::str;
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rdm4lib::{gltf_export::GltfExportFormat, vertex::TargetVertexFormat};
+    use rdm4lib::gltf_export::{GltfExportFormat, SceneNode};
+    use rdm4lib::vertex::TargetVertexFormat;
     use std::convert::TryFrom;
     use std::fs;
     use std::path::PathBuf;
 
-    #[cfg(target_os = "windows")]
-    use rdm4lib::rdm_material::RdMaterial;
+    use approx::assert_relative_eq;
+
+    use rdm4lib::rdm_material::{MaterialSlot, RdMaterial};
 
     #[test]
     #[cfg_attr(miri, ignore)]
@@ -62,12 +64,12 @@ mod tests {
 
         let output = if cfg!(target_os = "windows") {
             Command::new("..\\gltf_validator.exe")
-                .args(&["-ar", "gltf_out/out.gltf"])
+                .args(["-ar", "gltf_out/out.gltf"])
                 .output()
                 .expect("failed to execute process")
         } else {
             Command::new("../gltf_validator")
-                .args(&["-ar", "gltf_out/out.gltf"])
+                .args(["-ar", "gltf_out/out.gltf"])
                 .output()
                 .expect("failed to execute process")
         };
@@ -123,7 +125,6 @@ mod tests {
 
     #[test]
     #[cfg_attr(miri, ignore)]
-    #[cfg(target_os = "windows")]
     fn excavator_tycoons_lod1() {
         let mut rdm = RdModell::from("rdm/excavator_tycoons_lod1.rdm");
         rdm.mat = Some(RdMaterial::from(r"rdm/excavator_tycoons_diff_0.dds"));
@@ -145,11 +146,252 @@ mod tests {
             false,
             GltfExportFormat::GltfSeparate,
         );
+        assert!(Path::new("gltf_out1/excavator_tycoons_diff_0.PNG").is_file());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn excavator_tycoons_glb_texture() {
+        let mut rdm = RdModell::from("rdm/excavator_tycoons_lod1.rdm");
+        rdm.mat = Some(RdMaterial::from(r"rdm/excavator_tycoons_diff_0.dds"));
+
+        let dir = PathBuf::from("gltf_out_dds");
+        fs::create_dir_all(&dir).unwrap();
+        gltf_export::build(rdm, Some(dir.clone()), false, GltfExportFormat::Glb);
+
+        // the PNG is embedded in the glb buffer
+        let (document, _, images) = gltf::import(dir.join("out.glb")).unwrap();
+        let image = document.images().next().unwrap();
+        assert!(matches!(
+            image.source(),
+            gltf::image::Source::View {
+                mime_type: "image/png",
+                ..
+            }
+        ));
+        assert_eq!((images[0].width, images[0].height), (1024, 1024));
+        assert_eq!(images[0].format, gltf::image::Format::R8G8B8A8);
+    }
+
+    /// `emissiveStrength` of every material in a .glb.
+    fn emissive_strengths(glb: &Path) -> Vec<Option<f64>> {
+        let bytes = fs::read(glb).unwrap();
+        let glb = gltf::Glb::from_slice(&bytes).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&glb.json).unwrap();
+        json["materials"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| {
+                m["extensions"]["KHR_materials_emissive_strength"]["emissiveStrength"].as_f64()
+            })
+            .collect()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn glow_without_metal_texture() {
+        let mut rdm = RdModell::from("rdm/residence_tier_02_estate_02_lod2.rdm");
+        let glow = MaterialSlot {
+            name: Some("lamp".to_owned()),
+            emissive: Some([3.0, 1.5, 0.0]),
+            ..Default::default()
+        };
+        rdm.mat = Some(RdMaterial::from_slots(vec![
+            glow.clone(),
+            MaterialSlot {
+                emissive: Some([0.5, 0.25, 0.0]),
+                ..glow
+            },
+        ]));
+
+        let dir = PathBuf::from("gltf_out_glow");
+        fs::create_dir_all(&dir).unwrap();
+        gltf_export::build(rdm, Some(dir.clone()), false, GltfExportFormat::Glb);
+
+        let (document, _, _) = gltf::import(dir.join("out.glb")).unwrap();
+        let materials: Vec<gltf::Material> = document.materials().collect();
+        assert!(materials.iter().all(|m| m.emissive_texture().is_none()));
+        assert_eq!(materials[0].emissive_factor(), [1.0, 0.5, 0.0]);
+        assert_eq!(materials[1].emissive_factor(), [0.5, 0.25, 0.0]);
+        assert_eq!(materials[2].emissive_factor(), [0.0; 3]);
+        assert_eq!(
+            emissive_strengths(&dir.join("out.glb")),
+            vec![Some(3.0), None, None]
+        );
+        assert!(document
+            .extensions_used()
+            .any(|e| e == "KHR_materials_emissive_strength"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn residence_pbr_materials() {
+        let mut rdm = RdModell::from("rdm/residence_tier_02_estate_02_lod2.rdm");
+        let pbr = MaterialSlot {
+            name: Some("walls".to_owned()),
+            diffuse: Some("rdm/residence_tier02_04_diff_0.dds".into()),
+            normal: Some("rdm/residence_02_05_diff_0.dds".into()),
+            metallic: Some("rdm/residence_02_05_diff_0.dds".into()),
+            base_color: [0.5, 1.0, 1.0],
+            emissive: Some([2.0, 0.5, 0.0]),
+            alpha_blend: true,
+        };
+        rdm.mat = Some(RdMaterial::from_slots(vec![
+            pbr.clone(),
+            MaterialSlot {
+                name: Some("roof".to_owned()),
+                emissive: None,
+                ..pbr
+            },
+        ]));
+
+        let dir = PathBuf::from("gltf_out_pbr");
+        fs::create_dir_all(&dir).unwrap();
+        gltf_export::build(rdm, Some(dir.clone()), false, GltfExportFormat::Glb);
+
+        let (document, _, images) = gltf::import(dir.join("out.glb")).unwrap();
+        // base color, metallic-roughness, normal and glow mask are shared by both materials
+        assert_eq!(images.len(), 4);
+        let materials: Vec<gltf::Material> = document.materials().collect();
+        assert_eq!(materials.len(), 3);
+
+        let walls = &materials[0];
+        assert_eq!(walls.name(), Some("walls"));
+        assert_eq!(walls.alpha_mode(), gltf::material::AlphaMode::Blend);
+        let pbr = walls.pbr_metallic_roughness();
+        assert_eq!(pbr.base_color_factor(), [0.5, 1.0, 1.0, 1.0]);
+        assert!(pbr.base_color_texture().is_some());
+        let metal = pbr.metallic_roughness_texture().unwrap();
+        let normal = walls.normal_texture().unwrap();
+        // cEmissiveColor 2.0, 0.5, 0.0 is split into factor and KHR_materials_emissive_strength
+        assert_eq!(walls.emissive_factor(), [1.0, 0.25, 0.0]);
+        assert_eq!(
+            emissive_strengths(&dir.join("out.glb")),
+            vec![Some(2.0), None, None]
+        );
+        let glow = walls.emissive_texture().unwrap();
+
+        // metalness and roughness go to the blue and green channel
+        let metal_image = &images[metal.texture().source().index()];
+        assert_eq!(metal_image.format, gltf::image::Format::R8G8B8A8);
+        assert!(metal_image
+            .pixels
+            .chunks(4)
+            .all(|p| p[0] == 0 && p[3] == 255));
+        // normals are unit length after the reconstruction of z (0 for xy outside of the circle)
+        let normal_image = &images[normal.texture().source().index()];
+        assert!(normal_image.pixels.chunks(4).all(|p| {
+            let n = p[..3].iter().map(|&c| c as f32 / 127.5 - 1.0);
+            let len = n.map(|c| c * c).sum::<f32>();
+            p[2] == 128 || (0.95..1.05).contains(&len)
+        }));
+        let glow_image = &images[glow.texture().source().index()];
+        assert!(glow_image
+            .pixels
+            .chunks(4)
+            .all(|p| p[0] == p[1] && p[1] == p[2]));
+
+        let roof = &materials[1];
+        assert_eq!(roof.name(), Some("roof"));
+        assert_eq!(
+            roof.normal_texture().unwrap().texture().index(),
+            normal.texture().index()
+        );
+        assert!(roof.emissive_texture().is_none());
+        assert_eq!(roof.emissive_factor(), [0.0; 3]);
+
+        // the third slot has no cfg material
+        assert!(materials[2]
+            .pbr_metallic_roughness()
+            .base_color_texture()
+            .is_none());
+        assert_eq!(materials[2].alpha_mode(), gltf::material::AlphaMode::Opaque);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn scene_of_models() {
+        let excavator = || {
+            let mut rdm = RdModell::from("rdm/excavator_tycoons_lod1.rdm");
+            rdm.mat = Some(RdMaterial::from(r"rdm/excavator_tycoons_diff_0.dds"));
+            rdm
+        };
+        let mut root = SceneNode::new(Some("building".to_owned()));
+        let mut base = SceneNode::new(Some("base".to_owned()));
+        base.model = Some(RdModell::from("rdm/basalt_crusher_others_lod0.rdm"));
+        base.translation = [0.0, -0.25, 0.0];
+        let mut prop = SceneNode::new(Some("prop".to_owned()));
+        prop.translation = [2.0, 0.0, 1.0];
+        prop.scale = [0.5; 3];
+        let mut left = SceneNode::new(Some("left".to_owned()));
+        left.model = Some(excavator());
+        let mut right = SceneNode::new(Some("right".to_owned()));
+        right.model = Some(excavator());
+        right.rotation = [0.0, 0.70710677, 0.0, 0.70710677];
+        prop.children = vec![left, right];
+        root.children = vec![base, prop];
+
+        let dir = PathBuf::from("gltf_out_scene");
+        fs::create_dir_all(&dir).unwrap();
+        gltf_export::build_scene(root, Some(dir.clone()), false, GltfExportFormat::Glb).unwrap();
+
+        let (document, _, images) = gltf::import(dir.join("out.glb")).unwrap();
+        assert_eq!(document.meshes().count(), 3);
+        // the excavator texture is shared
+        assert_eq!(images.len(), 1);
+        assert_eq!(document.materials().count(), 3);
+
+        let scene = document.default_scene().unwrap();
+        let root = scene.nodes().next().unwrap();
+        assert_eq!(root.name(), Some("building"));
+        let children: Vec<gltf::Node> = root.children().collect();
+        assert_eq!(children[0].name(), Some("base"));
+        assert!(children[0].mesh().is_some());
+        let (translation, _, _) = children[0].transform().decomposed();
+        assert_eq!(translation, [0.0, -0.25, 0.0]);
+
+        let prop = &children[1];
+        assert!(prop.mesh().is_none());
+        let (_, _, scale) = prop.transform().decomposed();
+        assert_eq!(scale, [0.5; 3]);
+        let excavators: Vec<gltf::Node> = prop.children().collect();
+        assert_eq!(excavators.len(), 2);
+        let (_, rotation, _) = excavators[1].transform().decomposed();
+        assert_eq!(rotation, [0.0, 0.70710677, 0.0, 0.70710677]);
+        let mesh = excavators[1].mesh().unwrap();
+        assert_eq!(
+            mesh.primitives().next().unwrap().material().index(),
+            excavators[0]
+                .mesh()
+                .unwrap()
+                .primitives()
+                .next()
+                .unwrap()
+                .material()
+                .index()
+                .map(|i| i + 1)
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn scene_without_models() {
+        let mut root = SceneNode::new(Some("empty".to_owned()));
+        root.children
+            .push(SceneNode::new(Some("missing model".to_owned())));
+
+        let dir = PathBuf::from("gltf_out_empty_scene");
+        fs::create_dir_all(&dir).unwrap();
+        assert!(
+            gltf_export::build_scene(root, Some(dir.clone()), false, GltfExportFormat::Glb)
+                .is_err()
+        );
+        assert!(!dir.join("out.glb").exists());
     }
 
     #[test]
     #[cfg_attr(miri, ignore)]
-    #[cfg(target_os = "windows")]
     fn residence_tier02_estate02() {
         let mut rdm = RdModell::from("rdm/residence_tier_02_estate_02_lod2.rdm");
         rdm.mat = Some(RdMaterial::new(vec![
@@ -170,6 +412,13 @@ mod tests {
             false,
             GltfExportFormat::GltfSeparate,
         );
+        for png in [
+            "residence_tier02_04_diff_0.PNG",
+            "residence_02_05_diff_0.PNG",
+            "brick_wall_white_estate_01_diff_0.PNG",
+        ] {
+            assert!(Path::new("gltf_out2").join(png).is_file());
+        }
     }
 
     #[test]
@@ -222,6 +471,46 @@ mod tests {
         exp_rdm.write_rdm(Some(dir_dst), false);
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn rewrite_keeps_rdm_materials() {
+        let rdm = RdModell::from("rdm/residence_tier_02_estate_02_lod2.rdm");
+        let materials = rdm.rdm_materials();
+        assert_eq!(materials.len(), 3);
+        assert_eq!(materials[0].name, b"04");
+        assert!(materials[2].texture.ends_with(b"wall_elements_diff.tga"));
+
+        let dir_dst = PathBuf::from("rdm_out/rewrite_keeps_rdm_materials");
+        std::fs::create_dir_all(&dir_dst).unwrap();
+        RdWriter::from(rdm).write_rdm(Some(dir_dst.clone()), false);
+
+        let rewritten = RdModell::from(dir_dst.join("out.rdm").as_path());
+        assert_eq!(rewritten.rdm_materials(), materials);
+
+        // models that were not read from an rdm get placeholders
+        let skeleton = RdModell::skeleton_from_anim(
+            &RdAnim::from("rdm/basalt_crusher_others_work01.rdm"),
+            &JointMap::new(),
+        );
+        assert!(skeleton.rdm_materials().is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn optimize_keeps_rdm_materials() {
+        let mut rdm = RdModell::from("rdm/fishery_others_lod2.rdm");
+        let materials = rdm.rdm_materials();
+        assert_eq!(materials.len(), 2);
+        rdm.optimize_vertex_cache();
+
+        let dir_dst = PathBuf::from("rdm_out/optimize_keeps_rdm_materials");
+        std::fs::create_dir_all(&dir_dst).unwrap();
+        RdWriter::from(rdm).write_rdm(Some(dir_dst.clone()), false);
+
+        let optimized = RdModell::from(dir_dst.join("out.rdm").as_path());
+        assert_eq!(optimized.rdm_materials(), materials);
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn read_gltf_skin_round_trip() {
@@ -243,7 +532,7 @@ mod tests {
 
         let jj = rdm.joints.clone().unwrap();
         let mut anims =
-            gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, 6, 0.33333).unwrap();
+            gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, 6, 0.33333, true).unwrap();
 
         assert_eq!(anims.len(), 1);
         let anim = anims.pop().unwrap();
@@ -287,6 +576,24 @@ mod tests {
         exp_rdm.write_rdm(Some(dir_dst), false);
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_skin_weights() {
+        let rdm = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &gltf_reader::ImportedGltf::try_from(Path::new(
+                "rdm/gltf/stormtrooper_with_tangent.gltf",
+            ))
+            .unwrap(),
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b,
+            true,
+            false,
+            false,
+            None,
+        );
+        assert_eq!(rdm.vertex.to_string(), "P4h_N4b_G4b_B4b_T2h_I4b_W4b");
+        assert!(rdm.vertex.check_weights().is_empty());
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn read_gltf_anim() {
@@ -308,7 +615,7 @@ mod tests {
 
         let jj = &rdm.joints.unwrap();
         let mut anims =
-            gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, 6, 0.33333).unwrap();
+            gltf_reader::ImportedGltf::read_animation(&i_gltf, jj, 6, 0.33333, false).unwrap();
 
         assert_eq!(anims.len(), 1);
         let anim = anims.pop().unwrap();
@@ -367,6 +674,280 @@ mod tests {
         exp_rdm.write_rdm(Some(dir_dst), false);
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn basalt_crusher_multiple_anims() {
+        let mut rdm = RdModell::from("rdm/basalt_crusher_others_lod2.rdm");
+        rdm.add_skin();
+        rdm.add_anim(RdAnim::from("rdm/basalt_crusher_others_idle01.rdm"));
+        rdm.add_anim(RdAnim::from("rdm/basalt_crusher_others_work01.rdm"));
+
+        if !Path::new("gltf_out4").exists() {
+            fs::create_dir("gltf_out4").unwrap();
+        }
+        gltf_export::build(
+            rdm,
+            Some(Path::new("gltf_out4").into()),
+            false,
+            GltfExportFormat::GltfSeparate,
+        );
+
+        let gltf = gltf::Gltf::open("gltf_out4/out.gltf").unwrap();
+        let names: Vec<_> = gltf.animations().map(|a| a.name().unwrap()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "basalt_crusher_others_idle01",
+                "basalt_crusher_others_work01"
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn check_anim_skeleton() {
+        let mut rdm = RdModell::from("rdm/basalt_crusher_others_lod2.rdm");
+        rdm.add_skin();
+        let joints = rdm.joints.clone().unwrap();
+
+        let anim = RdAnim::from("rdm/basalt_crusher_others_work01.rdm");
+        let check = anim.check_skeleton(&joints);
+        assert!(check.is_compatible());
+        assert!(check.missing.is_empty());
+
+        let mut renamed = anim.clone();
+        renamed.anim_vec[0].name = renamed.anim_vec[0].name.to_uppercase();
+        let check = renamed.check_skeleton(&joints);
+        assert!(!check.is_compatible());
+        assert_eq!(check.mismatched.len(), 1);
+        assert!(check.extra.is_empty());
+
+        let foreign = RdAnim::from("rdm/excavator_tycoons_work02.rdm");
+        let check = foreign.check_skeleton(&joints);
+        assert!(!check.is_compatible());
+        assert_eq!(check.extra.len(), foreign.anim_vec.len());
+        assert_eq!(check.missing.len(), joints.len());
+
+        // unmatched tracks must not end up in the glTF
+        rdm.add_anim(renamed);
+        if !Path::new("gltf_out5").exists() {
+            fs::create_dir("gltf_out5").unwrap();
+        }
+        gltf_export::build(
+            rdm,
+            Some(Path::new("gltf_out5").into()),
+            false,
+            GltfExportFormat::GltfSeparate,
+        );
+        let (gltf, _, _) = gltf::import("gltf_out5/out.gltf").unwrap();
+        let animation = gltf.animations().next().unwrap();
+        assert_eq!(animation.channels().count(), 2 * (anim.anim_vec.len() - 1));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn anim_without_matching_tracks() {
+        let mut rdm = RdModell::from("rdm/basalt_crusher_others_lod2.rdm");
+        rdm.add_skin();
+        rdm.add_anim(RdAnim::from("rdm/excavator_tycoons_work02.rdm"));
+        rdm.add_anim(RdAnim::from("rdm/basalt_crusher_others_work01.rdm"));
+
+        if !Path::new("gltf_out7").exists() {
+            fs::create_dir("gltf_out7").unwrap();
+        }
+        gltf_export::build(
+            rdm,
+            Some(Path::new("gltf_out7").into()),
+            false,
+            GltfExportFormat::GltfSeparate,
+        );
+        let (gltf, _, _) = gltf::import("gltf_out7/out.gltf").unwrap();
+        let names: Vec<_> = gltf.animations().map(|a| a.name().unwrap()).collect();
+        assert_eq!(names, vec!["basalt_crusher_others_work01"]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn retarget_anim() {
+        let mut rdm = RdModell::from("rdm/basalt_crusher_others_lod2.rdm");
+        rdm.add_skin();
+        let joints = rdm.joints.clone().unwrap();
+        let anim = RdAnim::from("rdm/basalt_crusher_others_work01.rdm");
+
+        // same rig, identity map: key frames are unchanged
+        let mapping: JointMap = anim
+            .anim_vec
+            .iter()
+            .map(|c| (c.name.clone(), c.name.clone()))
+            .collect();
+        let retargeted = anim.retarget(&joints, &joints, &mapping).unwrap();
+        assert_eq!(retargeted.anim_vec.len(), joints.len());
+        assert_eq!(retargeted.time_max, anim.time_max);
+        for track in anim.anim_vec.iter() {
+            let new_track = retargeted
+                .anim_vec
+                .iter()
+                .find(|c| c.name == track.name)
+                .unwrap();
+            assert_eq!(new_track.len, track.len);
+            for (a, b) in track.frames.iter().zip(new_track.frames.iter()) {
+                assert_relative_eq!(a.time, b.time);
+                for i in 0..3 {
+                    assert_relative_eq!(a.translation[i], b.translation[i], epsilon = 1.0e-4);
+                }
+                // q and -q are the same rotation
+                let sign = if a.rotation[3] * b.rotation[3] < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                for i in 0..4 {
+                    assert_relative_eq!(a.rotation[i], sign * b.rotation[i], epsilon = 1.0e-4);
+                }
+            }
+        }
+
+        // unmapped joints hold their bind pose
+        let map_path = std::env::temp_dir().join("rdm4_retarget_joint_map.json");
+        let first = &anim.anim_vec[0].name;
+        fs::write(&map_path, format!("{{\"{}\": \"{}\"}}", first, first)).unwrap();
+        let mapping: JointMap = rdm4lib::load_json_map(&map_path).unwrap();
+        assert_eq!(mapping.len(), 1);
+        let retargeted = anim.retarget(&joints, &joints, &mapping).unwrap();
+        assert_eq!(retargeted.anim_vec.len(), joints.len());
+        for track in retargeted.anim_vec.iter().filter(|c| &c.name != first) {
+            assert_eq!(track.len, 2);
+            assert_relative_eq!(track.frames[1].time, anim.time_max as f32 / 1000.0);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn retarget_bad_joint_map() {
+        let mut rdm = RdModell::from("rdm/basalt_crusher_others_lod2.rdm");
+        rdm.add_skin();
+        let joints = rdm.joints.clone().unwrap();
+        let anim = RdAnim::from("rdm/basalt_crusher_others_work01.rdm");
+        let (a, b) = (&anim.anim_vec[0].name, &anim.anim_vec[1].name);
+
+        // two source joints for one target joint
+        let mapping = JointMap::from([(a.clone(), a.clone()), (b.clone(), a.clone())]);
+        let err = anim.retarget(&joints, &joints, &mapping).unwrap_err();
+        assert!(err.contains("both map to joint"), "{}", err);
+
+        // tracks whose joint is not in the source skeleton are skipped, the targets hold the bind pose
+        let mapping = JointMap::from([(a.clone(), a.clone()), (b.clone(), b.clone())]);
+        let retargeted = anim.retarget(&[], &joints, &mapping).unwrap();
+        assert_eq!(retargeted.anim_vec.len(), joints.len());
+        assert!(retargeted.anim_vec.iter().all(|c| c.len == 2));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn load_json_map_errors() {
+        let map_path = std::env::temp_dir().join("rdm4_invalid_material_map.json");
+        fs::write(&map_path, "{\"body\": \"zero\"}").unwrap();
+        assert!(rdm4lib::load_json_map::<u32, _>(&map_path).is_err());
+        assert!(rdm4lib::load_json_map::<String, _>(&map_path).is_ok());
+        assert!(rdm4lib::load_json_map::<u32, _>("does/not/exist.json").is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn anim_only_skeleton() {
+        let anim = RdAnim::from("rdm/basalt_crusher_others_work01.rdm");
+        let track_count = anim.anim_vec.len();
+        let mut rdm = RdModell::skeleton_from_anim(&anim, &JointMap::new());
+        assert!(!rdm.has_mesh());
+        assert_eq!(rdm.joints.as_ref().unwrap().len(), track_count);
+        rdm.add_anim(anim);
+
+        if !Path::new("gltf_out6").exists() {
+            fs::create_dir("gltf_out6").unwrap();
+        }
+        gltf_export::build(
+            rdm,
+            Some(Path::new("gltf_out6").into()),
+            false,
+            GltfExportFormat::Glb,
+        );
+
+        let (gltf, _, _) = gltf::import("gltf_out6/out.glb").unwrap();
+        assert_eq!(gltf.meshes().count(), 0);
+        assert_eq!(gltf.skins().count(), 0);
+        // joints + root node
+        assert_eq!(gltf.nodes().count(), track_count + 1);
+        let animation = gltf.animations().next().unwrap();
+        assert_eq!(animation.channels().count(), 2 * track_count);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn slice_anim() {
+        let anim = RdAnim::from("rdm/basalt_crusher_others_work01.rdm");
+        let sliced = anim.slice(0.5, 1.25);
+        assert_eq!(sliced.time_max, 750);
+        assert_eq!(sliced.anim_vec.len(), anim.anim_vec.len());
+        for collection in sliced.anim_vec.iter() {
+            assert_eq!(collection.len as usize, collection.frames.len());
+            assert_eq!(collection.frames.first().unwrap().time, 0.0);
+            assert_relative_eq!(collection.frames.last().unwrap().time, 0.75);
+            assert!(collection.frames.windows(2).all(|w| w[0].time < w[1].time));
+        }
+
+        let exp_rdm = RdAnimWriter::from(sliced);
+        let dir_dst = PathBuf::from("rdm_out/slice_anim");
+        std::fs::create_dir_all(&dir_dst).unwrap();
+        exp_rdm.write_anim_rdm(Some(dir_dst), false);
+
+        let reloaded = RdAnim::from("rdm_out/slice_anim/basalt_crusher_others_work01.rdm");
+        assert_eq!(reloaded.time_max, 750);
+    }
+
+    #[test]
+    fn axis_conversion() {
+        use nalgebra::{Matrix3, Vector3};
+        use rdm4lib::axis::{Axis, AxisConversion};
+
+        assert_eq!("-z".parse::<Axis>(), Ok(Axis::NegZ));
+        assert_eq!("+Y".parse::<Axis>(), Ok(Axis::Y));
+        assert!("W".parse::<Axis>().is_err());
+
+        let default = AxisConversion::default();
+        assert!(default.is_identity());
+        assert_eq!(default.to_rdm(), Matrix3::identity());
+
+        let z_up = AxisConversion {
+            up: Axis::Z,
+            forward: Axis::NegY,
+            left_handed: false,
+            scale: 1.0,
+        };
+        let m = z_up.to_rdm();
+        assert_relative_eq!(m.determinant(), 1.0);
+        assert_eq!(m * Vector3::z(), Vector3::y());
+        assert_eq!(z_up.to_gltf() * Vector3::y(), Vector3::z());
+
+        let left_handed = AxisConversion {
+            left_handed: true,
+            scale: 2.0,
+            ..AxisConversion::default()
+        };
+        assert_relative_eq!(left_handed.to_rdm().determinant(), -8.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be perpendicular")]
+    fn axis_conversion_parallel_axes() {
+        use rdm4lib::axis::{Axis, AxisConversion};
+        let parallel = AxisConversion {
+            up: Axis::Z,
+            forward: Axis::NegZ,
+            ..AxisConversion::default()
+        };
+        parallel.to_rdm();
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     #[ignore]
@@ -415,11 +996,9 @@ mod tests {
 
             let interpolation_value = (current_time - previous_time) / (next_time - previous_time);
 
-            let current_translation = previous_translation
-                + interpolation_value * (next_translation - previous_translation);
-            current_translation
+            previous_translation + interpolation_value * (next_translation - previous_translation)
         }
 
-        dbg!(interpolate(1.2f32, &&input_time, &output_values));
+        dbg!(interpolate(1.2f32, &input_time, &output_values));
     }
 }
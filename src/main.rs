@@ -1,23 +1,31 @@
 extern crate rdm4lib;
 
-use rdm4lib::gltf_reader::ResolveNodeName;
-use rdm4lib::{gltf_export::GltfExportFormat, vertex::TargetVertexFormat, RdModell};
+use rdm4lib::axis::{Axis, AxisConversion};
+use rdm4lib::gltf_reader::{MaterialMap, ResolveNodeName};
+use rdm4lib::{
+    gltf_export::GltfExportFormat,
+    vertex::{TargetVertexFormat, UniqueIdentifier},
+    RdModell,
+};
 
-use rdm4lib::gltf_export;
-use rdm4lib::rdm_anim::RdAnim;
+use rdm4lib::gltf_export::{self, SceneNode};
+use rdm4lib::rdm_anim::{AnimClip, JointMap, RdAnim};
 use rdm4lib::rdm_writer::RdWriter;
 
 use rdm4lib::rdm_anim_writer::RdAnimWriter;
 
-use rdm4lib::{gltf_reader, rdm_material::RdMaterial};
+use rdm4lib::gltf_reader;
+use rdm4lib::rdm_material::{MaterialSlot, RdMaterial};
 
 #[macro_use]
 extern crate log;
 
-use clap::Parser;
+use cfghelper::cfghelper;
+use clap::{Parser, Subcommand};
 use env_logger::Env;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 fn cli_in_is_file(v: &OsStr) -> Result<(), String> {
     let p = PathBuf::from(v);
@@ -28,15 +36,38 @@ fn cli_in_is_file(v: &OsStr) -> Result<(), String> {
     }
 }
 
+fn cli_in_is_file_or_dir(v: &OsStr) -> Result<(), String> {
+    let p = PathBuf::from(v);
+    if p.is_file() || p.is_dir() {
+        Ok(())
+    } else {
+        Err(format!("No such file or directory {}", v.to_string_lossy()))
+    }
+}
+
+fn cli_lod_ratio(v: &str) -> Result<(), String> {
+    match v.parse::<f32>() {
+        Ok(ratio) if ratio > 0.0 && ratio < 1.0 => Ok(()),
+        _ => Err(format!(
+            "LOD ratio must be a number between 0 and 1, got {}",
+            v
+        )),
+    }
+}
+
 static HEADER_GLTF2RDM: &str = "GLTF TO RDM OPTIONS";
 static HEADER_RDM2GLTF: &str = "RDM TO GLTF OPTIONS";
 
 #[derive(Parser)]
 #[clap(
     version = env!("CARGO_PKG_VERSION"),
-    author = "lukts30 <https://github.com/lukts30/rdm4>"
+    author = "lukts30 <https://github.com/lukts30/rdm4>",
+    subcommand_negates_reqs = true
 )]
 struct Opts {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     // start of common options
     /// Input file
     #[clap(
@@ -44,10 +75,11 @@ struct Opts {
         short = 'i',
         long = "input",
         value_name("glTF or rdm FILE"),
+        required = true,
         validator_os(cli_in_is_file),
         parse(from_str)
     )]
-    input: PathBuf,
+    input: Option<PathBuf>,
 
     /// Output file or folder. If `--in-is-out-filename` is set this must be a folder!
     #[clap(display_order(1), short = 'o', long = "outdst", parse(from_str))]
@@ -78,6 +110,34 @@ struct Opts {
     #[clap(display_order(7), short, long, parse(from_occurrences))]
     verbose: i32,
 
+    /// glTF axis that points up: X, Y, Z, -X, -Y or -Z. rdm is always +Y up
+    #[clap(
+        display_order(8),
+        long,
+        value_name("AXIS"),
+        default_value = "Y",
+        allow_hyphen_values(true)
+    )]
+    up_axis: Axis,
+
+    /// glTF axis that points forward: X, Y, Z, -X, -Y or -Z. rdm is always +Z forward
+    #[clap(
+        display_order(9),
+        long,
+        value_name("AXIS"),
+        default_value = "Z",
+        allow_hyphen_values(true)
+    )]
+    forward_axis: Axis,
+
+    /// The glTF coordinates are left-handed
+    #[clap(display_order(10), long)]
+    left_handed: bool,
+
+    /// Size of one glTF unit in rdm units, e.g. 0.01 for a glTF in centimetres
+    #[clap(display_order(11), long, default_value = "1.0")]
+    scale: f32,
+
     // end of common options
     // start of HEADER_GLTF2RDM
     /// VertexFormat for output rdm: P4h_N4b_G4b_B4b_T2h | P4h_N4b_G4b_B4b_T2h_I4b | P4h_N4b_G4b_B4b_T2h_I4b_W4b
@@ -100,7 +160,7 @@ struct Opts {
     )]
     gltf_mesh_index: u32,
 
-    /// glTF to rdm: Do not apply node transforms. Recommended to use when working with animations.
+    /// glTF to rdm: Do not apply node transforms (mesh, skeleton and animations stay relative to the mesh node).
     #[clap(
         display_order(2),
         long = "no_transform",
@@ -109,7 +169,7 @@ struct Opts {
     )]
     no_transform: bool,
 
-    /// Mirrors the object on the x axis.
+    /// Mirrors the object on the x axis. Not needed for mirrored node transforms (negative scale), they are detected automatically.
     #[clap(display_order(3),long, conflicts_with_all(&["skeleton", "animation"]),help_heading = HEADER_GLTF2RDM)]
     negative_x_and_v0v2v1: bool,
 
@@ -127,6 +187,102 @@ struct Opts {
     )]
     gltf_node_joint_name_src: ResolveNodeName,
 
+    /// Drops joints that neither influence a vertex nor are the ancestor of such a joint (IK targets, control and end bones).
+    #[clap(
+        display_order(8),
+        long,
+        requires_all(&["gltf", "skeleton"]),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    prune_joints: bool,
+
+    /// Converts all meshes of the scene without skin: mesh and animated nodes become joints, vertices are bound to the joint of their node and node animations become rdm animations. Needs a VertexFormat with I4b.
+    #[clap(
+        display_order(9),
+        long,
+        requires_all(&["gltf", "skeleton"]),
+        conflicts_with("no-transform"),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    rigid_nodes: bool,
+
+    /// Converts all meshes of the scene into one static rdm: the world transform of every mesh node is baked into its vertices and the glTF materials become the material slots.
+    #[clap(
+        display_order(10),
+        long,
+        requires("gltf"),
+        conflicts_with_all(&["skeleton", "rigid-nodes", "no-transform"]),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    merge_scene: bool,
+
+    /// Converts every glTF mesh into its own rdm, named after the mesh (or its node). --outdst must be a folder.
+    #[clap(
+        display_order(11),
+        long,
+        requires("gltf"),
+        conflicts_with_all(&["merge-scene", "rigid-nodes", "overide-mesh-idx", "in-is-out-filename"]),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    all_meshes: bool,
+
+    /// Merges vertices that are identical after quantisation to the rdm VertexFormat and rewrites the triangle indices. Helps to stay below the 65535 vertex limit.
+    #[clap(display_order(14), long, requires("gltf"), help_heading = HEADER_GLTF2RDM)]
+    weld: bool,
+
+    /// Reorders triangles and vertices for the post-transform vertex cache (see the optimize subcommand).
+    #[clap(display_order(15), long, requires("gltf"), help_heading = HEADER_GLTF2RDM)]
+    optimize: bool,
+
+    /// Also writes simplified LODs with these triangle ratios next to the rdm, e.g. `--lods 0.5,0.25` writes name_lod1.rdm and name_lod2.rdm.
+    #[clap(
+        display_order(16),
+        long,
+        value_name("RATIOS"),
+        use_value_delimiter(true),
+        validator(cli_lod_ratio),
+        requires("gltf"),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    lods: Vec<f32>,
+
+    /// Also writes the base color, normal and metallic-roughness textures of the glTF materials next to the rdm as BC7 DDS with mipmaps (material_diff_0.dds, material_norm_0.dds, material_metal_0.dds).
+    #[clap(display_order(17), long, requires("gltf"), help_heading = HEADER_GLTF2RDM)]
+    textures: bool,
+
+    /// Assigns material slots by glTF material name: JSON object {"material name": index} or an Anno .cfg (material names of --cfg-model).
+    #[clap(
+        display_order(12),
+        long,
+        value_name("map.json or *.cfg"),
+        validator_os(cli_in_is_file),
+        parse(from_str),
+        requires("gltf"),
+        conflicts_with("overide-mesh-idx"),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    material_map: Option<PathBuf>,
+
+    /// Index of the cfg model (e.g. the LOD) whose materials are used by --material-map and --cfg.
+    #[clap(
+        display_order(13),
+        long,
+        default_value = "0",
+        help_heading = HEADER_GLTF2RDM
+    )]
+    cfg_model: usize,
+
+    /// Splits each glTF animation into one rdm animation per time range (seconds). Can be used multiple times, e.g. `--clip idle01=0.0..2.0 --clip work01=2.0..6.5`
+    #[clap(
+        display_order(6),
+        long,
+        value_name("NAME=START..END"),
+        multiple_occurrences(true),
+        requires("animation"),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    clip: Vec<AnimClip>,
+
     // end of HEADER_GLTF2RDM
     // start of HEADER_RDM2GLTF
     /// Export format to use for rdm to gltf: "glb", "gltf", "gltfmin"
@@ -139,34 +295,224 @@ struct Opts {
     )]
     gltf_export_format: GltfExportFormat,
 
-    /// External animation file for rdm
+    /// External animation file for rdm. Can be used multiple times or point to a folder of animation files. Every file becomes a named glTF animation.
     #[clap(
         short = 'm',
         long = "rdanimation",
         display_order(1),
         value_name("anim/*.rdm"),
-        validator_os(cli_in_is_file),
+        multiple_occurrences(true),
+        validator_os(cli_in_is_file_or_dir),
         parse(from_str),
         conflicts_with("gltf"),
         requires_all(&["skeleton", "animation"]),
         help_heading = HEADER_RDM2GLTF
     )]
-    rdanimation: Option<PathBuf>,
+    rdanimation: Vec<PathBuf>,
+
+    /// Input is a rdm animation. It is exported onto a bone-only skeleton built from its tracks (first key frame as rest pose). Without --bone-parents the hierarchy is flat.
+    #[clap(
+        long = "anim-only",
+        display_order(5),
+        conflicts_with_all(&["gltf", "skeleton", "rdanimation", "diffusetexture"]),
+        help_heading = HEADER_RDM2GLTF
+    )]
+    anim_only: bool,
+
+    /// Bone hierarchy for --anim-only: a skinned rdm model the animation was made for, or a JSON object mapping bone names to their parent bone, e.g. {"Bone_Spine": "Bone_Hip"}.
+    #[clap(
+        long = "bone-parents",
+        value_name("rdm or json FILE"),
+        display_order(5),
+        validator_os(cli_in_is_file),
+        parse(from_str),
+        requires("anim-only"),
+        help_heading = HEADER_RDM2GLTF
+    )]
+    bone_parents: Option<PathBuf>,
+
+    /// Skinned rdm model the rdanimation files were made for. Animations are retargeted onto the skeleton of --input using --joint-map.
+    #[clap(
+        long = "retarget",
+        value_name("rdm FILE"),
+        display_order(2),
+        validator_os(cli_in_is_file),
+        parse(from_str),
+        requires_all(&["rdanimation", "joint-map"]),
+        help_heading = HEADER_RDM2GLTF
+    )]
+    retarget: Option<PathBuf>,
 
-    /// DiffuseTextures.
+    /// JSON object mapping joint names of the --retarget skeleton to joint names of the --input skeleton, e.g. {"Bone_Hip": "pelvis"}.
+    #[clap(
+        long = "joint-map",
+        value_name("map.json"),
+        display_order(3),
+        validator_os(cli_in_is_file),
+        parse(from_str),
+        requires("retarget"),
+        help_heading = HEADER_RDM2GLTF
+    )]
+    joint_map: Option<PathBuf>,
+
+    /// Diffuse DDS textures, one per material slot. They are decoded to PNG base color textures.
     #[clap(
         short = 't',
         long = "diffusetexture",
         value_name("*.dds"),
-        display_order(2),
+        display_order(4),
         validator_os(cli_in_is_file),
         parse(from_str),
         help_heading = HEADER_RDM2GLTF
     )]
     diffusetexture: Option<Vec<PathBuf>>,
+
+    /// cfg of the model: builds the glTF materials (base color, normal and metal textures, diffuse and emissive color, alpha blending) from the materials of --cfg-model.
+    #[clap(
+        long = "cfg",
+        value_name("*.cfg"),
+        display_order(6),
+        validator_os(cli_in_is_file),
+        parse(from_str),
+        conflicts_with_all(&["gltf", "diffusetexture"]),
+        help_heading = HEADER_RDM2GLTF
+    )]
+    cfg: Option<PathBuf>,
+
+    /// Game data root the texture paths of --cfg are relative to (the folder that contains "data"). Default: derived from the cfg path.
+    #[clap(
+        long = "data-root",
+        value_name("DIR"),
+        display_order(7),
+        parse(from_str),
+        requires("cfg"),
+        help_heading = HEADER_RDM2GLTF
+    )]
+    data_root: Option<PathBuf>,
     // end of HEADER_RDM2GLTF
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Checks whether rdm animations fit the skeleton of a rdm model
+    CheckAnim {
+        /// rdm model with skin
+        #[clap(
+            short = 'i',
+            long = "input",
+            value_name("rdm FILE"),
+            validator_os(cli_in_is_file),
+            parse(from_str)
+        )]
+        input: PathBuf,
+
+        /// Animation file or folder of animation files. Can be used multiple times.
+        #[clap(
+            short = 'm',
+            long = "rdanimation",
+            value_name("anim/*.rdm"),
+            required = true,
+            multiple_occurrences(true),
+            validator_os(cli_in_is_file_or_dir),
+            parse(from_str)
+        )]
+        rdanimation: Vec<PathBuf>,
+    },
+    /// Checks that the W4b weights of every vertex sum to 255
+    CheckWeights {
+        /// rdm model
+        #[clap(
+            short = 'i',
+            long = "input",
+            value_name("rdm FILE"),
+            validator_os(cli_in_is_file),
+            parse(from_str)
+        )]
+        input: PathBuf,
+
+        /// Re-quantises broken weights and writes the repaired rdm to --outdst
+        #[clap(long)]
+        repair: bool,
+
+        /// Output file or folder for --repair
+        #[clap(short = 'o', long = "outdst", parse(from_str), requires("repair"))]
+        out: Option<PathBuf>,
+
+        /// Override existing files
+        #[clap(long, requires("repair"))]
+        force: bool,
+    },
+    /// Reorders triangles and vertices of a rdm for the post-transform vertex cache and reports the ACMR
+    Optimize {
+        /// rdm model
+        #[clap(
+            short = 'i',
+            long = "input",
+            value_name("rdm FILE"),
+            validator_os(cli_in_is_file),
+            parse(from_str)
+        )]
+        input: PathBuf,
+
+        /// Output file or folder
+        #[clap(short = 'o', long = "outdst", parse(from_str))]
+        out: Option<PathBuf>,
+
+        /// Override existing files
+        #[clap(long)]
+        force: bool,
+    },
+    /// Assembles a cfg with all its models and (nested) files into one glTF scene. Meshes are static: skins and the Animations of the cfg are not exported
+    Cfg2gltf {
+        /// cfg of the asset, e.g. data/graphics/buildings/.../name.cfg
+        #[clap(
+            short = 'i',
+            long = "input",
+            value_name("*.cfg"),
+            validator_os(cli_in_is_file),
+            parse(from_str)
+        )]
+        input: PathBuf,
+
+        /// Game data root the paths of the cfg are relative to (the folder that contains "data"). Default: derived from the cfg path.
+        #[clap(long = "data-root", value_name("DIR"), parse(from_str))]
+        data_root: Option<PathBuf>,
+
+        /// Only the first model of every cfg (usually LOD 0) instead of all models.
+        #[clap(long)]
+        first_model: bool,
+
+        /// Export format: "glb", "gltf", "gltfmin"
+        #[clap(short = 'e', long, default_value = "glb")]
+        gltf_export_format: GltfExportFormat,
+
+        /// Output file or folder
+        #[clap(short = 'o', long = "outdst", parse(from_str))]
+        out: Option<PathBuf>,
+
+        /// Override existing files
+        #[clap(long)]
+        force: bool,
+    },
+}
+
+impl Opts {
+    fn input(&self) -> &Path {
+        self.input
+            .as_deref()
+            .expect("--input is required without subcommand")
+    }
+
+    fn axes(&self) -> AxisConversion {
+        AxisConversion {
+            up: self.up_axis,
+            forward: self.forward_axis,
+            left_handed: self.left_handed,
+            scale: self.scale,
+        }
+    }
+}
+
 fn main() {
     let opts: Opts = Opts::parse();
     match opts.verbose {
@@ -175,13 +521,102 @@ fn main() {
         2 => env_logger::Builder::from_env(Env::default().default_filter_or("trace")).init(),
         _ => warn!("Don't be crazy"),
     }
-    entry_do_work(opts);
+    match opts.command {
+        Some(Command::CheckAnim { input, rdanimation }) => check_anim(&input, &rdanimation),
+        Some(Command::CheckWeights {
+            input,
+            repair,
+            out,
+            force,
+        }) => check_weights(&input, repair, out, force),
+        Some(Command::Optimize { input, out, force }) => optimize(&input, out, force),
+        Some(Command::Cfg2gltf {
+            input,
+            data_root,
+            first_model,
+            gltf_export_format,
+            out,
+            force,
+        }) => {
+            let root = data_root.unwrap_or_else(|| cfg_data_root(&input));
+            let scene = cfg_scene(&input, &root, first_model, &mut Vec::new());
+            if let Err(e) = gltf_export::build_scene(scene, out, !force, gltf_export_format) {
+                error!("{:?}: {}, no glTF written", input, e);
+                std::process::exit(1);
+            }
+        }
+        None => entry_do_work(opts),
+    }
+}
+
+fn check_anim(input: &Path, rdanimation: &[PathBuf]) {
+    let mut rdm = RdModell::from(input);
+    rdm.add_skin();
+    let joints = rdm.joints.as_ref().unwrap();
+
+    let mut compatible = true;
+    for anim_path in collect_rdanimation_files(rdanimation) {
+        let anim = RdAnim::from(anim_path.as_path());
+        let check = anim.check_skeleton(joints);
+        if check.is_compatible() {
+            info!("{:?}: OK: {}", anim_path, check);
+        } else {
+            error!("{:?}: NOT compatible: {}", anim_path, check);
+            compatible = false;
+        }
+    }
+    if !compatible {
+        std::process::exit(1);
+    }
+}
+
+fn optimize(input: &Path, out: Option<PathBuf>, force: bool) {
+    let mut rdm = RdModell::from(input);
+    if rdm
+        .vertex
+        .find_component_offsets(UniqueIdentifier::I4b)
+        .next()
+        .is_some()
+    {
+        rdm.add_skin();
+    }
+    let stats = rdm.optimize_vertex_cache();
+    info!("ACMR {:.3} -> {:.3}", stats.acmr_before, stats.acmr_after);
+    RdWriter::from(rdm).write_rdm(out, !force);
+}
+
+fn check_weights(input: &Path, repair: bool, out: Option<PathBuf>, force: bool) {
+    let mut rdm = RdModell::from(input);
+    let bad = rdm.vertex.check_weights();
+    for (vertex, sum) in bad.iter() {
+        warn!("vertex {}: weight sum {} != 255", vertex, sum);
+    }
+    if bad.is_empty() {
+        info!("{:?}: all weight sums are 255", input);
+        return;
+    }
+    error!("{:?}: {} vertices with broken weights", input, bad.len());
+    if !repair {
+        std::process::exit(1);
+    }
+
+    let repaired = rdm.vertex.repair_weights();
+    info!("repaired {} vertices", repaired);
+    if rdm
+        .vertex
+        .find_component_offsets(UniqueIdentifier::I4b)
+        .next()
+        .is_some()
+    {
+        rdm.add_skin();
+    }
+    RdWriter::from(rdm).write_rdm(out, !force);
 }
 
 fn entry_do_work(mut opts: Opts) {
     if let Some(ref mut out) = opts.out {
         if opts.in_is_out_filename {
-            let k = opts.input.file_stem().unwrap();
+            let k = opts.input.as_deref().unwrap().file_stem().unwrap();
             assert!(
                 out.is_dir(),
                 "in_is_out_filename: output must not be a file!"
@@ -198,7 +633,7 @@ fn entry_do_work(mut opts: Opts) {
 
     info!("overide_mesh_idx: {:?}", &opts.overide_mesh_idx);
     // Gets a value for config if supplied by user, or defaults to "default.conf"
-    info!("Using input file: {:?}", opts.input);
+    info!("Using input file: {:?}", opts.input());
     info!("Export skeleton: {:?}", opts.skeleton);
     info!("Export rdanimation: {:?}", opts.rdanimation);
     if opts.gltf.is_none() {
@@ -208,15 +643,271 @@ fn entry_do_work(mut opts: Opts) {
     }
 }
 
+fn load_cfg_model(path: &Path, cfg_model: usize) -> cfghelper::Model {
+    let cfg = cfghelper::parse_cfg(path)
+        .unwrap_or_else(|e| panic!("could not parse cfg {:?}: {}", path, e));
+    let mut models = cfg.models.models_vec;
+    let len = models.len();
+    if cfg_model >= len {
+        panic!("cfg {:?} has {} models, no model {}", path, len, cfg_model);
+    }
+    models.swap_remove(cfg_model)
+}
+
+/// JSON object of joint names, `expected` describes it in the error message.
+fn load_joint_map(path: &Path, expected: &str) -> JointMap {
+    rdm4lib::load_json_map(path)
+        .unwrap_or_else(|e| panic!("invalid joint map {:?}, expected {}: {}", path, expected, e))
+}
+
+fn load_material_map(path: &Path, cfg_model: usize) -> MaterialMap {
+    let is_cfg = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cfg"));
+    let map = if is_cfg {
+        let model = load_cfg_model(path, cfg_model);
+        info!("material map from cfg model {:?}", model.file_name);
+        model.material_map()
+    } else {
+        rdm4lib::load_json_map(path).unwrap_or_else(|e| {
+            panic!(
+                "invalid material map {:?}, expected {{\"material name\": index, ...}}: {}",
+                path, e
+            )
+        })
+    };
+    info!("material map: {:?}", map);
+    map
+}
+
+/// Material slots from the materials of a cfg model. Texture paths are relative to `data_root`,
+/// by default the parent of the "data" folder the cfg is in.
+fn load_cfg_materials(path: &Path, cfg_model: usize, data_root: Option<&Path>) -> RdMaterial {
+    let model = load_cfg_model(path, cfg_model);
+    let root = data_root
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| cfg_data_root(path));
+    info!("cfg model {:?}, data root {:?}", model.file_name, root);
+    cfg_model_materials(&model, &root)
+}
+
+/// The folder that contains the "data" folder the cfg is in, else the folder of the cfg.
+fn cfg_data_root(cfg: &Path) -> PathBuf {
+    cfg.ancestors()
+        .find(|a| {
+            a.file_name()
+                .is_some_and(|n| n.eq_ignore_ascii_case("data"))
+        })
+        .and_then(Path::parent)
+        .or_else(|| cfg.parent())
+        .unwrap_or_else(|| Path::new(""))
+        .to_path_buf()
+}
+
+/// A path of a cfg (`data\graphics\...`) in the game data at `root`.
+fn cfg_data_path(root: &Path, path: &str) -> PathBuf {
+    root.join(path.replace('\\', "/"))
+}
+
+fn cfg_model_materials(model: &cfghelper::Model, root: &Path) -> RdMaterial {
+    let slots = model
+        .materials
+        .materials_vec
+        .iter()
+        .map(|m| {
+            let texture = |enabled: Option<bool>, tex: Option<&String>| match (enabled, tex) {
+                (Some(false), _) | (_, None) => None,
+                (_, Some(tex)) => resolve_texture(root, tex),
+            };
+            MaterialSlot {
+                name: Some(m.Name.clone()).filter(|n| !n.is_empty()),
+                diffuse: texture(m.DIFFUSE_ENABLED, Some(&m.cModelDiffTex)),
+                normal: texture(m.NORMAL_ENABLED, m.cModelNormalTex.as_ref()),
+                metallic: texture(m.METALLIC_TEX_ENABLED, m.cModelMetallicTex.as_ref()),
+                base_color: [m.cDiffuseColor_r, m.cDiffuseColor_g, m.cDiffuseColor_b],
+                emissive: (m.GLOW_ENABLED == Some(true)).then_some([
+                    m.cEmissiveColor_r,
+                    m.cEmissiveColor_g,
+                    m.cEmissiveColor_b,
+                ]),
+                alpha_blend: m.ALPHA_BLEND_ENABLED == Some(true),
+            }
+        })
+        .collect();
+    RdMaterial::from_slots(slots)
+}
+
+/// The DDS of a cfg texture path: cfgs reference the source file (e.g. `maps/x_diff.psd`), the
+/// game ships `maps/x_diff_0.dds`.
+fn resolve_texture(root: &Path, tex: &str) -> Option<PathBuf> {
+    if tex.is_empty() {
+        return None;
+    }
+    let file = cfg_data_path(root, tex);
+    let stem = file.file_stem()?.to_string_lossy().into_owned();
+    let candidates = [
+        file.with_file_name(format!("{}_0.dds", stem)),
+        file.with_extension("dds"),
+        file.clone(),
+    ];
+    let found = candidates.into_iter().find(|c| {
+        c.is_file()
+            && c.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("dds"))
+    });
+    if found.is_none() {
+        warn!("texture {:?} not found (no {}_0.dds), skipped", file, stem);
+    }
+    found
+}
+
+/// Scene of a cfg: one node per model and per file, placed by their ORIENTATION_TRANSFORM.
+/// Nested cfgs in `Files` are assembled recursively, `stack` holds the cfgs being assembled.
+fn cfg_scene(path: &Path, root: &Path, first_model: bool, stack: &mut Vec<PathBuf>) -> SceneNode {
+    let cfg = cfghelper::parse_cfg(path)
+        .unwrap_or_else(|e| panic!("could not parse cfg {:?}: {}", path, e));
+    let stem = |p: &Path| p.file_stem().map(|s| s.to_string_lossy().into_owned());
+    let mut node = SceneNode::new(stem(path));
+    stack.push(path.to_path_buf());
+
+    let model_count = if first_model { 1 } else { usize::MAX };
+    for model in cfg.models.models_vec.iter().take(model_count) {
+        let file = cfg_data_path(root, &model.file_name);
+        if !file.is_file() {
+            warn!("{:?}: model {:?} not found, skipped", path, file);
+            continue;
+        }
+        info!("{:?}: model {:?}", path, file);
+        let mut rdm = RdModell::from(file.as_path());
+        rdm.mat = Some(cfg_model_materials(model, root));
+        let mut model_node = SceneNode::new(stem(&file));
+        model_node.model = Some(rdm);
+        node.children
+            .push(with_transformer(model_node, model.transformer.as_ref()));
+    }
+
+    for file in cfg.files.iter().flat_map(|f| f.files_vec.iter()) {
+        let file_path = cfg_data_path(root, &file.file_name);
+        let is_cfg = file_path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("cfg"));
+        if !is_cfg {
+            warn!("{:?}: file {:?} is not a cfg, skipped", path, file_path);
+        } else if !file_path.is_file() {
+            warn!("{:?}: file {:?} not found, skipped", path, file_path);
+        } else if stack.contains(&file_path) {
+            warn!("{:?}: {:?} includes itself, skipped", path, file_path);
+        } else {
+            let mut child = cfg_scene(&file_path, root, first_model, stack);
+            if !file.name.is_empty() {
+                child.name = Some(file.name.clone());
+            }
+            node.children
+                .push(with_transformer(child, file.transformer.as_ref()));
+        }
+    }
+    stack.pop();
+    node
+}
+
+/// Places `node` by the ORIENTATION_TRANSFORMs of a Transformer chain. The first one is the
+/// outermost, further ones become nested parent nodes.
+fn with_transformer(
+    mut node: SceneNode,
+    transformer: Option<&cfghelper::Transformer>,
+) -> SceneNode {
+    let orientations: Vec<&cfghelper::Transform> = transformer
+        .map(|t| t.orientations().collect())
+        .unwrap_or_default();
+    for (i, t) in orientations.iter().rev().enumerate() {
+        if i > 0 {
+            let mut parent = SceneNode::new(None);
+            parent.children.push(node);
+            node = parent;
+        }
+        node.translation = t.position();
+        node.rotation = t.rotation();
+        node.scale = [t.scale(); 3];
+    }
+    node
+}
+
+fn collect_rdanimation_files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for p in paths {
+        if p.is_dir() {
+            let mut dir_files: Vec<PathBuf> = std::fs::read_dir(p)
+                .expect("I/O error")
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|e| {
+                    e.is_file()
+                        && e.extension()
+                            .is_some_and(|ext| ext.eq_ignore_ascii_case("rdm"))
+                })
+                .collect();
+            dir_files.sort();
+            files.append(&mut dir_files);
+        } else {
+            files.push(p.clone());
+        }
+    }
+    files
+}
+
 fn convert_rdm_to_gltf(opts: Opts) {
-    let mut rdm = RdModell::from(opts.input.as_path());
-    if opts.skeleton && opts.rdanimation.is_none() {
+    let axes = opts.axes();
+    if opts.anim_only {
+        let anim = RdAnim::from(opts.input());
+        let parents = match &opts.bone_parents {
+            Some(path)
+                if path
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("json")) =>
+            {
+                load_joint_map(path, "{\"bone\": \"parent bone\", ...}")
+            }
+            Some(path) => {
+                let mut model = RdModell::from(path.as_path());
+                model.add_skin();
+                model.joint_parents()
+            }
+            None => HashMap::new(),
+        };
+        let mut rdm = RdModell::skeleton_from_anim(&anim, &parents);
+        rdm.add_anim(anim);
+        info!("running gltf_export ...");
+        gltf_export::build_with_axes(rdm, opts.out, !opts.force, opts.gltf_export_format, &axes);
+        return;
+    }
+
+    let mut rdm = RdModell::from(opts.input());
+    if opts.skeleton && opts.rdanimation.is_empty() {
         rdm.add_skin();
         info!("Skin added !");
-    } else if opts.skeleton && opts.rdanimation.is_some() {
+    } else if opts.skeleton {
         rdm.add_skin();
-        let anim = RdAnim::from(opts.rdanimation.unwrap().as_path());
-        rdm.add_anim(anim);
+        let retarget = opts.retarget.as_ref().map(|source| {
+            let mut source_rdm = RdModell::from(source.as_path());
+            source_rdm.add_skin();
+            let mapping = load_joint_map(
+                opts.joint_map.as_ref().unwrap(),
+                "{\"source joint\": \"target joint\", ...}",
+            );
+            (source_rdm.joints.unwrap(), mapping)
+        });
+        for anim_path in collect_rdanimation_files(&opts.rdanimation) {
+            info!("Adding animation {:?}", anim_path);
+            let anim = RdAnim::from(anim_path.as_path());
+            match &retarget {
+                Some((source_joints, mapping)) => rdm.add_anim(
+                    anim.retarget(source_joints, rdm.joints.as_ref().unwrap(), mapping)
+                        .unwrap_or_else(|e| {
+                            panic!("{:?}: {}", opts.joint_map.as_ref().unwrap(), e)
+                        }),
+                ),
+                None => rdm.add_anim(anim),
+            }
+        }
         info!("Skin and anim added !");
     } else {
         warn!("No skin. No anim !");
@@ -224,47 +915,165 @@ fn convert_rdm_to_gltf(opts: Opts) {
 
     if let Some(diffusetexture) = opts.diffusetexture {
         rdm.mat = Some(RdMaterial::new(diffusetexture));
+    } else if let Some(cfg) = opts.cfg.as_ref() {
+        rdm.mat = Some(load_cfg_materials(
+            cfg,
+            opts.cfg_model,
+            opts.data_root.as_deref(),
+        ));
     }
     info!("running gltf_export ...");
 
-    gltf_export::build(rdm, opts.out, !opts.force, opts.gltf_export_format);
+    gltf_export::build_with_axes(rdm, opts.out, !opts.force, opts.gltf_export_format, &axes);
 }
 
 fn convert_gltf_to_rdm(opts: Opts) {
-    let f_path = opts.input.as_path();
-    let i_gltf = gltf_reader::ImportedGltf::try_import(
-        f_path,
+    let f_path = opts.input().to_owned();
+    let axes = opts.axes();
+    let mut i_gltf = gltf_reader::ImportedGltf::try_import(
+        &f_path,
         opts.gltf_mesh_index,
         opts.gltf_node_joint_name_src,
     )
     .unwrap();
+    i_gltf.prune_joints = opts.prune_joints;
+    i_gltf.rigid_nodes = opts.rigid_nodes;
+    i_gltf.merge_scene = opts.merge_scene;
+    i_gltf.weld = opts.weld;
+    i_gltf.axes = axes;
+    i_gltf.material_map = opts
+        .material_map
+        .as_ref()
+        .map(|path| load_material_map(path, opts.cfg_model));
 
-    let rdm = gltf_reader::ImportedGltf::gltf_to_rdm(
-        &i_gltf,
-        opts.gltf.unwrap(),
+    if !opts.all_meshes {
+        convert_gltf_mesh(&opts, &i_gltf, opts.out.clone());
+        if opts.textures {
+            write_textures(i_gltf.material_textures(), opts.out.as_deref(), opts.force);
+        }
+        return;
+    }
+
+    let dir = opts.out.clone().unwrap_or_else(|| PathBuf::from("rdm_out"));
+    std::fs::create_dir_all(&dir).expect("--all-meshes: output must be a folder!");
+    let meshes = i_gltf.instantiated_meshes();
+    // once for all meshes, they can share materials
+    if opts.textures {
+        write_textures(i_gltf.material_textures_of(&meshes), Some(&dir), opts.force);
+    }
+    let mut used_names = HashSet::new();
+    for mesh_idx in meshes {
+        i_gltf.change_mesh_index(mesh_idx);
+        let mut name = i_gltf.mesh_name();
+        if !used_names.insert(name.clone()) {
+            name = format!("{}_{}", name, mesh_idx);
+            used_names.insert(name.clone());
+        }
+        info!("mesh #{} -> {}.rdm", mesh_idx, name);
+        convert_gltf_mesh(&opts, &i_gltf, Some(dir.join(name).with_extension("rdm")));
+    }
+}
+
+fn convert_gltf_mesh(opts: &Opts, i_gltf: &gltf_reader::ImportedGltf, out: Option<PathBuf>) {
+    let mut rdm = gltf_reader::ImportedGltf::gltf_to_rdm(
+        i_gltf,
+        opts.gltf.clone().unwrap(),
         opts.skeleton,
         opts.negative_x_and_v0v2v1,
         opts.no_transform,
-        opts.overide_mesh_idx,
+        opts.overide_mesh_idx.clone(),
     );
+    if opts.optimize {
+        let stats = rdm.optimize_vertex_cache();
+        info!("ACMR {:.3} -> {:.3}", stats.acmr_before, stats.acmr_after);
+    }
+    let lods: Vec<RdModell> = opts
+        .lods
+        .iter()
+        .map(|&ratio| {
+            let mut lod = rdm.simplified(ratio);
+            if opts.optimize {
+                lod.optimize_vertex_cache();
+            }
+            lod
+        })
+        .collect();
 
     if opts.skeleton && opts.animation {
         let jj = rdm.joints.as_ref().unwrap();
 
-        match gltf_reader::ImportedGltf::read_animation(&i_gltf, jj, 6, 0.33333) {
+        match gltf_reader::ImportedGltf::read_animation(i_gltf, jj, 6, 0.33333, opts.no_transform) {
             Some(mut anims) => {
+                if !opts.clip.is_empty() {
+                    let anim_count = anims.len();
+                    anims = anims
+                        .iter()
+                        .flat_map(|anim| {
+                            opts.clip.iter().map(move |clip| {
+                                let mut sliced = anim.slice(clip.start, clip.end);
+                                sliced.name = if anim_count == 1 {
+                                    clip.name.clone()
+                                } else {
+                                    format!("{}_{}", anim.name, clip.name)
+                                };
+                                sliced
+                            })
+                        })
+                        .collect();
+                }
                 for anim in anims.drain(..) {
                     let exp_rdm = RdAnimWriter::from(anim);
-                    exp_rdm.write_anim_rdm(opts.out.clone(), !opts.force);
+                    exp_rdm.write_anim_rdm(out.clone(), !opts.force);
                 }
             }
             None => error!("Could not read animation. Does the glTF contain any animations ?"),
         }
     }
 
+    for (i, lod) in lods.into_iter().enumerate() {
+        let lod_out = lod_path(out.as_deref(), i + 1);
+        info!("LOD {} -> {}", i + 1, lod_out.display());
+        RdWriter::from(lod).write_rdm(Some(lod_out), !opts.force);
+    }
+
     let exp_rdm = RdWriter::from(rdm);
-    exp_rdm.write_rdm(opts.out, !opts.force);
-    if opts.skeleton && !opts.no_transform {
-        error!("glTF skeleton is set, but no_transform is not! Animation & Mesh might be severely deformed! Use --no_transform and apply rotation & translation in the cfg file.");
+    exp_rdm.write_rdm(out, !opts.force);
+}
+
+/// Writes the material textures as DDS into the folder of the rdm written to `out`. Existing
+/// files are skipped unless `force` is set.
+fn write_textures(textures: Vec<gltf_reader::MaterialTexture>, out: Option<&Path>, force: bool) {
+    let dir = match out {
+        Some(out) if out.is_dir() => out.to_path_buf(),
+        Some(out) => out.parent().map(Path::to_path_buf).unwrap_or_default(),
+        None => {
+            let f = PathBuf::from("rdm_out");
+            let _ = std::fs::create_dir(&f);
+            f
+        }
+    };
+    for texture in textures {
+        let path = dir.join(&texture.file_name);
+        if path.exists() && !force {
+            warn!("{} exists, skipped (use --force)", path.display());
+            continue;
+        }
+        info!("texture -> {}", path.display());
+        std::fs::write(&path, texture.to_dds()).expect("I/O error");
+    }
+}
+
+/// Path of LOD `n` next to the rdm written to `out`: a trailing "_lod0" of the name is replaced.
+fn lod_path(out: Option<&Path>, n: usize) -> PathBuf {
+    let mut file = out.map(Path::to_path_buf).unwrap_or_else(|| {
+        let f = PathBuf::from("rdm_out");
+        let _ = std::fs::create_dir(&f);
+        f
+    });
+    if file.is_dir() {
+        file.push("out.rdm");
     }
+    let stem = file.file_stem().unwrap().to_string_lossy().into_owned();
+    let base = stem.strip_suffix("_lod0").unwrap_or(&stem);
+    file.with_file_name(format!("{}_lod{}.rdm", base, n))
 }